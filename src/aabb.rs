@@ -50,6 +50,11 @@ impl AABB {
         return true;
     }
 
+    // False for boxes reaching infinity, like an infinite plane's.
+    pub fn is_bounded(&self) -> bool {
+        [self.x, self.y, self.z].iter().all(|i: &Interval| -> bool { i.min.is_finite() && i.max.is_finite() })
+    }
+
     pub fn pad(&self) -> AABB {
         let delta: f64 = 0.0001;
         AABB {
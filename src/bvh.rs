@@ -2,24 +2,41 @@ use std::{sync::Arc, cmp::Ordering};
 
 use rand::Rng;
 
-use crate::{aabb::*, hittable::Hittable, hittable_list::HittableList, interval::Interval};
+use crate::{aabb::*, hittable::Hittable, hittable_list::HittableList, interval::Interval, light::Light};
 
 #[derive(Clone, Default)]
 pub struct BVHNode {
     pub left: Option<Arc<dyn Hittable + Sync>>,
     pub right: Option<Arc<dyn Hittable + Sync>>,
-    pub bbox: AABB
+    pub bbox: AABB,
+    // Objects with infinite boxes, like infinite planes, on the root only.  In the tree they'd make every box
+    // above them infinite, so they're tested after it.
+    pub unbounded: HittableList,
+    // Lights of the list the tree was built from, on the root only.
+    pub lights: Vec<Arc<dyn Light + Sync>>
 }
 
 impl BVHNode {
 
     pub fn new_list(hlist: &HittableList) -> BVHNode {
-        BVHNode::new(&hlist.objects, 0, hlist.objects.len())
+        let mut bounded: Vec<Arc<dyn Hittable + Sync>> = Vec::new();
+        let mut unbounded: HittableList = HittableList::default();
+        for object in hlist.objects.iter() {
+            if object.bounding_box().is_bounded() {
+                bounded.push(object.clone());
+            } else {
+                unbounded.add_obj(object.clone());
+            }
+        }
+        let mut node: BVHNode = if bounded.is_empty() { BVHNode::default() } else { BVHNode::new(&bounded, 0, bounded.len()) };
+        node.unbounded = unbounded;
+        node.lights = hlist.lights.clone();
+        node
     }
 
-    pub fn new(src_ojects: &Vec<Arc<dyn Hittable + Sync>>, start:usize, end:usize) -> BVHNode {
+    pub fn new(src_ojects: &[Arc<dyn Hittable + Sync>], start:usize, end:usize) -> BVHNode {
 
-        let mut objects:Vec<Arc<dyn Hittable + Sync>> = src_ojects.clone();
+        let mut objects:Vec<Arc<dyn Hittable + Sync>> = src_ojects.to_vec();
 
         let object_span: usize = end - start;
         assert!(object_span != 0);        
@@ -84,19 +101,88 @@ fn box_z_compare(a: &AABB, b: &AABB) -> Ordering {
 
 impl Hittable for BVHNode {
     fn hit(&self, r:&crate::ray::Ray, ray_t: crate::interval::Interval, rec: &mut crate::hittable::HitRecord) -> bool {
-        if !self.bbox.hit(r, ray_t) {
-            return false;
+        let mut hit_tree: bool = false;
+        if let (Some(left), Some(right)) = (&self.left, &self.right) {
+            if self.bbox.hit(r, ray_t) {
+                let hit_left: bool = left.hit(r, ray_t, rec);
+
+                let t_max: f64 = if hit_left {rec.t} else {ray_t.max};
+                let hit_right: bool = right.hit(r, Interval { min: ray_t.min, max: t_max }, rec);
+                hit_tree = hit_left || hit_right;
+            }
         }
 
-        let hit_left: bool = self.left.as_ref().unwrap().hit(r, ray_t, rec);
-
-        let t_max: f64 = if hit_left {rec.t} else {ray_t.max};
-        let hit_right: bool = self.right.as_ref().unwrap().hit(r, Interval { min: ray_t.min, max: t_max }, rec);
+        if self.unbounded.objects.is_empty() {
+            return hit_tree;
+        }
+        let t_max: f64 = if hit_tree {rec.t} else {ray_t.max};
+        let hit_unbounded: bool = self.unbounded.hit(r, Interval { min: ray_t.min, max: t_max }, rec);
+        return hit_tree || hit_unbounded;
+    }
 
-        return hit_left || hit_right;
+    fn collect_lights(&self) -> Vec<Arc<dyn Light + Sync>> {
+        let mut lights: Vec<Arc<dyn Light + Sync>> = self.lights.clone();
+        if let Some(left) = &self.left {
+            lights.extend(left.collect_lights());
+        }
+        // Single object leaves hold it on both sides.
+        if let (Some(left), Some(right)) = (&self.left, &self.right) {
+            if !Arc::ptr_eq(left, right) {
+                lights.extend(right.collect_lights());
+            }
+        }
+        lights.extend(self.unbounded.collect_lights());
+        lights
     }
 
     fn bounding_box(&self) -> AABB {
-        self.bbox
+        match (self.left.is_some(), self.unbounded.objects.is_empty()) {
+            (_, true) => self.bbox,
+            (false, false) => self.unbounded.bbox,
+            (true, false) => self.bbox + self.unbounded.bbox
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::HitRecord;
+    use crate::material::{Lambertian, Material};
+    use crate::quad::Plane;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use crate::vec3::{Color, Point3, Vec3};
+
+    fn closest_t(world: &dyn Hittable, r: &Ray) -> Option<f64> {
+        let mut rec: HitRecord = HitRecord::default();
+        if world.hit(r, Interval { min: 0.001, max: f64::INFINITY }, &mut rec) { Some(rec.t) } else { None }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn unbounded_objects_stay_out_of_the_tree() {
+        let mat: Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut list: HittableList = HittableList::default();
+        for i in 0..4 {
+            list.add_obj(Arc::new(Sphere::new_static(Point3::new(i as f64 * 3.0, 1.0, 0.0), 1.0, &mat)));
+        }
+        list.add_obj(Arc::new(Plane::new(Point3::zero(), Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0), &mat)));
+
+        let bvh: BVHNode = BVHNode::new_list(&list);
+        assert_eq!(bvh.unbounded.objects.len(), 1);
+        assert!(bvh.bbox.is_bounded());
+
+        // Down onto a sphere, the sphere is closer than the plane below it, and between spheres the plane is hit.
+        let down = |x: f64| -> Ray { Ray::new(Point3::new(x, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0), 0.0) };
+        assert!((closest_t(&bvh, &down(3.0)).unwrap() - 3.0).abs() < 1e-9);
+        assert!((closest_t(&bvh, &down(4.5)).unwrap() - 5.0).abs() < 1e-9);
+        assert!(closest_t(&bvh, &Ray::new(Point3::new(0.0, 5.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 0.0)).is_none());
+
+        // With only unbounded objects there is no tree at all.
+        let mut planes: HittableList = HittableList::default();
+        planes.add_obj(Arc::new(Plane::new(Point3::zero(), Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0), &mat)));
+        let bvh: BVHNode = BVHNode::new_list(&planes);
+        assert!(bvh.left.is_none() && bvh.right.is_none());
+        assert!((closest_t(&bvh, &down(100.0)).unwrap() - 5.0).abs() < 1e-9);
+    }
+}
@@ -1,13 +1,16 @@
-use std::{io::{stdout, Write}, sync::{Arc, Mutex}, time::Instant, fs};
+use std::{io::{stdout, Write}, sync::{Arc, Mutex}, time::Instant, fs, ops};
 use threadpool::ThreadPool;
 use rand::Rng;
 
-use crate::{tga, hittable::{HitRecord, Hittable}, interval::Interval, material::Material};
-use crate::common::{saturate, seconds_to_hhmmss, degrees_to_radians};
-use crate::ray::Ray;
-use crate::vec3::{Point3, Vec3, Color, normalize, cross, random_in_unit_disk};
+use crate::{tga::{self, TgaOptions}, hittable::{HitRecord, Hittable}, interval::Interval, material::Material, tonemap::ToneMapping, environment::Environment, light::Light, mat3::Mat3};
+use crate::color_space::{conversion_matrix, working_to_rec709, xyz_to_working_matrix, ColorSpace};
+use crate::spectrum::{SampledSpectrum, SampledWavelengths};
+use crate::common::{saturate, seconds_to_hhmmss, degrees_to_radians, power_heuristic};
+use crate::medium::{Medium, MediumSample};
+use crate::ray::{Ray, RayDifferentials};
+use crate::vec3::{Point3, Vec3, Color, normalize, cross, dot, random_in_unit_disk};
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Camera {
     pub origin: Point3,
     pub target: Point3,
@@ -21,6 +24,17 @@ pub struct Camera {
     pub focus_dist: f64,
     pub delta_time: f64,
     pub background: Color,
+    pub environment: Option<Arc<dyn Environment + Sync>>,
+    pub lights: Vec<Arc<dyn Light + Sync>>,
+    pub tone_mapping: ToneMapping,
+    // Trace sampled wavelengths instead of RGB, needed for dispersion.
+    pub spectral: bool,
+    // Output encoding.  With alpha, the alpha channel is the fraction of camera rays that hit geometry.
+    pub tga_options: TgaOptions,
+    // Colour space the image is encoded in, tone mapping happens in linear Rec.709 before converting to it.
+    pub output_space: ColorSpace,
+    xyz_to_rgb: Mat3,
+    rec709_to_output: Mat3,
     viewport_upper_left: Point3,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
@@ -45,6 +59,14 @@ impl Default for Camera {
             focus_dist: 10.0,
             delta_time: 0.0,
             background: Color::black(),
+            environment: None,
+            lights: Vec::new(),
+            tone_mapping: ToneMapping::default(),
+            spectral: false,
+            tga_options: TgaOptions::default(),
+            output_space: ColorSpace::default(),
+            xyz_to_rgb: Mat3::identity(),
+            rec709_to_output: Mat3::identity(),
             viewport_upper_left: Point3::zero(),
             pixel_delta_u: Point3::zero(),
             pixel_delta_v: Point3::zero(),
@@ -55,14 +77,129 @@ impl Default for Camera {
     }
 }
 
-fn write_color(buffer: &mut Vec<u8>, color:&Color, spp: i32, pos:usize) {
+// Track the medium, rays crossing the surface enter the object or leave it for vacuum.
+fn scattered_medium(r: &Ray, rec: &HitRecord, scattered: &Ray) -> Medium {
+    if dot(&scattered.direction, &rec.normal) >= 0.0 {
+        return r.medium;
+    }
+    let mat: &Arc<dyn Material + Sync> = rec.mat.as_ref().unwrap();
+    if rec.front_face { mat.medium() } else { Medium::default() }
+}
+
+// Follows the differentials of `r` through a mirror reflection or refraction at `rec`, treating the surface
+// as locally flat, after pbrt's SpecularReflect and SpecularTransmit.  Other bounces blur the footprint
+// beyond what textures need to filter, so they drop them.
+fn specular_differentials(r: &Ray, rec: &HitRecord, scattered: &Ray, pdf: f64) -> Option<RayDifferentials> {
+    let d: RayDifferentials = r.differentials?;
+    if pdf > 0.0 {
+        return None;
+    }
+
+    let n: Vec3 = rec.shading_normal;
+    let wo: Vec3 = -normalize(r.direction);
+    let wi: Vec3 = normalize(scattered.direction);
+    let dpdx: Vec3 = offset_on_tangent_plane(rec, &d.rx_origin, &d.rx_direction)?;
+    let dpdy: Vec3 = offset_on_tangent_plane(rec, &d.ry_origin, &d.ry_direction)?;
+    let dwodx: Vec3 = -normalize(d.rx_direction) - wo;
+    let dwody: Vec3 = -normalize(d.ry_direction) - wo;
+    let dcos_dx: f64 = dot(&dwodx, &n);
+    let dcos_dy: f64 = dot(&dwody, &n);
+
+    let cos_o: f64 = dot(&wo, &n);
+    let cos_i: f64 = dot(&wi, &n);
+    let (rx_direction, ry_direction) = if cos_i * cos_o > 0.0 {
+        (wi - dwodx + 2.0 * dcos_dx * n, wi - dwody + 2.0 * dcos_dy * n)
+    } else {
+        // Relative IOR from Snell's law on the actual directions, close to normal incidence it can't be told.
+        let sin_o: f64 = (1.0 - cos_o * cos_o).max(0.0).sqrt();
+        let sin_i: f64 = (1.0 - cos_i * cos_i).max(0.0).sqrt();
+        let eta: f64 = if sin_o > 1e-4 { sin_i / sin_o } else { 1.0 };
+        let dmu: f64 = eta - (eta * eta * cos_o) / cos_i.abs();
+        (wi - eta * dwodx + (dmu * dcos_dx) * n, wi - eta * dwody + (dmu * dcos_dy) * n)
+    };
+
+    Some(RayDifferentials {
+        rx_origin: rec.p + dpdx,
+        rx_direction: rx_direction,
+        ry_origin: rec.p + dpdy,
+        ry_direction: ry_direction
+    })
+}
+
+fn offset_on_tangent_plane(rec: &HitRecord, origin: &Point3, direction: &Vec3) -> Option<Vec3> {
+    let denom: f64 = dot(&rec.normal, direction);
+    if denom.abs() < 1e-12 {
+        return None;
+    }
+    let t: f64 = dot(&rec.normal, &(rec.p - *origin)) / denom;
+    Some(*origin + *direction * t - rec.p)
+}
+
+// Upper bound on scattering events inside a medium before the path is terminated.
+const MAX_MEDIUM_EVENTS: i32 = 1024;
+
+// Last segment of a random walk through the ray's medium, ending on a surface or escaping.
+struct MediumWalk {
+    ray: Ray,
+    // Throughput of the walk including transmittance along the last segment.
+    weight: Color,
+    hit: bool,
+    // True if the direction came from the phase function, which isn't weighted against light sampling.
+    scattered: bool
+}
 
-    let scale: f64 = 1.0 / (spp as f64);
-    let scaled_color: Color = (*color * scale).to_srgb();
+// Random walks `r` through its medium until it reaches a surface, filling `rec`.  None if the walk
+// was absorbed or ran out of events.
+fn walk_medium(world: &Arc<dyn Hittable + Sync>, r: &Ray, rec: &mut HitRecord) -> Option<MediumWalk> {
+    let mut walk: MediumWalk = MediumWalk { ray: *r, weight: Color::white(), hit: false, scattered: false };
+    for _ in 0..MAX_MEDIUM_EVENTS {
+        walk.hit = world.hit(&walk.ray, Interval { min: 0.001, max: f64::INFINITY }, rec);
+        let t_max: f64 = if walk.hit { rec.t } else { f64::INFINITY };
+        match walk.ray.medium.sample(&walk.ray, t_max) {
+            MediumSample::Pass { weight } => {
+                walk.weight *= weight;
+                return Some(walk);
+            }
+            MediumSample::Scatter { t, weight } => {
+                walk.weight *= weight;
+                if walk.weight.is_black() {
+                    return None;
+                }
+                let direction: Vec3 = walk.ray.medium.sample_phase(&normalize(walk.ray.direction));
+                let mut next: Ray = Ray::new(walk.ray.at(t), direction, walk.ray.time);
+                next.medium = walk.ray.medium;
+                next.wavelength = walk.ray.wavelength;
+                walk.ray = next;
+                walk.scattered = true;
+            }
+        }
+    }
+    None
+}
 
-    buffer[pos]   =  (255.0 * saturate(scaled_color.b())) as u8;
-    buffer[pos+1] =  (255.0 * saturate(scaled_color.g())) as u8;
-    buffer[pos+2] =  (255.0 * saturate(scaled_color.r())) as u8;
+// Pdf used to weight whatever `scattered` hits against light sampling, zero for specular bounces.
+// Light sampling itself always runs, materials without a non-specular lobe evaluate to black.
+fn scattered_pdf(r: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+    if scattered.specular {
+        return 0.0;
+    }
+    rec.mat.as_ref().unwrap().scattering_pdf(r, rec, &scattered.direction)
+}
+
+// Beer-Lambert transmittance with the absorption coefficient upsampled rather than the transmitted colour.
+fn spectral_transmittance(sw: &SampledWavelengths, r: &Ray, t: f64) -> SampledSpectrum {
+    let mut tr: SampledSpectrum = SampledSpectrum::new(1.0);
+    if r.medium.absorption.is_black() {
+        return tr;
+    }
+    let sigma: SampledSpectrum = sw.reflectance(&r.medium.absorption);
+    let distance: f64 = t * r.direction.length();
+    for i in 0..tr.values.len() {
+        if sigma.values[i] > 0.0 {
+            tr.values[i] = (-sigma.values[i] * distance).exp();
+        }
+    }
+    tr
 }
 
 impl Camera {
@@ -94,6 +231,24 @@ impl Camera {
         let defocus_radius: f64 = self.focus_dist * degrees_to_radians(self.defocus_angle/2.0).tan();
         self.defocus_disk_u = u * defocus_radius;
         self.defocus_disk_v = v * defocus_radius;
+
+        self.xyz_to_rgb = xyz_to_working_matrix();
+        self.rec709_to_output = conversion_matrix(ColorSpace::Rec709, self.output_space);
+    }
+
+    fn write_color(&self, buffer: &mut [u8], color:&Color, coverage: f64, pos:usize) {
+
+        let scale: f64 = 1.0 / (self.samples_per_pixel as f64);
+        let display_color: Color = self.tone_mapping.apply(&working_to_rec709(&(*color * scale)));
+        let c: Color = self.rec709_to_output * display_color;
+        let scaled_color: Color = self.output_space.encode(&Color::new(saturate(c.r()), saturate(c.g()), saturate(c.b())));
+
+        buffer[pos]   =  (255.0 * saturate(scaled_color.b())) as u8;
+        buffer[pos+1] =  (255.0 * saturate(scaled_color.g())) as u8;
+        buffer[pos+2] =  (255.0 * saturate(scaled_color.r())) as u8;
+        if self.tga_options.alpha {
+            buffer[pos+3] = (255.0 * saturate(coverage * scale)) as u8;
+        }
     }
 
     pub fn defocus_disk_sample(&self) -> Point3 {
@@ -113,44 +268,206 @@ impl Camera {
         let ray_origin: Vec3 = if self.defocus_angle <= 0.0 {self.origin} else {self.defocus_disk_sample()};
         let ray_direction: Vec3 = pixel_sample - ray_origin;
 
-        Ray::new(
+        let mut r: Ray = Ray::new(
             ray_origin,
             ray_direction,
             rand::thread_rng().gen_range(0.0..=self.delta_time)
-        )
+        );
+
+        // Offsets shrink with the sample count, many samples per pixel already average over it.
+        let scale: f64 = (1.0 / (self.samples_per_pixel.max(1) as f64).sqrt()).max(0.125);
+        r.differentials = Some(RayDifferentials {
+            rx_origin: ray_origin,
+            rx_direction: ray_direction + self.pixel_delta_u * scale,
+            ry_origin: ray_origin,
+            ry_direction: ray_direction + self.pixel_delta_v * scale
+        });
+        r
+    }
+
+    // The colour seen along `r`, and whether `r` itself hit geometry.
+    pub fn ray_color(&self, world: &Arc<dyn Hittable + Sync>, r: &Ray, depth: i32) -> (Color, bool) {
+        let mut hit: bool = false;
+        let color: Color = self.trace(world, r, depth, 0.0, &mut hit);
+        (color, hit)
     }
 
-    pub fn ray_color(&self, world: &Arc<dyn Hittable + Sync>, r: &Ray, depth: i32) -> Color {
+    // `scattering_pdf` is the pdf the previous bounce chose `r` with, zero for camera rays and specular bounces.
+    // `hit` is set to whether `r` reached a surface, for coverage.
+    fn trace(&self, world: &Arc<dyn Hittable + Sync>, r: &Ray, depth: i32, scattering_pdf: f64, hit: &mut bool) -> Color {
         if depth <= 0 {
             return Color::black();
         }
     
         let mut rec: HitRecord = HitRecord{..HitRecord::default()};
-    
-        if !world.hit(r, Interval { min: 0.001, max: f64::INFINITY }, &mut rec) {
-            return self.background;
+
+        let walk: MediumWalk = match walk_medium(world, r, &mut rec) {
+            None => return Color::black(),
+            Some(walk) => walk
+        };
+        *hit = walk.hit;
+        let r: &Ray = &walk.ray;
+        let transmittance: Color = walk.weight;
+        if !walk.hit {
+            return transmittance * self.miss_color(r, if walk.scattered { 0.0 } else { scattering_pdf });
         }
 
         let mut scattered: Ray = Ray{..Ray::default()};
         let mut attenuation: Color = Color::zero();
         let mat: &Arc<dyn Material + Sync> = rec.mat.as_ref().unwrap();
 
-        let light_color: Color = mat.emitted(&rec);
+        let light_color: Color = mat.emitted(r, &rec);
         if !mat.scatter(r, &rec, &mut attenuation, &mut scattered) {
-            return light_color;
+            return transmittance * light_color;
         }
-        let material_color: Color = attenuation * self.ray_color(world, &scattered, depth-1); 
 
-        return light_color + material_color;
+        scattered.medium = scattered_medium(r, &rec, &scattered);
+
+        let pdf: f64 = scattered_pdf(r, &rec, &scattered);
+        scattered.differentials = specular_differentials(r, &rec, &scattered, pdf);
+        let combine = |f: &Color, radiance: &Color| -> Color { *f * *radiance };
+        let direct_color: Color = self.sample_environment(world, r, &rec, &combine) + self.sample_lights(world, r, &rec, &combine);
+        let material_color: Color = attenuation * self.trace(world, &scattered, depth-1, pdf, &mut false);
+
+        return transmittance * (light_color + direct_color + material_color);
+    }
+
+    // Spectral counterpart of `trace`, colours are upsampled at the wavelengths in `sw`.
+    fn trace_spectral(&self, world: &Arc<dyn Hittable + Sync>, r: &Ray, depth: i32, scattering_pdf: f64, sw: &mut SampledWavelengths, hit: &mut bool) -> SampledSpectrum {
+        if depth <= 0 {
+            return SampledSpectrum::default();
+        }
+
+        let mut rec: HitRecord = HitRecord{..HitRecord::default()};
+
+        let walk: MediumWalk = match walk_medium(world, r, &mut rec) {
+            None => return SampledSpectrum::default(),
+            Some(walk) => walk
+        };
+        *hit = walk.hit;
+        let r: &Ray = &walk.ray;
+        // Scattering media are walked in RGB, purely absorbing ones keep the exact spectral falloff.
+        let t_max: f64 = if walk.hit { rec.t } else { f64::INFINITY };
+        let transmittance: SampledSpectrum = if r.medium.is_scattering() { sw.reflectance(&walk.weight) } else { spectral_transmittance(sw, r, t_max) };
+        if !walk.hit {
+            return transmittance * sw.illuminant(&self.miss_color(r, if walk.scattered { 0.0 } else { scattering_pdf }));
+        }
+
+        let mut scattered: Ray = Ray{..Ray::default()};
+        let mut attenuation: Color = Color::zero();
+        let mat: &Arc<dyn Material + Sync> = rec.mat.as_ref().unwrap();
+
+        let light_color: SampledSpectrum = sw.illuminant(&mat.emitted(r, &rec));
+        if !mat.scatter(r, &rec, &mut attenuation, &mut scattered) {
+            return transmittance * light_color;
+        }
+        if mat.dispersive() {
+            sw.terminate_secondary();
+        }
+        scattered.wavelength = r.wavelength;
+        scattered.medium = scattered_medium(r, &rec, &scattered);
+
+        let pdf: f64 = scattered_pdf(r, &rec, &scattered);
+        scattered.differentials = specular_differentials(r, &rec, &scattered, pdf);
+        let combine = |f: &Color, radiance: &Color| -> SampledSpectrum { sw.reflectance(f) * sw.illuminant(radiance) };
+        let direct_color: SampledSpectrum = self.sample_environment(world, r, &rec, &combine) + self.sample_lights(world, r, &rec, &combine);
+        let material_color: SampledSpectrum = sw.reflectance(&attenuation) * self.trace_spectral(world, &scattered, depth-1, pdf, sw, &mut false);
+
+        return transmittance * (light_color + direct_color + material_color);
+    }
+
+    fn miss_color(&self, r: &Ray, scattering_pdf: f64) -> Color {
+        match &self.environment {
+            None => self.background,
+            Some(env) => {
+                let color: Color = env.value(&r.direction);
+                if scattering_pdf <= 0.0 {
+                    return color;
+                }
+                color * power_heuristic(scattering_pdf, env.pdf(&r.direction))
+            }
+        }
+    }
+
+    // Next event estimation towards the environment, weighted against hitting it by scattering.
+    // `combine` multiplies the BSDF times cosine with the weighted incident radiance, in RGB or spectrally.
+    fn sample_environment<T, F>(&self, world: &Arc<dyn Hittable + Sync>, r: &Ray, rec: &HitRecord, combine: &F) -> T
+    where T: Default, F: Fn(&Color, &Color) -> T {
+        let env: &Arc<dyn Environment + Sync> = match &self.environment {
+            None => return T::default(),
+            Some(env) => env
+        };
+
+        let (direction, light_pdf) = env.sample();
+        if light_pdf <= 0.0 {
+            return T::default();
+        }
+
+        let mat: &Arc<dyn Material + Sync> = rec.mat.as_ref().unwrap();
+        let f: Color = mat.eval(r, rec, &direction);
+        if f.is_black() {
+            return T::default();
+        }
+
+        if self.occluded(world, &rec.p, &direction, f64::INFINITY, r.time) {
+            return T::default();
+        }
+
+        let weight: f64 = power_heuristic(light_pdf, mat.scattering_pdf(r, rec, &direction));
+        combine(&f, &(env.value(&direction) * (weight / light_pdf)))
+    }
+
+    // Direct lighting from every delta light.  Scattering can never hit these, so no MIS is needed.
+    fn sample_lights<T, F>(&self, world: &Arc<dyn Hittable + Sync>, r: &Ray, rec: &HitRecord, combine: &F) -> T
+    where T: Default + ops::AddAssign, F: Fn(&Color, &Color) -> T {
+        let mat: &Arc<dyn Material + Sync> = rec.mat.as_ref().unwrap();
+        let mut color: T = T::default();
+        for light in self.lights.iter() {
+            let (direction, distance, radiance) = light.sample_li(&rec.p);
+            if radiance.is_black() {
+                continue;
+            }
+            let f: Color = mat.eval(r, rec, &direction);
+            if f.is_black() {
+                continue;
+            }
+            if self.occluded(world, &rec.p, &direction, distance, r.time) {
+                continue;
+            }
+            color += combine(&f, &radiance);
+        }
+        color
+    }
+
+    fn occluded(&self, world: &Arc<dyn Hittable + Sync>, p: &Point3, direction: &Vec3, distance: f64, time: f64) -> bool {
+        let shadow_ray: Ray = Ray::new(*p, *direction, time);
+        let mut shadow_rec: HitRecord = HitRecord{..HitRecord::default()};
+        world.hit(&shadow_ray, Interval { min: 0.001, max: distance - 0.001 }, &mut shadow_rec)
     }
     
-    fn render_pixel(self, x:i32, y: i32, world: &Arc<dyn Hittable + Sync>) -> Color {
+    // Returns the summed colour and the number of camera rays that hit geometry.
+    fn render_pixel(&self, x:i32, y: i32, world: &Arc<dyn Hittable + Sync>) -> (Color, f64) {
         let mut pixel_color: Vec3 = Color::zero();
+        let mut coverage: f64 = 0.0;
         for _s in 0..self.samples_per_pixel {
-            let r: Ray = self.get_ray(x, y);
-            pixel_color += self.ray_color(world, &r, self.max_depth);
+            let mut r: Ray = self.get_ray(x, y);
+            let hit: bool = if self.spectral {
+                let mut sw: SampledWavelengths = SampledWavelengths::sample_visible(rand::thread_rng().gen());
+                r.wavelength = sw.hero();
+                let mut hit: bool = false;
+                let spectrum: SampledSpectrum = self.trace_spectral(world, &r, self.max_depth, 0.0, &mut sw, &mut hit);
+                pixel_color += self.xyz_to_rgb * sw.spectrum_to_xyz(&spectrum);
+                hit
+            } else {
+                let (color, hit) = self.ray_color(world, &r, self.max_depth);
+                pixel_color += color;
+                hit
+            };
+            if hit {
+                coverage += 1.0;
+            }
         }
-        pixel_color
+        (pixel_color, coverage)
 
     }
 
@@ -158,12 +475,11 @@ impl Camera {
         let size: i32  = image_buffer.lock().unwrap().len() as i32;
         for y in 0..self.image_height {
             for x in 0..self.image_width {                
-                let pixel_color: Color = self.render_pixel(x, y, &world_arc);
-                let pos: i32 = (x + y * self.image_width) * 3;
+                let (pixel_color, coverage) = self.render_pixel(x, y, world_arc);
+                let pos: i32 = (x + y * self.image_width) * self.bytes_per_pixel();
                 let mut buffer: std::sync::MutexGuard<'_, Vec<u8>> = image_buffer.lock().unwrap();
-                write_color(&mut buffer, &pixel_color, self.samples_per_pixel, pos as usize);
+                self.write_color(&mut buffer, &pixel_color, coverage, pos as usize);
 
-                let pos: i32 = (x + y * self.image_width) * 3;
                 let prog: f64 = pos as f64 / size as f64;
                 let t: f64 = start.elapsed().as_secs_f64();
                 let estimate: f64 = if pos > 0 {(t/pos as f64) * size as f64} else {0.0};
@@ -178,7 +494,7 @@ impl Camera {
         }
     }
 
-    fn render_multi(self, world_arc:&Arc<dyn Hittable + Sync>, image_buffer: &Arc<Mutex<Vec<u8>>>, threads:usize, start: &Instant) {
+    fn render_multi(&self, world_arc:&Arc<dyn Hittable + Sync>, image_buffer: &Arc<Mutex<Vec<u8>>>, threads:usize, start: &Instant) {
         let total_possible_threads: i32 = self.image_height * threads as i32;
 
         let line_step: i32 = self.image_width / threads as i32;
@@ -188,16 +504,17 @@ impl Camera {
         for y in 0..self.image_height {
             for i in 0..threads {
                 pool.execute( {
-                    let image_buffer_clone: Arc<Mutex<Vec<u8>>> = Arc::clone(&image_buffer);
+                    let image_buffer_clone: Arc<Mutex<Vec<u8>>> = Arc::clone(image_buffer);
                     let world_clone: Arc<dyn Hittable + Sync> = world_arc.clone();
+                    let cam: Camera = self.clone();
                     move || {
-                        let scanline_start: i32 = (i as i32 * line_step).min(self.image_width);
-                        let scanline_end: i32 = (scanline_start + line_step).min(self.image_width);
+                        let scanline_start: i32 = (i as i32 * line_step).min(cam.image_width);
+                        let scanline_end: i32 = (scanline_start + line_step).min(cam.image_width);
                         for x in scanline_start..scanline_end {
-                            let pixel_color = self.render_pixel(x, y, &world_clone);
-                            let pos: i32 = (x + y * self.image_width) * 3;
+                            let (pixel_color, coverage) = cam.render_pixel(x, y, &world_clone);
+                            let pos: i32 = (x + y * cam.image_width) * cam.bytes_per_pixel();
                             let mut buffer: std::sync::MutexGuard<'_, Vec<u8>> = image_buffer_clone.lock().unwrap();
-                            write_color(&mut buffer, &pixel_color, self.samples_per_pixel, pos as usize);
+                            cam.write_color(&mut buffer, &pixel_color, coverage, pos as usize);
                         }
                     }
                 });   
@@ -231,7 +548,7 @@ impl Camera {
 
         println!("Image size: {}x{}, Samples: {}", self.image_width, self.image_height, self.samples_per_pixel);
         
-        let size: i32 = self.image_width * self.image_width * 3;
+        let size: i32 = self.image_width * self.image_height * self.bytes_per_pixel();
         let image_buffer: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(vec![0; size as usize]));
     
         // Start timer
@@ -245,19 +562,23 @@ impl Camera {
 
         println!("\nTotal render time: {}", seconds_to_hhmmss(start.elapsed().as_secs_f64()));
     
-        self.save_file(&*image_buffer.lock().unwrap(), output)?;
+        self.save_file(&image_buffer.lock().unwrap(), output)?;
     
         println!("Total time {}", seconds_to_hhmmss(start.elapsed().as_secs_f64()));
         Ok(())
     }
 
-    fn save_file(&self, image_data:&Vec<u8>, output:std::path::PathBuf) -> Result<(), std::io::Error> {
+    fn save_file(&self, image_data:&[u8], output:std::path::PathBuf) -> Result<(), std::io::Error> {
         println!("Saving to: {}", output.display());
         let dir: std::path::PathBuf = output.with_file_name("");
         if !(dir.exists() || dir.as_os_str().is_empty()) {
             fs::create_dir_all(dir)?;
         }
-        tga::write_tga_file(self.image_width, self.image_height, &image_data, &output)
+        tga::write_tga_file(self.image_width, self.image_height, image_data, &self.tga_options, &output)
+    }
+
+    fn bytes_per_pixel(&self) -> i32 {
+        if self.tga_options.alpha { 4 } else { 3 }
     }
 
 }
\ No newline at end of file
@@ -0,0 +1,140 @@
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+
+use crate::mat3::Mat3;
+use crate::spectrum::xyz_to_srgb_matrix;
+use crate::tonemap::{bradford_matrix, xy_to_xyz};
+use crate::vec3::*;
+
+// RGB colour spaces, each a set of primaries, a white point and a transfer function.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, ValueEnum)]
+pub enum ColorSpace {
+    /// Rec.709 primaries with the sRGB curve, how 8-bit images and displays are usually encoded.
+    #[default]
+    Srgb,
+    /// Linear Rec.709, the primaries of sRGB without the curve.
+    Rec709,
+    /// Linear ACES AP1 primaries with a D60 white.
+    #[value(name = "acescg")]
+    AcesCg,
+    /// DCI-P3 primaries with a D65 white and the sRGB curve.
+    DisplayP3
+}
+
+pub fn srgb_to_linear_f64(c: f64) -> f64 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+// Bradford adaptation between two XYZ white points, in XYZ.
+fn adapt_white(src: &Vec3, dst: &Vec3) -> Mat3 {
+    let bradford: Mat3 = bradford_matrix();
+    let src_lms: Vec3 = bradford * *src;
+    let dst_lms: Vec3 = bradford * *dst;
+    let scale: Mat3 = Mat3::diagonal(&Vec3::new(dst_lms.x() / src_lms.x(), dst_lms.y() / src_lms.y(), dst_lms.z() / src_lms.z()));
+    bradford.inverse() * scale * bradford
+}
+
+impl ColorSpace {
+    // Chromaticities of the red, green and blue primaries and of the white point.
+    fn chromaticities(&self) -> [(f64, f64); 4] {
+        match self {
+            ColorSpace::Srgb | ColorSpace::Rec709 => [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06), (0.3127, 0.3290)],
+            ColorSpace::AcesCg => [(0.713, 0.293), (0.165, 0.830), (0.128, 0.044), (0.32168, 0.33767)],
+            ColorSpace::DisplayP3 => [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060), (0.3127, 0.3290)]
+        }
+    }
+
+    fn white(&self) -> Vec3 {
+        let (x, y) = self.chromaticities()[3];
+        xy_to_xyz(x, y)
+    }
+
+    // Linear RGB to XYZ, scaled so RGB white maps to the white point with luminance 1.
+    fn rgb_to_xyz_matrix(&self) -> Mat3 {
+        let c: [(f64, f64); 4] = self.chromaticities();
+        let (r, g, b) = (xy_to_xyz(c[0].0, c[0].1), xy_to_xyz(c[1].0, c[1].1), xy_to_xyz(c[2].0, c[2].1));
+        let primaries: Mat3 = Mat3::new([
+            [r.x(), g.x(), b.x()],
+            [r.y(), g.y(), b.y()],
+            [r.z(), g.z(), b.z()]
+        ]);
+        let s: Vec3 = primaries.inverse() * self.white();
+        primaries * Mat3::diagonal(&s)
+    }
+
+    pub fn is_linear(&self) -> bool {
+        matches!(self, ColorSpace::Rec709 | ColorSpace::AcesCg)
+    }
+
+    // Encoded values to linear light in this space's primaries.
+    pub fn decode(&self, c: &Color) -> Color {
+        if self.is_linear() {
+            return *c;
+        }
+        Color::new(srgb_to_linear_f64(c.r()), srgb_to_linear_f64(c.g()), srgb_to_linear_f64(c.b()))
+    }
+
+    // Linear light in this space's primaries to encoded values.
+    pub fn encode(&self, c: &Color) -> Color {
+        if self.is_linear() {
+            return *c;
+        }
+        c.to_srgb()
+    }
+}
+
+// Linear RGB in `from` to linear RGB in `to`, adapting between white points.
+pub fn conversion_matrix(from: ColorSpace, to: ColorSpace) -> Mat3 {
+    if from.chromaticities() == to.chromaticities() {
+        return Mat3::identity();
+    }
+    to.rgb_to_xyz_matrix().inverse() * adapt_white(&from.white(), &to.white()) * from.rgb_to_xyz_matrix()
+}
+
+// The linear space rendering happens in.  Colours written in scenes are taken to be in it, image textures
+// and environment maps are converted to it on load, and the camera converts out of it on output.
+struct WorkingSpace {
+    space: ColorSpace,
+    to_rec709: Mat3,
+    xyz_to_working: Mat3
+}
+
+static WORKING_SPACE: OnceLock<WorkingSpace> = OnceLock::new();
+
+fn working() -> &'static WorkingSpace {
+    WORKING_SPACE.get_or_init(|| -> WorkingSpace { WorkingSpace::new(ColorSpace::Rec709) })
+}
+
+impl WorkingSpace {
+    fn new(space: ColorSpace) -> WorkingSpace {
+        WorkingSpace {
+            space: space,
+            to_rec709: conversion_matrix(space, ColorSpace::Rec709),
+            xyz_to_working: conversion_matrix(ColorSpace::Rec709, space) * xyz_to_srgb_matrix()
+        }
+    }
+}
+
+// Only the primaries of `space` matter, the working space is always linear.  Must be called before any scene
+// is built, returns false if the working space was already fixed.
+pub fn set_working_space(space: ColorSpace) -> bool {
+    WORKING_SPACE.set(WorkingSpace::new(space)).is_ok()
+}
+
+pub fn working_space() -> ColorSpace {
+    working().space
+}
+
+// Working space RGB to linear Rec.709, for code that assumes sRGB primaries like spectral upsampling.
+pub fn working_to_rec709(c: &Color) -> Color {
+    if working().space.chromaticities() == ColorSpace::Rec709.chromaticities() {
+        return *c;
+    }
+    working().to_rec709 * *c
+}
+
+// XYZ to working space RGB, white balanced like `xyz_to_srgb_matrix`.
+pub fn xyz_to_working_matrix() -> Mat3 {
+    working().xyz_to_working
+}
@@ -17,6 +17,16 @@ pub fn degrees_to_radians(degrees:f64) -> f64 {
     degrees * PI / 180.0
 }
 
+// Multiple importance sampling weight for a sample taken with pdf `f`, against another strategy with pdf `g`.
+pub fn power_heuristic(f: f64, g: f64) -> f64 {
+    let f2: f64 = f * f;
+    let g2: f64 = g * g;
+    if f2 + g2 <= 0.0 {
+        return 0.0;
+    }
+    f2 / (f2 + g2)
+}
+
 pub fn seconds_to_hhmmss(s:f64) -> String {
     let seconds = s % 60.0;
     let minutes = (s as i32 / 60) % 60;
@@ -37,13 +47,13 @@ pub fn seconds_to_hhmmss(s:f64) -> String {
     return s_string;
 }
 
-pub fn validate_path(path: &std::path::PathBuf) -> bool {
+pub fn validate_path(path: &std::path::Path) -> bool {
     if path.extension().is_none() {
         let suggestion = path.with_extension("tga");
         println!("{} is missing an extension. Did you mean {}?", path.display(), suggestion.display());
         return false;
     } else {
-        if path.extension().unwrap().to_ascii_lowercase() != "tga" {
+        if !path.extension().unwrap().eq_ignore_ascii_case("tga") {
             println!("Only TGA format is supported.");
             return false;
         }
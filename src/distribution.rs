@@ -0,0 +1,95 @@
+// Piecewise constant distributions, used to importance sample tabulated functions like environment maps.
+
+#[derive(Clone, Default)]
+pub struct Distribution1D {
+    pub func: Vec<f64>,
+    pub cdf: Vec<f64>,
+    pub integral: f64
+}
+
+impl Distribution1D {
+    pub fn new(func: &[f64]) -> Distribution1D {
+        let n: usize = func.len();
+        let mut cdf: Vec<f64> = vec![0.0; n + 1];
+        for i in 1..=n {
+            cdf[i] = cdf[i-1] + func[i-1].abs() / n as f64;
+        }
+
+        let integral: f64 = cdf[n];
+        if integral == 0.0 {
+            for (i, c) in cdf.iter_mut().enumerate() {
+                *c = i as f64 / n as f64;
+            }
+        } else {
+            for c in cdf.iter_mut() {
+                *c /= integral;
+            }
+        }
+
+        Distribution1D {
+            func: func.to_vec(),
+            cdf: cdf,
+            integral: integral
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    // Returns the sampled position in [0, 1), its pdf and the index of the segment it fell in.
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        // Last cdf entry that is <= u.
+        let offset: usize = self.cdf.partition_point(|c| *c <= u).clamp(1, self.count()) - 1;
+
+        let mut du: f64 = u - self.cdf[offset];
+        let width: f64 = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+
+        ((offset as f64 + du) / self.count() as f64, self.pdf(offset), offset)
+    }
+
+    pub fn pdf(&self, offset: usize) -> f64 {
+        if self.integral > 0.0 { self.func[offset] / self.integral } else { 1.0 }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Distribution2D {
+    pub conditional: Vec<Distribution1D>,
+    pub marginal: Distribution1D
+}
+
+impl Distribution2D {
+    // `func` is row major, `height` rows of `width` values.
+    pub fn new(func: &[f64], width: usize, height: usize) -> Distribution2D {
+        let conditional: Vec<Distribution1D> = (0..height)
+            .map(|v| Distribution1D::new(&func[v * width..(v + 1) * width]))
+            .collect();
+        let marginal_func: Vec<f64> = conditional.iter().map(|d| d.integral).collect();
+        Distribution2D {
+            conditional: conditional,
+            marginal: Distribution1D::new(&marginal_func)
+        }
+    }
+
+    // Returns the sampled (u, v) in [0, 1)^2 and its pdf with respect to area in uv space.
+    pub fn sample_continuous(&self, u0: f64, u1: f64) -> (f64, f64, f64) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u1);
+        let (u, pdf_u, _) = self.conditional[row].sample_continuous(u0);
+        (u, v, pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let width: usize = self.conditional[0].count();
+        let height: usize = self.marginal.count();
+        let iu: usize = ((u * width as f64) as usize).min(width - 1);
+        let iv: usize = ((v * height as f64) as usize).min(height - 1);
+        if self.marginal.integral <= 0.0 {
+            return 1.0;
+        }
+        self.conditional[iv].func[iu] / self.marginal.integral
+    }
+}
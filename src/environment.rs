@@ -0,0 +1,119 @@
+use std::f64::consts::PI;
+use std::path::PathBuf;
+
+use rand::Rng;
+
+use crate::color_space::{conversion_matrix, working_space, ColorSpace};
+use crate::common::degrees_to_radians;
+use crate::distribution::Distribution2D;
+use crate::hdr::read_hdr_file;
+use crate::mat3::Mat3;
+use crate::vec3::*;
+
+// Radiance arriving from infinitely far away, seen by rays that miss the world.
+pub trait Environment : Send {
+    fn value(&self, direction: &Vec3) -> Color;
+    // Picks a direction to sample for direct lighting, returns the direction and its solid angle pdf.
+    fn sample(&self) -> (Vec3, f64);
+    fn pdf(&self, direction: &Vec3) -> f64;
+}
+
+#[derive(Clone, Default)]
+pub struct EnvironmentMap {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<Color>,
+    pub intensity: f64,
+    pub sin_theta: f64,
+    pub cos_theta: f64,
+    pub distribution: Distribution2D
+}
+
+impl EnvironmentMap {
+    // Equirectangular (latitude-longitude) map, `rotation` is in degrees around the up axis.
+    pub fn new(file_path: &PathBuf, rotation: f64, intensity: f64) -> Result<EnvironmentMap, std::io::Error> {
+        let mut env: EnvironmentMap = EnvironmentMap::default();
+        read_hdr_file(file_path, &mut env.data, &mut env.width, &mut env.height)?;
+        if env.width == 0 || env.height == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Empty environment map."));
+        }
+
+        // Radiance files are linear Rec.709.
+        let to_working: Mat3 = conversion_matrix(ColorSpace::Rec709, working_space());
+        for c in env.data.iter_mut() {
+            *c = to_working * *c;
+        }
+
+        let s_c: (f64, f64) = degrees_to_radians(rotation).sin_cos();
+        env.sin_theta = s_c.0;
+        env.cos_theta = s_c.1;
+        env.intensity = intensity;
+
+        // Weight by sin(theta) to account for the stretching of rows near the poles.
+        let mut func: Vec<f64> = vec![0.0; env.width * env.height];
+        for y in 0..env.height {
+            let sin_theta: f64 = (PI * (y as f64 + 0.5) / env.height as f64).sin();
+            for x in 0..env.width {
+                func[y * env.width + x] = env.data[y * env.width + x].luminance() * sin_theta;
+            }
+        }
+        env.distribution = Distribution2D::new(&func, env.width, env.height);
+
+        Ok(env)
+    }
+
+    // World direction to map uv, u wraps around the up axis and v runs from up (0) to down (1).
+    fn direction_to_uv(&self, direction: &Vec3) -> (f64, f64) {
+        let d: Vec3 = normalize(*direction);
+        let x: f64 =  self.cos_theta * d.x() - self.sin_theta * d.z();
+        let z: f64 =  self.sin_theta * d.x() + self.cos_theta * d.z();
+        let u: f64 = 0.5 + x.atan2(-z) / (2.0 * PI);
+        let v: f64 = d.y().clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let phi: f64 = (u - 0.5) * 2.0 * PI;
+        let theta: f64 = v * PI;
+        let x: f64 = theta.sin() * phi.sin();
+        let z: f64 = -theta.sin() * phi.cos();
+        Vec3::new(
+            self.cos_theta * x + self.sin_theta * z,
+            theta.cos(),
+            -self.sin_theta * x + self.cos_theta * z
+        )
+    }
+
+    fn lookup(&self, u: f64, v: f64) -> Color {
+        let x: usize = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y: usize = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.data[y * self.width + x]
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn value(&self, direction: &Vec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        self.lookup(u, v) * self.intensity
+    }
+
+    fn sample(&self) -> (Vec3, f64) {
+        let u0: f64 = rand::thread_rng().gen();
+        let u1: f64 = rand::thread_rng().gen();
+        let (u, v, map_pdf) = self.distribution.sample_continuous(u0, u1);
+        let sin_theta: f64 = (v * PI).sin();
+        if map_pdf <= 0.0 || sin_theta <= 0.0 {
+            return (Vec3::up(), 0.0);
+        }
+        (self.uv_to_direction(u, v), map_pdf / (2.0 * PI * PI * sin_theta))
+    }
+
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta: f64 = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
@@ -0,0 +1,183 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Error, ErrorKind};
+use std::path::PathBuf;
+
+use crate::vec3::Color;
+
+// Radiance RGBE (.hdr) reader.
+// https://www.graphics.cornell.edu/~bjw/rgbe.html
+
+// Larger than a 16384 x 8192 map, and well past anything that fits in memory as Colors.
+const MAX_PIXELS: usize = 1 << 27;
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+fn rgbe_to_color(rgbe: &[u8]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::black();
+    }
+    let f: f64 = 2.0_f64.powi(rgbe[3] as i32 - (128 + 8));
+    Color::new(
+        (rgbe[0] as f64 + 0.5) * f,
+        (rgbe[1] as f64 + 0.5) * f,
+        (rgbe[2] as f64 + 0.5) * f
+    )
+}
+
+fn read_header(reader: &mut impl BufRead) -> Result<(usize, usize, bool), Error> {
+    let mut line: String = String::new();
+    reader.read_line(&mut line)?;
+    if !(line.starts_with("#?RADIANCE") || line.starts_with("#?RGBE")) {
+        return Err(invalid("Not a Radiance HDR file."));
+    }
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("Unexpected end of HDR header."));
+        }
+        let trimmed: &str = line.trim();
+        if trimmed.is_empty() {
+            break;
+        }
+        if let Some(format) = trimmed.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid("Only 32-bit_rle_rgbe HDR files are supported."));
+            }
+        }
+    }
+
+    // Resolution string, e.g. "-Y 512 +X 1024".
+    line.clear();
+    reader.read_line(&mut line)?;
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() != 4 || tokens[2] != "+X" || !(tokens[0] == "-Y" || tokens[0] == "+Y") {
+        return Err(invalid("Unsupported HDR resolution string."));
+    }
+    let height: usize = tokens[1].parse().map_err(|_| invalid("Bad HDR height."))?;
+    let width: usize = tokens[3].parse().map_err(|_| invalid("Bad HDR width."))?;
+    let flip_y: bool = tokens[0] == "+Y";
+    if width == 0 || height == 0 {
+        return Err(invalid("HDR image has no pixels."));
+    }
+    if width.checked_mul(height).is_none_or(|pixels: usize| -> bool { pixels > MAX_PIXELS }) {
+        return Err(invalid("HDR image is too large."));
+    }
+
+    Ok((width, height, flip_y))
+}
+
+fn read_scanline(reader: &mut impl Read, width: usize, scanline: &mut [u8]) -> Result<(), Error> {
+    let mut rgbe: [u8; 4] = [0; 4];
+    reader.read_exact(&mut rgbe)?;
+
+    let is_rle: bool = (8..0x8000).contains(&width) && rgbe[0] == 2 && rgbe[1] == 2 && rgbe[2] & 0x80 == 0;
+    if !is_rle {
+        // Flat scanline, the first pixel is already read.
+        scanline[0..4].copy_from_slice(&rgbe);
+        reader.read_exact(&mut scanline[4..width * 4])?;
+        return Ok(());
+    }
+
+    if ((rgbe[2] as usize) << 8 | rgbe[3] as usize) != width {
+        return Err(invalid("HDR scanline width mismatch."));
+    }
+
+    // New style RLE, each of the four channels is run length encoded separately.
+    for channel in 0..4 {
+        let mut x: usize = 0;
+        while x < width {
+            let mut count: [u8; 1] = [0];
+            reader.read_exact(&mut count)?;
+            if count[0] > 128 {
+                let run: usize = (count[0] - 128) as usize;
+                if x + run > width {
+                    return Err(invalid("Bad HDR run length."));
+                }
+                let mut value: [u8; 1] = [0];
+                reader.read_exact(&mut value)?;
+                for i in 0..run {
+                    scanline[(x + i) * 4 + channel] = value[0];
+                }
+                x += run;
+            } else {
+                let run: usize = count[0] as usize;
+                if run == 0 || x + run > width {
+                    return Err(invalid("Bad HDR run length."));
+                }
+                let mut values: Vec<u8> = vec![0; run];
+                reader.read_exact(&mut values)?;
+                for (i, v) in values.iter().enumerate() {
+                    scanline[(x + i) * 4 + channel] = *v;
+                }
+                x += run;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Pixels are returned in linear RGB, top scanline first.
+pub fn read_hdr_file(file_path: &PathBuf, out_image_data: &mut Vec<Color>, out_width: &mut usize, out_height: &mut usize) -> Result<(), Error> {
+    let mut reader: BufReader<File> = BufReader::new(File::open(file_path)?);
+
+    let (width, height, flip_y) = read_header(&mut reader)?;
+
+    out_image_data.clear();
+    out_image_data.resize(width * height, Color::black());
+
+    let mut scanline: Vec<u8> = vec![0; width * 4];
+    for y in 0..height {
+        read_scanline(&mut reader, width, &mut scanline)?;
+        let row: usize = if flip_y { height - 1 - y } else { y };
+        for x in 0..width {
+            out_image_data[row * width + x] = rgbe_to_color(&scanline[x * 4..x * 4 + 4]);
+        }
+    }
+
+    *out_width = width;
+    *out_height = height;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(name: &str, contents: &[u8]) -> Result<(Vec<Color>, usize, usize), Error> {
+        let path: PathBuf = std::env::temp_dir().join(format!("raytracing_weekend_{}_{}.hdr", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let mut data: Vec<Color> = Vec::new();
+        let (mut width, mut height) = (0, 0);
+        let result: Result<(), Error> = read_hdr_file(&path, &mut data, &mut width, &mut height);
+        std::fs::remove_file(&path).unwrap();
+        result.map(|_| -> (Vec<Color>, usize, usize) { (data, width, height) })
+    }
+
+    fn file(resolution: &str, pixels: &[u8]) -> Vec<u8> {
+        let mut contents: Vec<u8> = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{}\n", resolution).into_bytes();
+        contents.extend_from_slice(pixels);
+        contents
+    }
+
+    #[test]
+    fn flat_scanlines() {
+        // 128 x 2^(129 - 136) = 1 in the red channel, 64 x 2^(130 - 136) = 1 in green, 0 exponent is black.
+        let (data, width, height) = read("flat", &file("-Y 1 +X 3", &[128, 0, 0, 129, 0, 64, 0, 130, 9, 9, 9, 0])).unwrap();
+        assert_eq!((width, height), (3, 1));
+        assert!((data[0].r() - 1.0039).abs() < 1e-3 && data[0].g() < 0.01);
+        assert!((data[1].g() - 1.0078).abs() < 1e-3 && data[1].r() < 0.02);
+        assert!(data[2].is_black());
+    }
+
+    #[test]
+    fn bad_resolutions_are_errors() {
+        for resolution in ["-Y 4 +X 0", "-Y 0 +X 4", "+Y 0 +X 0", "-Y 4294967296 +X 4294967296", "-Y 65536 +X 65536"] {
+            let result: Result<(Vec<Color>, usize, usize), Error> = read("resolution", &file(resolution, &[0; 16]));
+            assert!(matches!(result, Err(ref e) if e.kind() == ErrorKind::InvalidData), "{}", resolution);
+        }
+    }
+}
@@ -2,8 +2,11 @@ use std::fmt;
 use std::sync::Arc;
 
 use crate::common::degrees_to_radians;
+use crate::onb::ONB;
 use crate::interval::*;
+use crate::light::Light;
 use crate::material::Material;
+use crate::texture::UvFootprint;
 use crate::vec3::*;
 use crate::ray::*;
 use crate::aabb::*;
@@ -11,19 +14,96 @@ use crate::aabb::*;
 #[derive(Clone, Default)]
 pub struct HitRecord {
     pub p:Point3,
+    // Hit point before instance transforms, relative to the centre for spheres.  Procedural textures in
+    // object space stay attached to the object through it.
+    pub object_p: Point3,
+    // Outward geometric normal before instance transforms.
+    pub object_normal: Vec3,
     pub normal: Vec3,
     pub mat: Option<Arc<dyn Material + Sync>>,
     pub t: f64,
     pub uvw: Vec3,
-    pub front_face: bool
+    pub front_face: bool,
+    // Surface derivatives with respect to the texture coordinates u and v.
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
+    // The same before instance transforms, to move `object_p` along the surface.
+    pub object_dpdu: Vec3,
+    pub object_dpdv: Vec3,
+    // Normal used for shading, on the same side as `normal`.  Differs from it under normal and bump maps.
+    pub shading_normal: Vec3,
+    // Texture coordinate change across a pixel, zero when the ray carried no differentials.
+    pub footprint: UvFootprint,
+    // Position change across a pixel before instance transforms, set with the footprint by the primitive.
+    pub object_dpdx: Vec3,
+    pub object_dpdy: Vec3
 }
 
 impl HitRecord {
     pub fn set_face_normal(self: &mut HitRecord, r: &Ray, outward_normal: &Vec3) {
         self.front_face = dot(&r.direction, outward_normal) < 0.0;
         self.normal = if self.front_face {*outward_normal} else {-(*outward_normal)};
+        self.shading_normal = self.normal;
+    }
+
+    // Intersects the ray differentials with the tangent plane and expresses the offsets in uv, by least
+    // squares against dpdu and dpdv.  Needs p, normal, dpdu and dpdv set.
+    pub fn set_differentials(self: &mut HitRecord, r: &Ray) {
+        self.footprint = UvFootprint::default();
+        self.object_dpdx = Vec3::zero();
+        self.object_dpdy = Vec3::zero();
+        let d: RayDifferentials = match r.differentials {
+            None => return,
+            Some(d) => d
+        };
+
+        let plane_d: f64 = dot(&self.normal, &self.p);
+        let offset_on_plane = |origin: &Point3, direction: &Vec3| -> Option<Vec3> {
+            let denom: f64 = dot(&self.normal, direction);
+            if denom.abs() < 1e-12 {
+                return None;
+            }
+            let t: f64 = (plane_d - dot(&self.normal, origin)) / denom;
+            Some(*origin + *direction * t - self.p)
+        };
+        let (dpdx, dpdy) = match (offset_on_plane(&d.rx_origin, &d.rx_direction), offset_on_plane(&d.ry_origin, &d.ry_direction)) {
+            (Some(dpdx), Some(dpdy)) => (dpdx, dpdy),
+            _ => return
+        };
+        self.object_dpdx = dpdx;
+        self.object_dpdy = dpdy;
+
+        let ata00: f64 = dot(&self.dpdu, &self.dpdu);
+        let ata01: f64 = dot(&self.dpdu, &self.dpdv);
+        let ata11: f64 = dot(&self.dpdv, &self.dpdv);
+        let det: f64 = ata00 * ata11 - ata01 * ata01;
+        if det.abs() < 1e-20 {
+            return;
+        }
+        let inv_det: f64 = 1.0 / det;
+        let solve = |dp: &Vec3| -> (f64, f64) {
+            let b0: f64 = dot(&self.dpdu, dp);
+            let b1: f64 = dot(&self.dpdv, dp);
+            ((ata11 * b0 - ata01 * b1) * inv_det, (ata00 * b1 - ata01 * b0) * inv_det)
+        };
+        let (dudx, dvdx) = solve(&dpdx);
+        let (dudy, dvdy) = solve(&dpdy);
+        if dudx.is_finite() && dvdx.is_finite() && dudy.is_finite() && dvdy.is_finite() {
+            self.footprint = UvFootprint { dudx: dudx, dvdx: dvdx, dudy: dudy, dvdy: dvdy };
+        }
+    }
+
+    pub fn is_cutout(&self) -> bool {
+        match &self.mat {
+            Some(mat) => mat.cutout(self),
+            None => false
+        }
+    }
+
+    // Shading frame with the tangent following dpdu, so anisotropic and normal mapped materials line up with the uvs.
+    pub fn shading_frame(&self) -> ONB {
+        ONB::new_tangent(&self.shading_normal, &self.dpdu)
     }
-    
 }
 
 impl fmt::Display for HitRecord {
@@ -35,6 +115,12 @@ impl fmt::Display for HitRecord {
 pub trait Hittable : Send {
     fn hit(&self, r:&Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
     fn bounding_box(&self) -> AABB;
+
+    // Lights added to lists anywhere under this object, gathered once for next event estimation.  Lights are
+    // placed in world space, instance transforms don't move them and the search stops there.
+    fn collect_lights(&self) -> Vec<Arc<dyn Light + Sync>> {
+        Vec::new()
+    }
 }
 
 #[derive(Default, Clone)]
@@ -57,7 +143,7 @@ impl Translate {
 
 impl Hittable for Translate {
     fn hit(&self, r:&Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let offset_r: Ray = Ray::new(r.origin - self.offset, r.direction, r.time);
+        let offset_r: Ray = r.transformed(|p: &Point3| -> Point3 { *p - self.offset }, |v: &Vec3| -> Vec3 { *v });
 
         if ! self.object.as_ref().unwrap().hit(&offset_r, ray_t, rec) {
             return false;
@@ -121,18 +207,21 @@ impl RotateY {
     }
 }
 
-impl Hittable for RotateY {
-    fn hit(&self, r:&Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let mut origin: Vec3 = r.origin;
-        let mut direction: Vec3 = r.direction;
-
-        origin[0] = self.cos_theta * r.origin[0] - self.sin_theta * r.origin[2];
-        origin[2] = self.sin_theta * r.origin[0] + self.cos_theta * r.origin[2];
+impl RotateY {
+    // Object to world space.
+    fn rotate(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(self.cos_theta * v[0] + self.sin_theta * v[2], v[1], -self.sin_theta * v[0] + self.cos_theta * v[2])
+    }
 
-        direction[0] = self.cos_theta * r.direction[0] - self.sin_theta * r.direction[2];
-        direction[2] = self.sin_theta * r.direction[0] + self.cos_theta * r.direction[2];
+    // World to object space.
+    fn rotate_inverse(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(self.cos_theta * v[0] - self.sin_theta * v[2], v[1], self.sin_theta * v[0] + self.cos_theta * v[2])
+    }
+}
 
-        let rotated_r: Ray = Ray::new(origin, direction, r.time);
+impl Hittable for RotateY {
+    fn hit(&self, r:&Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let rotated_r: Ray = r.transformed(|p: &Point3| -> Point3 { self.rotate_inverse(p) }, |v: &Vec3| -> Vec3 { self.rotate_inverse(v) });
         if ! self.object.as_ref().unwrap().hit(&rotated_r, ray_t, rec) {
             return false;
         }
@@ -141,12 +230,11 @@ impl Hittable for RotateY {
         p[0] =  self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
         p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
 
-        let mut normal: Vec3 = rec.normal;
-        normal[0] =  self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
-        normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
-
         rec.p = p;
-        rec.normal = normal;
+        rec.normal = self.rotate(&rec.normal);
+        rec.shading_normal = self.rotate(&rec.shading_normal);
+        rec.dpdu = self.rotate(&rec.dpdu);
+        rec.dpdv = self.rotate(&rec.dpdv);
 
         return true;
     }
@@ -178,14 +266,16 @@ impl Scale {
 impl Hittable for Scale {
     fn hit(&self, r:&Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
         let inv_scale: Vec3 = 1.0 / self.scale;
-        let scaled_o: Vec3 = r.origin * inv_scale;
-        let scaled_d: Vec3 = ((r.origin + r.direction) * inv_scale) - scaled_o;
-        let scaled_r: Ray = Ray::new(scaled_o, scaled_d, r.time);
+        let scaled_r: Ray = r.transformed(|p: &Point3| -> Point3 { *p * inv_scale }, |v: &Vec3| -> Vec3 { *v * inv_scale });
         if ! self.object.as_ref().unwrap().hit(&scaled_r, ray_t, rec) {
             return false;
         }
         rec.p *= self.scale;
-        rec.normal = normalize(rec.normal * self.scale);
+        // Normals take the inverse transpose, which for a scale is the inverse scale.
+        rec.normal = normalize(rec.normal * inv_scale);
+        rec.shading_normal = normalize(rec.shading_normal * inv_scale);
+        rec.dpdu *= self.scale;
+        rec.dpdv *= self.scale;
         return true;
     }
 
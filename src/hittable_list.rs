@@ -4,10 +4,12 @@ use crate::hittable::*;
 use crate::interval::*;
 use crate::ray::*;
 use crate::aabb::*;
+use crate::light::Light;
 
 #[derive(Default, Clone)]
 pub struct HittableList {
     pub objects: Vec<Arc<dyn Hittable + Sync>>,
+    pub lights: Vec<Arc<dyn Light + Sync>>,
     pub bbox: AABB
 }
 
@@ -23,6 +25,11 @@ impl HittableList {
         self.bbox += obj.bounding_box();
         self.objects.push(obj);
     }
+
+    // Lights can go on any list, nested ones included, as long as no instance transform sits above it.
+    pub fn add_light(self: &mut HittableList, light: Arc<dyn Light + Sync>) {
+        self.lights.push(light);
+    }
 }
 
 impl Hittable for HittableList {
@@ -46,4 +53,12 @@ impl Hittable for HittableList {
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
+
+    fn collect_lights(&self) -> Vec<Arc<dyn Light + Sync>> {
+        let mut lights: Vec<Arc<dyn Light + Sync>> = self.lights.clone();
+        for object in self.objects.iter() {
+            lights.extend(object.collect_lights());
+        }
+        lights
+    }
 }
\ No newline at end of file
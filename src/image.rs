@@ -0,0 +1,108 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+use crate::color_space::ColorSpace;
+use crate::hdr::read_hdr_file;
+use crate::jpeg::{read_jpeg_file, JPEG_SIGNATURE};
+use crate::png::{read_png_file, PNG_SIGNATURE};
+use crate::tga::{read_tga_file, TgaError};
+use crate::vec3::Color;
+
+#[derive(Debug)]
+pub enum ImageError {
+    // PNG, JPEG and HDR problems are reported as invalid data.
+    Io(std::io::Error),
+    Tga(TgaError)
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(e) => write!(f, "{}", e),
+            ImageError::Tga(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageError::Io(e) => Some(e),
+            ImageError::Tga(e) => Some(e)
+        }
+    }
+}
+
+impl From<std::io::Error> for ImageError {
+    fn from(e: std::io::Error) -> ImageError {
+        ImageError::Io(e)
+    }
+}
+
+impl From<TgaError> for ImageError {
+    fn from(e: TgaError) -> ImageError {
+        ImageError::Tga(e)
+    }
+}
+
+// Decoded image as RGBA, bottom row first like TGA so v runs up the image.  8 and 16-bit formats are scaled to
+// [0, 1], HDR values are unbounded.  Pixels are as stored, `color_space` is how they're usually meant, sRGB for
+// integer formats and linear Rec.709 for HDR.
+#[derive(Clone, Default)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub has_alpha: bool,
+    pub color_space: ColorSpace,
+    pub pixels: Vec<[f32; 4]>
+}
+
+impl Image {
+    // Builds an image from RGBA rows given top row first.
+    fn from_top_down(width: usize, height: usize, has_alpha: bool, pixels: Vec<[f32; 4]>) -> Image {
+        let mut pixels: Vec<[f32; 4]> = pixels;
+        for y in 0..height / 2 {
+            let (top, bottom) = pixels.split_at_mut((height - 1 - y) * width);
+            top[y * width..(y + 1) * width].swap_with_slice(&mut bottom[0..width]);
+        }
+        Image { width: width, height: height, has_alpha: has_alpha, color_space: ColorSpace::Srgb, pixels: pixels }
+    }
+}
+
+// Loads a PNG, JPEG, Radiance HDR or TGA file, picked by its signature.  TGA has none and is the fallback.
+pub fn load_image(file_path: &PathBuf) -> Result<Image, ImageError> {
+    let mut signature: Vec<u8> = Vec::new();
+    File::open(file_path)?.take(10).read_to_end(&mut signature)?;
+
+    let mut pixels: Vec<[f32; 4]> = Vec::new();
+    let (mut width, mut height) = (0, 0);
+
+    if signature.starts_with(&PNG_SIGNATURE) {
+        let mut has_alpha: bool = false;
+        read_png_file(file_path, &mut pixels, &mut width, &mut height, &mut has_alpha)?;
+        return Ok(Image::from_top_down(width, height, has_alpha, pixels));
+    }
+
+    if signature.starts_with(&JPEG_SIGNATURE) {
+        read_jpeg_file(file_path, &mut pixels, &mut width, &mut height)?;
+        return Ok(Image::from_top_down(width, height, false, pixels));
+    }
+
+    if signature.starts_with(b"#?RADIANCE") || signature.starts_with(b"#?RGBE") {
+        let mut colors: Vec<Color> = Vec::new();
+        read_hdr_file(file_path, &mut colors, &mut width, &mut height)?;
+        pixels = colors.iter().map(|c: &Color| -> [f32; 4] { [c.r() as f32, c.g() as f32, c.b() as f32, 1.0] }).collect();
+        return Ok(Image { color_space: ColorSpace::Rec709, ..Image::from_top_down(width, height, false, pixels) });
+    }
+
+    let mut data: Vec<u8> = Vec::new();
+    let mut bytes_per_pixel: usize = 0;
+    read_tga_file(file_path, &mut data, &mut width, &mut height, &mut bytes_per_pixel)?;
+    pixels = data.chunks_exact(bytes_per_pixel).map(|bgr: &[u8]| -> [f32; 4] {
+        let alpha: u8 = if bytes_per_pixel >= 4 { bgr[3] } else { 255 };
+        [bgr[2] as f32 / 255.0, bgr[1] as f32 / 255.0, bgr[0] as f32 / 255.0, alpha as f32 / 255.0]
+    }).collect();
+    Ok(Image { width: width, height: height, has_alpha: bytes_per_pixel >= 4, color_space: ColorSpace::Srgb, pixels: pixels })
+}
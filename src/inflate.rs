@@ -0,0 +1,330 @@
+use std::io::{Error, ErrorKind};
+
+// zlib (RFC 1950) and DEFLATE (RFC 1951) decompression, enough for PNG image data.
+// Huffman decoding follows Mark Adler's puff.c, one bit at a time.
+
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+// Order the code length code lengths are stored in by dynamic blocks.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+// Reads bits least significant first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, n: u32) -> Result<u32, Error> {
+        while self.bit_count < n {
+            let byte: u8 = *self.data.get(self.pos).ok_or_else(|| invalid("Unexpected end of deflate stream."))?;
+            self.pos += 1;
+            self.bit_buf |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value: u32 = self.bit_buf & ((1u32 << n) - 1);
+        self.bit_buf >>= n;
+        self.bit_count -= n;
+        Ok(value)
+    }
+
+    // Drops the bits left in the current byte.
+    fn align_to_byte(&mut self) {
+        let remainder: u32 = self.bit_count % 8;
+        self.bit_buf >>= remainder;
+        self.bit_count -= remainder;
+    }
+}
+
+// Canonical Huffman code, as the number of codes of each length and the symbols ordered by code.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, Error> {
+        let mut counts: [u16; MAX_BITS + 1] = [0; MAX_BITS + 1];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // Over-subscribed codes can't be decoded, incomplete ones are allowed.
+        let mut left: i32 = 1;
+        for count in counts.iter().skip(1) {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return Err(invalid("Over-subscribed Huffman code."));
+            }
+        }
+
+        let mut offsets: [u16; MAX_BITS + 1] = [0; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols: Vec<u16> = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+
+        Ok(Huffman { counts: counts, symbols: symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, Error> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..=MAX_BITS {
+            code |= reader.bits(1)? as i32;
+            let count: i32 = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("Bad Huffman code."))
+    }
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), Error> {
+    let mut lengths: [u8; 288] = [8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), Error> {
+    let literal_count: usize = reader.bits(5)? as usize + 257;
+    let distance_count: usize = reader.bits(5)? as usize + 1;
+    let code_length_count: usize = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(invalid("Bad deflate code counts."));
+    }
+
+    let mut code_lengths: [u8; 19] = [0; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+        code_lengths[*index] = reader.bits(3)? as u8;
+    }
+    let code_length_code: Huffman = Huffman::new(&code_lengths)?;
+
+    // Literal/length and distance code lengths are one run length coded sequence.
+    let mut lengths: Vec<u8> = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol: u16 = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or_else(|| invalid("Repeat with no previous length."))?, 3 + reader.bits(2)? as usize),
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize)
+        };
+        if lengths.len() + repeat > literal_count + distance_count {
+            return Err(invalid("Too many deflate code lengths."));
+        }
+        lengths.extend(std::iter::repeat_n(value, repeat));
+    }
+    if lengths[256] == 0 {
+        return Err(invalid("Deflate block has no end code."));
+    }
+
+    Ok((Huffman::new(&lengths[0..literal_count])?, Huffman::new(&lengths[literal_count..])?))
+}
+
+fn inflate_block(reader: &mut BitReader, literals: &Huffman, distances: &Huffman, out: &mut Vec<u8>) -> Result<(), Error> {
+    loop {
+        let symbol: usize = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let symbol: usize = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(invalid("Bad deflate length code."));
+        }
+        let length: usize = LENGTH_BASE[symbol] as usize + reader.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+        let symbol: usize = distances.decode(reader)? as usize;
+        if symbol >= DIST_BASE.len() {
+            return Err(invalid("Bad deflate distance code."));
+        }
+        let distance: usize = DIST_BASE[symbol] as usize + reader.bits(DIST_EXTRA[symbol] as u32)? as usize;
+        if distance > out.len() {
+            return Err(invalid("Deflate distance is too far back."));
+        }
+
+        // The copy may overlap what it writes.
+        let start: usize = out.len() - distance;
+        for i in 0..length {
+            out.push(out[start + i]);
+        }
+    }
+}
+
+// Decompresses a raw DEFLATE stream, returning the data and the number of bytes consumed.
+fn inflate(data: &[u8]) -> Result<(Vec<u8>, usize), Error> {
+    let mut reader: BitReader = BitReader { data: data, pos: 0, bit_buf: 0, bit_count: 0 };
+    let mut out: Vec<u8> = Vec::new();
+    loop {
+        let last: bool = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let length: u32 = reader.bits(16)?;
+                let complement: u32 = reader.bits(16)?;
+                if length != !complement & 0xFFFF {
+                    return Err(invalid("Bad stored block length."));
+                }
+                // Whole bytes are all that's left in the bit buffer once aligned.
+                for _ in 0..length {
+                    out.push(reader.bits(8)? as u8);
+                }
+            }
+            1 => {
+                let (literals, distances) = fixed_codes()?;
+                inflate_block(&mut reader, &literals, &distances, &mut out)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &literals, &distances, &mut out)?;
+            }
+            _ => return Err(invalid("Bad deflate block type."))
+        }
+        if last {
+            break;
+        }
+    }
+    reader.align_to_byte();
+    let consumed: usize = reader.pos - (reader.bit_count / 8) as usize;
+    Ok((out, consumed))
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+// Decompresses a zlib stream and checks its Adler-32.
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    if data.len() < 6 || data[0] & 0x0F != 8 || !((data[0] as u16) << 8 | data[1] as u16).is_multiple_of(31) {
+        return Err(invalid("Bad zlib header."));
+    }
+    if data[1] & 0x20 != 0 {
+        return Err(invalid("zlib preset dictionaries are not supported."));
+    }
+
+    let (out, consumed) = inflate(&data[2..])?;
+    let end: usize = 2 + consumed;
+    let checksum: &[u8] = data.get(end..end + 4).ok_or_else(|| invalid("Missing zlib checksum."))?;
+    if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(&out) {
+        return Err(invalid("zlib checksum mismatch."));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &[u8] = b"Hello, hello, hello! The quick brown fox jumps over the lazy dog. ";
+
+    fn text() -> Vec<u8> {
+        TEXT.repeat(3)
+    }
+
+    // Stored blocks of the first 40 bytes, nothing from a flush, and then the rest.
+    const STORED: [u8; 219] = [
+        0x78, 0x01, 0x00, 0x28, 0x00, 0xD7, 0xFF, 0x48, 0x65, 0x6C, 0x6C, 0x6F, 0x2C, 0x20, 0x68, 0x65,
+        0x6C, 0x6C, 0x6F, 0x2C, 0x20, 0x68, 0x65, 0x6C, 0x6C, 0x6F, 0x21, 0x20, 0x54, 0x68, 0x65, 0x20,
+        0x71, 0x75, 0x69, 0x63, 0x6B, 0x20, 0x62, 0x72, 0x6F, 0x77, 0x6E, 0x20, 0x66, 0x6F, 0x78, 0x00,
+        0x00, 0x00, 0xFF, 0xFF, 0x01, 0x9E, 0x00, 0x61, 0xFF, 0x20, 0x6A, 0x75, 0x6D, 0x70, 0x73, 0x20,
+        0x6F, 0x76, 0x65, 0x72, 0x20, 0x74, 0x68, 0x65, 0x20, 0x6C, 0x61, 0x7A, 0x79, 0x20, 0x64, 0x6F,
+        0x67, 0x2E, 0x20, 0x48, 0x65, 0x6C, 0x6C, 0x6F, 0x2C, 0x20, 0x68, 0x65, 0x6C, 0x6C, 0x6F, 0x2C,
+        0x20, 0x68, 0x65, 0x6C, 0x6C, 0x6F, 0x21, 0x20, 0x54, 0x68, 0x65, 0x20, 0x71, 0x75, 0x69, 0x63,
+        0x6B, 0x20, 0x62, 0x72, 0x6F, 0x77, 0x6E, 0x20, 0x66, 0x6F, 0x78, 0x20, 0x6A, 0x75, 0x6D, 0x70,
+        0x73, 0x20, 0x6F, 0x76, 0x65, 0x72, 0x20, 0x74, 0x68, 0x65, 0x20, 0x6C, 0x61, 0x7A, 0x79, 0x20,
+        0x64, 0x6F, 0x67, 0x2E, 0x20, 0x48, 0x65, 0x6C, 0x6C, 0x6F, 0x2C, 0x20, 0x68, 0x65, 0x6C, 0x6C,
+        0x6F, 0x2C, 0x20, 0x68, 0x65, 0x6C, 0x6C, 0x6F, 0x21, 0x20, 0x54, 0x68, 0x65, 0x20, 0x71, 0x75,
+        0x69, 0x63, 0x6B, 0x20, 0x62, 0x72, 0x6F, 0x77, 0x6E, 0x20, 0x66, 0x6F, 0x78, 0x20, 0x6A, 0x75,
+        0x6D, 0x70, 0x73, 0x20, 0x6F, 0x76, 0x65, 0x72, 0x20, 0x74, 0x68, 0x65, 0x20, 0x6C, 0x61, 0x7A,
+        0x79, 0x20, 0x64, 0x6F, 0x67, 0x2E, 0x20, 0xEF, 0x14, 0x45, 0x55
+    ];
+
+    // One fixed Huffman block with back references.
+    const FIXED: [u8; 67] = [
+        0x78, 0x01, 0xF3, 0x48, 0xCD, 0xC9, 0xC9, 0xD7, 0x51, 0xC8, 0x40, 0xA2, 0x14, 0x15, 0x42, 0x32,
+        0x52, 0x15, 0x0A, 0x4B, 0x33, 0x93, 0xB3, 0x15, 0x92, 0x8A, 0xF2, 0xCB, 0xF3, 0x14, 0xD2, 0xF2,
+        0x2B, 0x14, 0xB2, 0x4A, 0x73, 0x0B, 0x8A, 0x15, 0xF2, 0xCB, 0x52, 0x8B, 0x14, 0x4A, 0x80, 0xD2,
+        0x39, 0x89, 0x55, 0x95, 0x0A, 0x29, 0xF9, 0xE9, 0x7A, 0x0A, 0x1E, 0x83, 0xC0, 0x04, 0x00, 0xEF,
+        0x14, 0x45, 0x55
+    ];
+
+    #[test]
+    fn stored_blocks() {
+        assert_eq!(zlib_decompress(&STORED).unwrap(), text());
+    }
+
+    #[test]
+    fn fixed_huffman_block() {
+        assert_eq!(zlib_decompress(&FIXED).unwrap(), text());
+    }
+
+    #[test]
+    fn consumed_stops_at_the_final_block() {
+        let (out, consumed) = inflate(&FIXED[2..]).unwrap();
+        assert_eq!(out, text());
+        assert_eq!(consumed, FIXED.len() - 6);
+    }
+
+    #[test]
+    fn adler32_known_value() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+        assert_eq!(adler32(&[]), 1);
+        // Long enough for the sums to be reduced part way through.
+        assert_eq!(adler32(&[0xFF; 6000]), 0xA497_59EA);
+    }
+
+    #[test]
+    fn corrupt_streams_are_errors() {
+        let mut checksum: Vec<u8> = FIXED.to_vec();
+        *checksum.last_mut().unwrap() ^= 1;
+        assert!(zlib_decompress(&checksum).is_err());
+
+        let mut length: Vec<u8> = STORED.to_vec();
+        length[5] ^= 1;
+        assert!(zlib_decompress(&length).is_err());
+
+        assert!(zlib_decompress(&FIXED[..FIXED.len() / 2]).is_err());
+        assert!(zlib_decompress(&FIXED[..FIXED.len() - 2]).is_err());
+        assert!(zlib_decompress(&[0x78, 0x02, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01]).is_err());
+        // Reserved block type 3.
+        assert!(zlib_decompress(&[0x78, 0x01, 0x07, 0x00, 0x00, 0x00, 0x00, 0x01]).is_err());
+        // Preset dictionary.
+        assert!(zlib_decompress(&[0x78, 0xBB, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01]).is_err());
+    }
+}
@@ -0,0 +1,562 @@
+use std::f64::consts::PI;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+
+// Baseline and extended sequential Huffman JPEG reader, greyscale or three component YCbCr (JFIF) or RGB (Adobe).
+// Progressive, arithmetic coded, 12-bit and CMYK files are rejected.
+// https://www.w3.org/Graphics/JPEG/itu-t81.pdf
+
+pub const JPEG_SIGNATURE: [u8; 3] = [0xFF, 0xD8, 0xFF];
+
+// Position in the 8x8 block, row major, of each coefficient in zig-zag order.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5,
+    12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63
+];
+
+// Markers.
+const SOF0: u8 = 0xC0;
+const SOF1: u8 = 0xC1;
+const DHT: u8 = 0xC4;
+const RST0: u8 = 0xD0;
+const RST7: u8 = 0xD7;
+const SOI: u8 = 0xD8;
+const EOI: u8 = 0xD9;
+const SOS: u8 = 0xDA;
+const DQT: u8 = 0xDB;
+const DRI: u8 = 0xDD;
+const APP14: u8 = 0xEE;
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+fn u16_be(bytes: &[u8], pos: usize) -> usize {
+    u16::from_be_bytes([bytes[pos], bytes[pos + 1]]) as usize
+}
+
+// Huffman table as the largest code of each length and where that length's symbols start (F.2.2.3).
+#[derive(Clone, Default)]
+struct HuffmanTable {
+    max_code: [i32; 17],
+    val_offset: [i32; 17],
+    values: Vec<u8>
+}
+
+impl HuffmanTable {
+    fn new(counts: &[u8], values: &[u8]) -> HuffmanTable {
+        let mut table: HuffmanTable = HuffmanTable { max_code: [-1; 17], val_offset: [0; 17], values: values.to_vec() };
+        let mut code: i32 = 0;
+        let mut k: i32 = 0;
+        for len in 1..=16 {
+            let count: i32 = counts[len - 1] as i32;
+            table.val_offset[len] = k - code;
+            if count > 0 {
+                code += count;
+                k += count;
+                table.max_code[len] = code - 1;
+            }
+            code <<= 1;
+        }
+        table
+    }
+}
+
+#[derive(Clone, Default)]
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant_table: usize,
+    dc_table: usize,
+    ac_table: usize,
+    dc_prediction: i32,
+    // Decoded samples, whole blocks wide and high.
+    blocks_wide: usize,
+    blocks_high: usize,
+    samples: Vec<u8>
+}
+
+// Reads entropy coded bits most significant first, removing stuffed zero bytes and stopping at markers.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> u32 {
+        if self.bit_count == 0 {
+            let mut byte: u8 = 0;
+            if self.pos < self.data.len() {
+                byte = self.data[self.pos];
+                if byte == 0xFF {
+                    match self.data.get(self.pos + 1) {
+                        Some(0) => self.pos += 2,
+                        // A marker, feed zeros until the decoder reaches it.
+                        _ => byte = 0
+                    }
+                } else {
+                    self.pos += 1;
+                }
+            }
+            self.bit_buf = byte as u32;
+            self.bit_count = 8;
+        }
+        self.bit_count -= 1;
+        (self.bit_buf >> self.bit_count) & 1
+    }
+
+    fn bits(&mut self, n: u32) -> u32 {
+        let mut value: u32 = 0;
+        for _ in 0..n {
+            value = (value << 1) | self.bit();
+        }
+        value
+    }
+
+    // Value of `n` bits in the sign and magnitude form of F.2.2.1.
+    fn receive_extend(&mut self, n: u32) -> i32 {
+        if n == 0 {
+            return 0;
+        }
+        let value: i32 = self.bits(n) as i32;
+        if value < 1 << (n - 1) { value - (1 << n) + 1 } else { value }
+    }
+
+    fn decode(&mut self, table: &HuffmanTable) -> Result<u8, Error> {
+        let mut code: i32 = 0;
+        for len in 1..=16 {
+            code = (code << 1) | self.bit() as i32;
+            if code <= table.max_code[len] {
+                return table.values.get((code + table.val_offset[len]) as usize).copied().ok_or_else(|| invalid("Bad JPEG Huffman code."));
+            }
+        }
+        Err(invalid("Bad JPEG Huffman code."))
+    }
+
+    // Skips to the restart marker expected after each restart interval.
+    fn restart(&mut self) -> Result<(), Error> {
+        self.bit_count = 0;
+        while self.pos + 1 < self.data.len() && !(self.data[self.pos] == 0xFF && (RST0..=RST7).contains(&self.data[self.pos + 1])) {
+            self.pos += 1;
+        }
+        if self.pos + 1 >= self.data.len() {
+            return Err(invalid("Missing JPEG restart marker."));
+        }
+        self.pos += 2;
+        Ok(())
+    }
+}
+
+// Inverse DCT basis, C(u) cos((2x + 1) u pi / 16) / 2.
+fn idct_table() -> [[f32; 8]; 8] {
+    let mut table: [[f32; 8]; 8] = [[0.0; 8]; 8];
+    for (x, row) in table.iter_mut().enumerate() {
+        for (u, c) in row.iter_mut().enumerate() {
+            let scale: f64 = if u == 0 { 1.0 / 2.0_f64.sqrt() } else { 1.0 };
+            *c = (scale * ((2 * x + 1) as f64 * u as f64 * PI / 16.0).cos() / 2.0) as f32;
+        }
+    }
+    table
+}
+
+// Separable 8x8 inverse DCT of dequantized coefficients in row major order, level shifted back to [0, 255].
+fn idct(coefficients: &[i32; 64], table: &[[f32; 8]; 8], out: &mut [u8; 64]) {
+    let mut rows: [f32; 64] = [0.0; 64];
+    for v in 0..8 {
+        for x in 0..8 {
+            rows[v * 8 + x] = (0..8).map(|u: usize| -> f32 { table[x][u] * coefficients[v * 8 + u] as f32 }).sum();
+        }
+    }
+    for y in 0..8 {
+        for x in 0..8 {
+            let value: f32 = (0..8).map(|v: usize| -> f32 { table[y][v] * rows[v * 8 + x] }).sum();
+            out[y * 8 + x] = (value + 128.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+struct Decoder {
+    width: usize,
+    height: usize,
+    components: Vec<Component>,
+    quant_tables: [[u16; 64]; 4],
+    dc_tables: [HuffmanTable; 4],
+    ac_tables: [HuffmanTable; 4],
+    restart_interval: usize,
+    // Adobe APP14 transform flag, 0 means three components are RGB rather than YCbCr.
+    adobe_transform: Option<u8>,
+    idct: [[f32; 8]; 8]
+}
+
+impl Decoder {
+    fn max_sampling(&self) -> (usize, usize) {
+        let h: usize = self.components.iter().map(|c: &Component| -> usize { c.h }).max().unwrap_or(1);
+        let v: usize = self.components.iter().map(|c: &Component| -> usize { c.v }).max().unwrap_or(1);
+        (h, v)
+    }
+
+    fn read_frame(&mut self, segment: &[u8]) -> Result<(), Error> {
+        if segment.len() < 6 || segment[0] != 8 {
+            return Err(invalid("Only 8-bit JPEG files are supported."));
+        }
+        self.height = u16_be(segment, 1);
+        self.width = u16_be(segment, 3);
+        let count: usize = segment[5] as usize;
+        if self.width == 0 || self.height == 0 {
+            return Err(invalid("JPEG image has no pixels, or its height is only given by a DNL marker."));
+        }
+        if count != 1 && count != 3 {
+            return Err(invalid("Only greyscale and three component JPEG files are supported."));
+        }
+        for i in 0..count {
+            let c: &[u8] = segment.get(6 + i * 3..9 + i * 3).ok_or_else(|| invalid("JPEG frame header is truncated."))?;
+            let (h, v) = ((c[1] >> 4) as usize, (c[1] & 0x0F) as usize);
+            if !(1..=4).contains(&h) || !(1..=4).contains(&v) || c[2] > 3 {
+                return Err(invalid("Bad JPEG component."));
+            }
+            self.components.push(Component { id: c[0], h: h, v: v, quant_table: c[2] as usize, ..Component::default() });
+        }
+
+        let (h_max, v_max) = self.max_sampling();
+        let mcus_wide: usize = self.width.div_ceil(8 * h_max);
+        let mcus_high: usize = self.height.div_ceil(8 * v_max);
+        for c in self.components.iter_mut() {
+            c.blocks_wide = mcus_wide * c.h;
+            c.blocks_high = mcus_high * c.v;
+            c.samples = vec![0; c.blocks_wide * c.blocks_high * 64];
+        }
+        Ok(())
+    }
+
+    fn read_quant_tables(&mut self, segment: &[u8]) -> Result<(), Error> {
+        let mut pos: usize = 0;
+        while pos < segment.len() {
+            let precision: u8 = segment[pos] >> 4;
+            let id: usize = (segment[pos] & 0x0F) as usize;
+            let size: usize = if precision == 0 { 64 } else { 128 };
+            let values: &[u8] = segment.get(pos + 1..pos + 1 + size).ok_or_else(|| invalid("JPEG quantization table is truncated."))?;
+            if id > 3 {
+                return Err(invalid("Bad JPEG quantization table."));
+            }
+            for k in 0..64 {
+                self.quant_tables[id][k] = if precision == 0 { values[k] as u16 } else { u16_be(values, k * 2) as u16 };
+            }
+            pos += 1 + size;
+        }
+        Ok(())
+    }
+
+    fn read_huffman_tables(&mut self, segment: &[u8]) -> Result<(), Error> {
+        let mut pos: usize = 0;
+        while pos < segment.len() {
+            let class: u8 = segment[pos] >> 4;
+            let id: usize = (segment[pos] & 0x0F) as usize;
+            let counts: &[u8] = segment.get(pos + 1..pos + 17).ok_or_else(|| invalid("JPEG Huffman table is truncated."))?;
+            let total: usize = counts.iter().map(|c: &u8| -> usize { *c as usize }).sum();
+            let values: &[u8] = segment.get(pos + 17..pos + 17 + total).ok_or_else(|| invalid("JPEG Huffman table is truncated."))?;
+            if id > 3 || class > 1 {
+                return Err(invalid("Bad JPEG Huffman table."));
+            }
+            let table: HuffmanTable = HuffmanTable::new(counts, values);
+            if class == 0 {
+                self.dc_tables[id] = table;
+            } else {
+                self.ac_tables[id] = table;
+            }
+            pos += 17 + total;
+        }
+        Ok(())
+    }
+
+    fn decode_block(&mut self, reader: &mut BitReader, component: usize, block_x: usize, block_y: usize) -> Result<(), Error> {
+        let c: &Component = &self.components[component];
+        let quant: &[u16; 64] = &self.quant_tables[c.quant_table];
+        let mut coefficients: [i32; 64] = [0; 64];
+
+        let size: u8 = reader.decode(&self.dc_tables[c.dc_table])?;
+        let dc: i32 = c.dc_prediction + reader.receive_extend(size as u32);
+        coefficients[0] = dc * quant[0] as i32;
+
+        let mut k: usize = 1;
+        while k < 64 {
+            let rs: u8 = reader.decode(&self.ac_tables[c.ac_table])?;
+            let (run, size) = ((rs >> 4) as usize, (rs & 0x0F) as u32);
+            if size == 0 {
+                if run != 15 {
+                    break;
+                }
+                k += 16;
+                continue;
+            }
+            k += run;
+            if k > 63 {
+                return Err(invalid("Bad JPEG AC coefficient run."));
+            }
+            coefficients[ZIGZAG[k]] = reader.receive_extend(size) * quant[k] as i32;
+            k += 1;
+        }
+
+        let mut block: [u8; 64] = [0; 64];
+        idct(&coefficients, &self.idct, &mut block);
+
+        let c: &mut Component = &mut self.components[component];
+        c.dc_prediction = dc;
+        let stride: usize = c.blocks_wide * 8;
+        for y in 0..8 {
+            let row: usize = (block_y * 8 + y) * stride + block_x * 8;
+            c.samples[row..row + 8].copy_from_slice(&block[y * 8..y * 8 + 8]);
+        }
+        Ok(())
+    }
+
+    // Decodes one scan starting at `data`, returning how many bytes of entropy coded data it used.
+    fn read_scan(&mut self, header: &[u8], data: &[u8]) -> Result<usize, Error> {
+        let count: usize = *header.first().ok_or_else(|| invalid("JPEG scan header is truncated."))? as usize;
+        let mut scan: Vec<usize> = Vec::with_capacity(count);
+        for i in 0..count {
+            let s: &[u8] = header.get(1 + i * 2..3 + i * 2).ok_or_else(|| invalid("JPEG scan header is truncated."))?;
+            let index: usize = self.components.iter().position(|c: &Component| -> bool { c.id == s[0] }).ok_or_else(|| invalid("JPEG scan uses an unknown component."))?;
+            let (dc, ac) = ((s[1] >> 4) as usize, (s[1] & 0x0F) as usize);
+            if dc > 3 || ac > 3 {
+                return Err(invalid("Bad JPEG scan tables."));
+            }
+            self.components[index].dc_table = dc;
+            self.components[index].ac_table = ac;
+            self.components[index].dc_prediction = 0;
+            scan.push(index);
+        }
+
+        let mut reader: BitReader = BitReader { data: data, pos: 0, bit_buf: 0, bit_count: 0 };
+        let (h_max, v_max) = self.max_sampling();
+
+        // A single component scan covers just that component's blocks in raster order, an interleaved scan
+        // covers each MCU's blocks of every component in turn.
+        let (mcus_wide, mcus_high) = if scan.len() == 1 {
+            let c: &Component = &self.components[scan[0]];
+            ((self.width * c.h).div_ceil(h_max).div_ceil(8), (self.height * c.v).div_ceil(v_max).div_ceil(8))
+        } else {
+            (self.width.div_ceil(8 * h_max), self.height.div_ceil(8 * v_max))
+        };
+
+        for mcu in 0..mcus_wide * mcus_high {
+            if self.restart_interval > 0 && mcu > 0 && mcu % self.restart_interval == 0 {
+                reader.restart()?;
+                for index in scan.iter() {
+                    self.components[*index].dc_prediction = 0;
+                }
+            }
+
+            let (mcu_x, mcu_y) = (mcu % mcus_wide, mcu / mcus_wide);
+            if scan.len() == 1 {
+                self.decode_block(&mut reader, scan[0], mcu_x, mcu_y)?;
+                continue;
+            }
+            for index in scan.iter() {
+                let (h, v) = (self.components[*index].h, self.components[*index].v);
+                for by in 0..v {
+                    for bx in 0..h {
+                        self.decode_block(&mut reader, *index, mcu_x * h + bx, mcu_y * v + by)?;
+                    }
+                }
+            }
+        }
+        Ok(reader.pos)
+    }
+
+    // Upsamples subsampled planes by interpolating between sample centres, which for 2x is libjpeg's "fancy"
+    // triangle filter, and converts to RGB.
+    fn to_rgb(&self, out_image_data: &mut Vec<[f32; 4]>) {
+        let (h_max, v_max) = self.max_sampling();
+        let rgb_components: bool = self.adobe_transform == Some(0);
+        out_image_data.clear();
+        out_image_data.reserve(self.width * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let sample = |c: &Component| -> f32 {
+                    let plane_width: usize = (self.width * c.h).div_ceil(h_max);
+                    let plane_height: usize = (self.height * c.v).div_ceil(v_max);
+                    let sx: f32 = ((x as f32 + 0.5) * c.h as f32 / h_max as f32 - 0.5).max(0.0);
+                    let sy: f32 = ((y as f32 + 0.5) * c.v as f32 / v_max as f32 - 0.5).max(0.0);
+                    let (x0, y0) = ((sx as usize).min(plane_width - 1), (sy as usize).min(plane_height - 1));
+                    let (x1, y1) = ((x0 + 1).min(plane_width - 1), (y0 + 1).min(plane_height - 1));
+                    let (tx, ty) = (sx - x0 as f32, sy - y0 as f32);
+                    let at = |px: usize, py: usize| -> f32 { c.samples[py * c.blocks_wide * 8 + px] as f32 };
+                    (at(x0, y0) * (1.0 - tx) + at(x1, y0) * tx) * (1.0 - ty) + (at(x0, y1) * (1.0 - tx) + at(x1, y1) * tx) * ty
+                };
+                let rgb: [f32; 3] = if self.components.len() == 1 {
+                    [sample(&self.components[0]); 3]
+                } else if rgb_components {
+                    [sample(&self.components[0]), sample(&self.components[1]), sample(&self.components[2])]
+                } else {
+                    let luma: f32 = sample(&self.components[0]);
+                    let cb: f32 = sample(&self.components[1]) - 128.0;
+                    let cr: f32 = sample(&self.components[2]) - 128.0;
+                    [luma + 1.402 * cr, luma - 0.344136 * cb - 0.714136 * cr, luma + 1.772 * cb]
+                };
+                out_image_data.push([
+                    (rgb[0] / 255.0).clamp(0.0, 1.0),
+                    (rgb[1] / 255.0).clamp(0.0, 1.0),
+                    (rgb[2] / 255.0).clamp(0.0, 1.0),
+                    1.0
+                ]);
+            }
+        }
+    }
+}
+
+// Pixels are returned as RGBA in [0, 1], top row first.
+pub fn read_jpeg_file(file_path: &PathBuf, out_image_data: &mut Vec<[f32; 4]>, out_width: &mut usize, out_height: &mut usize) -> Result<(), Error> {
+    let file: Vec<u8> = fs::read(file_path)?;
+    if file.len() < 4 || file[0] != 0xFF || file[1] != SOI {
+        return Err(invalid("Not a JPEG file."));
+    }
+
+    let mut decoder: Decoder = Decoder {
+        width: 0,
+        height: 0,
+        components: Vec::new(),
+        quant_tables: [[0; 64]; 4],
+        dc_tables: Default::default(),
+        ac_tables: Default::default(),
+        restart_interval: 0,
+        adobe_transform: None,
+        idct: idct_table()
+    };
+    let mut scanned: bool = false;
+
+    let mut pos: usize = 2;
+    loop {
+        // Markers may be padded with any number of 0xFF fill bytes.
+        while pos < file.len() && file[pos] != 0xFF {
+            pos += 1;
+        }
+        while pos < file.len() && file[pos] == 0xFF {
+            pos += 1;
+        }
+        let marker: u8 = *file.get(pos).ok_or_else(|| invalid("JPEG file is truncated."))?;
+        pos += 1;
+        if marker == EOI {
+            break;
+        }
+        // Stuffed zeros and restart markers left over from the entropy coded data.
+        if marker == 0 || (RST0..=RST7).contains(&marker) {
+            continue;
+        }
+
+        let length: usize = if pos + 2 <= file.len() { u16_be(&file, pos) } else { 0 };
+        let segment: &[u8] = file.get(pos + 2..pos + length).ok_or_else(|| invalid("JPEG file is truncated."))?;
+        pos += length;
+
+        match marker {
+            SOF0 | SOF1 => decoder.read_frame(segment)?,
+            0xC2 | 0xC6 | 0xCA | 0xCE => return Err(invalid("Progressive JPEG files are not supported.")),
+            0xC3 | 0xC5 | 0xC7 | 0xC9 | 0xCB | 0xCD | 0xCF => return Err(invalid("Lossless and arithmetic coded JPEG files are not supported.")),
+            DHT => decoder.read_huffman_tables(segment)?,
+            DQT => decoder.read_quant_tables(segment)?,
+            DRI => decoder.restart_interval = if segment.len() >= 2 { u16_be(segment, 0) } else { 0 },
+            APP14 if segment.len() >= 12 && segment.starts_with(b"Adobe") => decoder.adobe_transform = Some(segment[11]),
+            SOS => {
+                if decoder.components.is_empty() {
+                    return Err(invalid("JPEG scan before frame header."));
+                }
+                pos += decoder.read_scan(segment, &file[pos..])?;
+                scanned = true;
+            }
+            _ => {}
+        }
+    }
+
+    if !scanned {
+        return Err(invalid("JPEG file has no image data."));
+    }
+    decoder.to_rgb(out_image_data);
+    *out_width = decoder.width;
+    *out_height = decoder.height;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 32x16 YCbCr at 4:2:0, so two 16x16 MCUs with a restart marker between them, all quantizers one and
+    // Huffman tables holding just the symbols used.  Luma is 70 + 3 x + 2 y, chroma is flat across each MCU.
+    const CB: [f32; 2] = [100.0, 150.0];
+    const CR: [f32; 2] = [160.0, 110.0];
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+    }
+
+    fn read(bytes: &[u8]) -> Result<(), Error> {
+        let path: PathBuf = std::env::temp_dir().join(format!("raytracing_weekend_jpeg_{}.jpg", std::process::id()));
+        fs::write(&path, bytes).unwrap();
+        let mut data: Vec<[f32; 4]> = Vec::new();
+        let (mut w, mut h) = (0, 0);
+        let result: Result<(), Error> = read_jpeg_file(&path, &mut data, &mut w, &mut h);
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn subsampled_with_restart_interval() {
+        let mut data: Vec<[f32; 4]> = Vec::new();
+        let (mut w, mut h) = (0, 0);
+        read_jpeg_file(&fixture("ycbcr420_restart.jpg"), &mut data, &mut w, &mut h).unwrap();
+        assert_eq!((w, h, data.len()), (32, 16, 32 * 16));
+
+        for y in 0..16 {
+            for x in 0..32 {
+                // The chroma of the two columns either side of the MCU edge is blended by the upsampling.
+                if x == 15 || x == 16 {
+                    continue;
+                }
+                let luma: f32 = (70 + 3 * x + 2 * y) as f32;
+                let (cb, cr) = (CB[x / 16] - 128.0, CR[x / 16] - 128.0);
+                let want: [f32; 3] = [luma + 1.402 * cr, luma - 0.344136 * cb - 0.714136 * cr, luma + 1.772 * cb];
+                let got: [f32; 4] = data[y * 32 + x];
+                // Rounding the coefficients and the samples costs a few levels.
+                for c in 0..3 {
+                    assert!((got[c] * 255.0 - want[c]).abs() < 4.0, "({}, {}) channel {}: {} != {}", x, y, c, got[c] * 255.0, want[c]);
+                }
+                assert_eq!(got[3], 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn broken_files_are_errors() {
+        let file: Vec<u8> = fs::read(fixture("ycbcr420_restart.jpg")).unwrap();
+        assert!(read(&file).is_ok());
+        assert!(read(&file[..file.len() / 2]).is_err());
+        assert!(read(&file[1..]).is_err());
+
+        // Without its restart marker the second MCU can't be found.
+        let marker: usize = (2..file.len() - 1).find(|i: &usize| -> bool { file[*i] == 0xFF && file[*i + 1] == RST0 }).unwrap();
+        let mut no_restart: Vec<u8> = file.clone();
+        no_restart.drain(marker..marker + 2);
+        assert!(read(&no_restart).is_err());
+
+        // The same frame marked progressive.
+        let frame: usize = (2..file.len() - 1).find(|i: &usize| -> bool { file[*i] == 0xFF && file[*i + 1] == SOF0 }).unwrap();
+        let mut progressive: Vec<u8> = file.clone();
+        progressive[frame + 1] = 0xC2;
+        assert!(read(&progressive).is_err());
+    }
+
+    #[test]
+    fn idct_of_dc_is_flat() {
+        let mut coefficients: [i32; 64] = [0; 64];
+        coefficients[0] = 80;
+        let mut out: [u8; 64] = [0; 64];
+        idct(&coefficients, &idct_table(), &mut out);
+        assert!(out.iter().all(|s: &u8| -> bool { *s == 138 }));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::common::{degrees_to_radians, saturate};
+use crate::vec3::*;
+
+// Lights without area, only reachable through direct light sampling.
+pub trait Light : Send {
+    // Direction from `p` towards the light, distance to it and the radiance arriving at `p`.
+    fn sample_li(&self, p: &Point3) -> (Vec3, f64, Color);
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct PointLight {
+    pub position: Point3,
+    pub intensity: Color,
+    // Distance at which the light smoothly fades to zero, zero means inverse square falloff only.
+    pub range: f64
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct SpotLight {
+    pub position: Point3,
+    pub direction: Vec3,
+    pub intensity: Color,
+    pub range: f64,
+    cos_inner: f64,
+    cos_outer: f64
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct DirectionalLight {
+    // Direction the light travels in.
+    pub direction: Vec3,
+    pub irradiance: Color
+}
+
+// Inverse square falloff, windowed to reach zero at `range`.
+fn distance_falloff(distance_squared: f64, range: f64) -> f64 {
+    let inverse_square: f64 = 1.0 / distance_squared.max(1e-8);
+    if range <= 0.0 {
+        return inverse_square;
+    }
+    let ratio: f64 = distance_squared / (range * range);
+    let window: f64 = saturate(1.0 - ratio * ratio);
+    inverse_square * window * window
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> PointLight {
+        PointLight {
+            position: position,
+            intensity: intensity,
+            range: 0.0
+        }
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, p: &Point3) -> (Vec3, f64, Color) {
+        let to_light: Vec3 = self.position - *p;
+        let distance_squared: f64 = to_light.length_squared();
+        let distance: f64 = distance_squared.sqrt();
+        (to_light / distance, distance, self.intensity * distance_falloff(distance_squared, self.range))
+    }
+}
+
+impl SpotLight {
+    // Full intensity inside `inner_angle`, fading out smoothly by `outer_angle`, both half angles in degrees.
+    pub fn new(position: Point3, target: Point3, intensity: Color, inner_angle: f64, outer_angle: f64) -> SpotLight {
+        let outer: f64 = outer_angle.max(inner_angle);
+        SpotLight {
+            position: position,
+            direction: normalize(target - position),
+            intensity: intensity,
+            range: 0.0,
+            cos_inner: degrees_to_radians(inner_angle).cos(),
+            cos_outer: degrees_to_radians(outer).cos()
+        }
+    }
+
+    fn cone_falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.0;
+        }
+        let t: f64 = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, p: &Point3) -> (Vec3, f64, Color) {
+        let to_light: Vec3 = self.position - *p;
+        let distance_squared: f64 = to_light.length_squared();
+        let distance: f64 = distance_squared.sqrt();
+        let wi: Vec3 = to_light / distance;
+        let cone: f64 = self.cone_falloff(dot(&-wi, &self.direction));
+        (wi, distance, self.intensity * (cone * distance_falloff(distance_squared, self.range)))
+    }
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Color) -> DirectionalLight {
+        DirectionalLight {
+            direction: normalize(direction),
+            irradiance: irradiance
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _p: &Point3) -> (Vec3, f64, Color) {
+        (-self.direction, f64::INFINITY, self.irradiance)
+    }
+}
@@ -1,15 +1,27 @@
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::upper_case_acronyms, clippy::manual_range_contains)]
+
 use std::{thread, sync::Arc};
 use camera::Camera;
+use color_space::ColorSpace;
+use environment::EnvironmentMap;
+use sky::PreethamSky;
 use clap::Parser;
 use clap_num::number_range;
 
 use hittable::Hittable;
+use texture_cache::{texture_cache, TextureCacheOptions};
+use tga::TgaOptions;
+use tonemap::{ToneMapper, ToneMapping};
 use vec3::Point3;
 
 use crate::{hittable_list::HittableList, vec3::Color};
 
 
 mod tga;
+mod image;
+mod inflate;
+mod jpeg;
+mod png;
 mod vec3;
 mod ray;
 mod sphere;
@@ -24,10 +36,39 @@ mod bvh;
 mod interval;
 mod quad;
 mod texture;
+mod texture_cache;
+mod noise;
+mod procedural;
+mod texture_graph;
+mod mtl;
+mod projection;
+mod mat3;
+mod tonemap;
+mod hdr;
+mod distribution;
+mod environment;
+mod sky;
+mod light;
+mod onb;
+mod microfacet;
+mod spectrum;
+mod medium;
+mod thin_film;
+mod color_space;
 
 
 fn demo_scene_range(s: &str) -> Result<i32, String> {
-    number_range(s, 0, 2)
+    number_range(s, 0, 18)
+}
+
+fn month_day(s: &str) -> Result<u32, String> {
+    let parts: Vec<&str> = s.split('-').collect();
+    if parts.len() != 2 {
+        return Err(String::from("Expected a date as MM-DD."));
+    }
+    let month: u32 = number_range(parts[0], 1, 12)?;
+    let day: u32 = number_range(parts[1], 1, 31)?;
+    Ok(sky::day_of_year(month, day))
 }
 
 fn thread_range(s: &str) -> Result<usize, String> {
@@ -40,7 +81,13 @@ struct Args{
     #[arg(short, long, long_help="Output image path.  Only TGA output is supported.", default_value="output/image.tga")]
     output: std::path::PathBuf,
 
-    #[arg(short, long, long_help="Demo scene to render:\n\t0 = Random Spheres\n\t1 = Cornell Box.\n\t2 = Qauds", value_parser=demo_scene_range, default_value_t=1)]
+    #[arg(long, long_help="Run length encode the output image.")]
+    rle: bool,
+
+    #[arg(long, long_help="Write an alpha channel holding the fraction of camera rays that hit geometry.")]
+    alpha: bool,
+
+    #[arg(short, long, long_help="Demo scene to render:\n\t0 = Random Spheres\n\t1 = Cornell Box.\n\t2 = Qauds\n\t3 = Point, Spot and Directional Lights\n\t4 = Textured and Two Sided Emitters\n\t5 = White Furnace\n\t6 = Rough and Tinted Dielectrics\n\t7 = Dispersion\n\t8 = Principled BSDF\n\t9 = Mixed and Coated Materials\n\t10 = Normal and Bump Mapping\n\t11 = Alpha Cutouts\n\t12 = Subsurface Scattering\n\t13 = Thin Film Interference\n\t14 = Texture Filtering\n\t15 = Procedural Textures\n\t16 = Texture Graphs\n\t17 = Projection Mapping\n\t18 = Planar Shapes", value_parser=demo_scene_range, default_value_t=1)]
     demo_scene: i32,
 
     #[arg(long, long_help="Output image width.", default_value_t=1920)]
@@ -57,6 +104,80 @@ struct Args{
 
     #[arg(short, long, long_help="Max number of threads. 1 means disable threading.", value_parser=thread_range, default_value_t=thread::available_parallelism().unwrap().get())]
     threads: usize,
+
+    #[arg(long, long_help="Trace sampled wavelengths instead of RGB.  Slower, but needed for dispersion.")]
+    spectral: bool,
+
+    #[arg(long, long_help="Tone mapping operator applied to the linear image before output encoding.", value_enum, default_value_t=ToneMapper::Clip)]
+    tonemap: ToneMapper,
+
+    #[arg(long, long_help="Linear colour space rendering happens in.  Scene colours are taken to be in it, textures are converted to it.  Only the primaries matter, sRGB and Rec.709 are the same here.", value_enum, default_value_t=ColorSpace::Rec709)]
+    working_space: ColorSpace,
+
+    #[arg(long, long_help="Colour space the output image is encoded in.", value_enum, default_value_t=ColorSpace::Srgb)]
+    output_space: ColorSpace,
+
+    #[arg(long, long_help="Megabytes of image texture data to keep in memory.  Least recently used tiles are dropped past it and rebuilt from their files when needed, keeping the decoded file while it fits.  Unlimited when not set.")]
+    texture_memory: Option<usize>,
+
+    #[arg(long, long_help="Store image textures as 16-bit floats, halving their memory.")]
+    half_textures: bool,
+
+    #[arg(short, long, long_help="Exposure adjustment in EV stops.", allow_negative_numbers=true, default_value_t=0.0)]
+    exposure: f64,
+
+    #[arg(long, long_help="Luminance mapped to white by the extended Reinhard and Hable operators.", default_value_t=4.0)]
+    white_point: f64,
+
+    #[arg(long, long_help="Colour temperature of the scene lighting in Kelvin, neutralized to 6500K.  Disabled by default.")]
+    white_balance: Option<f64>,
+
+    #[arg(long, long_help="Equirectangular Radiance HDR (.hdr) image lighting the scene.  Rays that miss use the scene background when not set.")]
+    environment: Option<std::path::PathBuf>,
+
+    #[arg(long, long_help="Rotation of the environment map around the up axis, in degrees.", allow_negative_numbers=true, default_value_t=0.0)]
+    environment_rotation: f64,
+
+    #[arg(long, long_help="Environment map intensity multiplier.", default_value_t=1.0)]
+    environment_intensity: f64,
+
+    #[arg(long, long_help="Light the scene with a Preetham daylight sky and sun.  Ignored when an environment map is set.")]
+    sky: bool,
+
+    #[arg(long, long_help="Sun elevation above the horizon in degrees.", allow_negative_numbers=true, default_value_t=45.0)]
+    sun_elevation: f64,
+
+    #[arg(long, long_help="Sun azimuth in degrees, clockwise from north.  North is -Z and east is +X.", allow_negative_numbers=true, default_value_t=135.0)]
+    sun_azimuth: f64,
+
+    #[arg(long, long_help="Atmospheric turbidity, 2 is very clear and 10 is hazy.", default_value_t=3.0)]
+    turbidity: f64,
+
+    #[arg(long, long_help="Latitude in degrees.  When set, the sun position is computed from --sun-date and --solar-time instead of --sun-elevation and --sun-azimuth.", allow_negative_numbers=true)]
+    latitude: Option<f64>,
+
+    #[arg(long, long_help="Date used to compute the sun position, as MM-DD.", value_parser=month_day, default_value="06-21")]
+    sun_date: u32,
+
+    #[arg(long, long_help="Local solar time in hours used to compute the sun position.", default_value_t=12.0)]
+    solar_time: f64,
+}
+
+fn create_sky(args: &Args) -> PreethamSky {
+    let (elevation, azimuth) = match args.latitude {
+        Some(latitude) => sky::sun_position(args.sun_date, args.solar_time, latitude),
+        None => (args.sun_elevation, args.sun_azimuth)
+    };
+    println!("Sun elevation: {:.2}, azimuth: {:.2}", elevation, azimuth);
+    PreethamSky::from_angles(elevation, azimuth, args.turbidity)
+}
+
+fn tone_mapping(args: &Args) -> ToneMapping {
+    let mut tm: ToneMapping = ToneMapping::new(args.tonemap, args.exposure, args.white_point);
+    if let Some(kelvin) = args.white_balance {
+        tm.set_white_balance(kelvin);
+    }
+    tm
 }
 
 fn create_random_world(args: &Args) -> (HittableList, Camera) {
@@ -68,6 +189,8 @@ fn create_random_world(args: &Args) -> (HittableList, Camera) {
     cam.image_height= args.height;
     cam.samples_per_pixel = args.spp;
     cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
     cam.background = Color::new(0.7, 0.8, 1.0);
     cam.initialize();
     (world, cam)
@@ -85,6 +208,8 @@ fn create_cornell_box(args: &Args) -> (HittableList, Camera) {
     cam.image_height= args.height;
     cam.samples_per_pixel = args.spp;
     cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
     cam.background = Color::new(0.7, 0.8, 1.0) * 0.5;
     cam.initialize();
     (world, cam)
@@ -101,11 +226,317 @@ fn create_quads(args: &Args) -> (HittableList, Camera) {
     cam.image_height= args.height;
     cam.samples_per_pixel = args.spp;
     cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::new(0.7, 0.8, 1.0);
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_lights(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up lights scene.");
+    let world: HittableList = world::lights();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 3.0, 10.0);
+    cam.target = Point3::new(0.0, 1.0, 0.0);
+    cam.vfov = 40.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::new(0.02, 0.02, 0.03);
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_emitters(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up emitters scene.");
+    let world: HittableList = world::emitters();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 3.0, 12.0);
+    cam.target = Point3::new(0.0, 2.5, 0.0);
+    cam.vfov = 45.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::black();
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_white_furnace(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up white furnace.");
+    let world: HittableList = world::white_furnace();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 0.0, 30.0);
+    cam.target = Point3::new(0.0, -1.0, 0.0);
+    cam.vfov = 22.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::white();
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_rough_dielectrics(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up rough dielectrics.");
+    let world: HittableList = world::rough_dielectrics();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 3.0, 14.0);
+    cam.target = Point3::new(0.0, 1.0, 0.0);
+    cam.vfov = 35.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
     cam.background = Color::new(0.7, 0.8, 1.0);
     cam.initialize();
     (world, cam)
 }
 
+fn create_dispersion(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up dispersion scene.");
+    let world: HittableList = world::dispersion();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 2.5, 11.0);
+    cam.target = Point3::new(0.0, 1.0, 0.0);
+    cam.vfov = 40.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::black();
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_principled(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up principled BSDF scene.");
+    let world: HittableList = world::principled();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 6.0, 14.0);
+    cam.target = Point3::new(0.0, 0.5, 0.0);
+    cam.vfov = 35.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::new(0.7, 0.8, 1.0) * 0.3;
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_layered(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up mixed and coated materials.");
+    let world: HittableList = world::layered();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 4.0, 12.0);
+    cam.target = Point3::new(0.0, 0.8, 0.0);
+    cam.vfov = 35.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::new(0.7, 0.8, 1.0) * 0.3;
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_normal_maps(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up normal and bump mapping.");
+    let world: HittableList = world::normal_maps();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 4.0, 12.0);
+    cam.target = Point3::new(0.0, 0.8, 0.0);
+    cam.vfov = 30.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::new(0.7, 0.8, 1.0) * 0.3;
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_cutouts(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up alpha cutouts.");
+    let world: HittableList = world::cutouts();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 4.0, 12.0);
+    cam.target = Point3::new(0.0, 0.8, 0.0);
+    cam.vfov = 35.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::new(0.7, 0.8, 1.0) * 0.3;
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_subsurface(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up subsurface scattering.");
+    let world: HittableList = world::subsurface();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 4.0, 12.0);
+    cam.target = Point3::new(0.0, 0.8, 0.0);
+    cam.vfov = 40.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::new(0.7, 0.8, 1.0) * 0.3;
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_thin_films(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up thin film interference.");
+    let world: HittableList = world::thin_films();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 4.0, 12.0);
+    cam.target = Point3::new(0.0, 0.8, 0.0);
+    cam.vfov = 35.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::new(0.7, 0.8, 1.0) * 0.3;
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_texture_filtering(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up texture filtering.");
+    let world: HittableList = world::texture_filtering();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 1.5, 8.0);
+    cam.target = Point3::new(0.0, 0.8, 0.0);
+    cam.vfov = 40.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::new(0.7, 0.8, 1.0) * 0.3;
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_procedural_textures(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up procedural textures.");
+    let world: HittableList = world::procedural_textures();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 2.0, 9.0);
+    cam.target = Point3::new(0.0, 0.8, 0.0);
+    cam.vfov = 35.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::new(0.7, 0.8, 1.0) * 0.3;
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_texture_graphs(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up texture graphs.");
+    let world: HittableList = world::texture_graphs();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 2.5, 8.0);
+    cam.target = Point3::new(0.0, 0.9, 0.0);
+    cam.vfov = 35.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::new(0.7, 0.8, 1.0) * 0.3;
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_projection_mapping(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up projection mapping.");
+    let world: HittableList = world::projection_mapping();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 3.0, 9.0);
+    cam.target = Point3::new(0.0, 0.8, 0.0);
+    cam.vfov = 40.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::new(0.7, 0.8, 1.0) * 0.3;
+    cam.initialize();
+    (world, cam)
+}
+
+fn create_planar_shapes(args: &Args) -> (HittableList, Camera) {
+    println!("Setting up planar shapes.");
+    let world: HittableList = world::planar_shapes();
+    let mut cam: Camera = Camera::new();
+    cam.origin = Point3::new(0.0, 2.0, 9.0);
+    cam.target = Point3::new(0.0, 0.9, 0.0);
+    cam.vfov = 35.0;
+    cam.defocus_angle = 0.0;
+    cam.image_width = args.width;
+    cam.image_height= args.height;
+    cam.samples_per_pixel = args.spp;
+    cam.max_depth = args.max_depth;
+    cam.tone_mapping = tone_mapping(args);
+    cam.output_space = args.output_space;
+    cam.background = Color::new(0.7, 0.8, 1.0) * 0.3;
+    cam.initialize();
+    (world, cam)
+}
+
 fn error_world() -> (HittableList, Camera) {
     let world: HittableList = HittableList{..Default::default()};
     let cam: Camera = Camera::new();
@@ -117,22 +548,56 @@ fn main() -> Result<(), std::io::Error> {
 
     let args: Args = Args::parse();
 
-    let mut output_path: std::path::PathBuf = args.output.clone();
-    if !common::validate_path(&mut output_path) {
+    let output_path: std::path::PathBuf = args.output.clone();
+    if !common::validate_path(&output_path) {
         return Ok(());
     }
 
+    // Textures are converted to the working space as scenes load them.
+    color_space::set_working_space(args.working_space);
+    texture_cache::set_texture_cache_options(TextureCacheOptions {
+        memory_budget: args.texture_memory.map(|mb: usize| -> usize { mb * 1024 * 1024 }),
+        half: args.half_textures
+    });
+
     let world_cam: (HittableList, Camera) = match args.demo_scene { 
         0=> create_random_world(&args),
         1=> create_cornell_box(&args),
         2=> create_quads(&args),
+        3=> create_lights(&args),
+        4=> create_emitters(&args),
+        5=> create_white_furnace(&args),
+        6=> create_rough_dielectrics(&args),
+        7=> create_dispersion(&args),
+        8=> create_principled(&args),
+        9=> create_layered(&args),
+        10=> create_normal_maps(&args),
+        11=> create_cutouts(&args),
+        12=> create_subsurface(&args),
+        13=> create_thin_films(&args),
+        14=> create_texture_filtering(&args),
+        15=> create_procedural_textures(&args),
+        16=> create_texture_graphs(&args),
+        17=> create_projection_mapping(&args),
+        18=> create_planar_shapes(&args),
         _=> error_world() // This should never happen, the argument parser should always catch this.
     };
    
     // Camera
-    let cam: Camera = world_cam.1;    
+    let mut cam: Camera = world_cam.1;    
+    cam.lights = world_cam.0.collect_lights();
+    cam.spectral = args.spectral;
+    cam.tga_options = TgaOptions { rle: args.rle, alpha: args.alpha };
+    if let Some(path) = &args.environment {
+        println!("Loading environment map: {}", path.display());
+        cam.environment = Some(Arc::new(EnvironmentMap::new(path, args.environment_rotation, args.environment_intensity)?));
+    } else if args.sky {
+        cam.environment = Some(Arc::new(create_sky(&args)));
+    }
     let world_arc: Arc<HittableList> = Arc::new(world_cam.0);
     
-    cam.render(&(world_arc as Arc<dyn Hittable + Sync>), args.threads, output_path)
+    cam.render(&(world_arc as Arc<dyn Hittable + Sync>), args.threads, output_path)?;
+    texture_cache().print_stats();
+    Ok(())
 
 }
@@ -0,0 +1,94 @@
+use std::{ops, fmt};
+
+use crate::vec3::*;
+
+// Row major 3x3 matrix, mostly used for colour space conversions.
+#[derive(Copy, Clone)]
+pub struct Mat3 {
+    m: [[f64; 3]; 3]
+}
+
+impl Default for Mat3 {
+    fn default() -> Mat3 {
+        Mat3::identity()
+    }
+}
+
+impl Mat3 {
+
+    pub fn new(m: [[f64; 3]; 3]) -> Mat3 {
+        Mat3 { m: m }
+    }
+
+    pub fn identity() -> Mat3 {
+        Mat3::diagonal(&Vec3::one())
+    }
+
+    pub fn diagonal(d: &Vec3) -> Mat3 {
+        Mat3 { m: [
+            [d.x(), 0.0, 0.0],
+            [0.0, d.y(), 0.0],
+            [0.0, 0.0, d.z()]
+        ]}
+    }
+
+    pub fn determinant(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+      - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+      + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn inverse(&self) -> Mat3 {
+        let m = &self.m;
+        let inv_det: f64 = 1.0 / self.determinant();
+        Mat3 { m: [
+            [
+                (m[1][1] * m[2][2] - m[1][2] * m[2][1]) * inv_det,
+                (m[0][2] * m[2][1] - m[0][1] * m[2][2]) * inv_det,
+                (m[0][1] * m[1][2] - m[0][2] * m[1][1]) * inv_det
+            ],
+            [
+                (m[1][2] * m[2][0] - m[1][0] * m[2][2]) * inv_det,
+                (m[0][0] * m[2][2] - m[0][2] * m[2][0]) * inv_det,
+                (m[0][2] * m[1][0] - m[0][0] * m[1][2]) * inv_det
+            ],
+            [
+                (m[1][0] * m[2][1] - m[1][1] * m[2][0]) * inv_det,
+                (m[0][1] * m[2][0] - m[0][0] * m[2][1]) * inv_det,
+                (m[0][0] * m[1][1] - m[0][1] * m[1][0]) * inv_det
+            ]
+        ]}
+    }
+}
+
+impl ops::Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+    fn mul(self, rhs: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * rhs.x() + m[0][1] * rhs.y() + m[0][2] * rhs.z(),
+            m[1][0] * rhs.x() + m[1][1] * rhs.y() + m[1][2] * rhs.z(),
+            m[2][0] * rhs.x() + m[2][1] * rhs.y() + m[2][2] * rhs.z()
+        )
+    }
+}
+
+impl ops::Mul<Mat3> for Mat3 {
+    type Output = Mat3;
+    fn mul(self, rhs: Mat3) -> Mat3 {
+        let mut out: [[f64; 3]; 3] = [[0.0; 3]; 3];
+        for (r, row) in out.iter_mut().enumerate() {
+            for (c, v) in row.iter_mut().enumerate() {
+                *v = self.m[r][0] * rhs.m[0][c] + self.m[r][1] * rhs.m[1][c] + self.m[r][2] * rhs.m[2][c];
+            }
+        }
+        Mat3 { m: out }
+    }
+}
+
+impl fmt::Display for Mat3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Mat3({:?}, {:?}, {:?})", self.m[0], self.m[1], self.m[2])
+    }
+}
@@ -1,17 +1,56 @@
+use std::f64::consts::PI;
 use std::sync::Arc;
 
 use rand::Rng;
 
 use crate::common::*;
 use crate::hittable::*;
+use crate::medium::Medium;
+use crate::microfacet::*;
+use crate::onb::ONB;
 use crate::ray::*;
-use crate::texture::SolidColorTexture;
+use crate::spectrum::Dispersion;
+use crate::thin_film::{ThinFilm, channel_at_wavelength};
+use crate::texture::{SolidColorTexture, solid_color, solid_value};
 use crate::texture::Texture;
 use crate::vec3::*;
 
 pub trait Material : Send {
     fn scatter(&self, ray_in: &Ray, rec: &HitRecord, color: &mut Color, scattered: &mut Ray) -> bool;
-    fn emitted(&self, rec: &HitRecord) -> Color;
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord) -> Color;
+
+    // BSDF times cosine for light leaving along -ray_in after arriving from `direction`.
+    // Only needed by materials that can be sampled for direct lighting.
+    fn eval(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Color {
+        Color::black()
+    }
+
+    // Solid angle pdf of scatter() choosing `direction`.  Zero means the material is specular
+    // and direct light sampling is skipped.
+    fn scattering_pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    // Absorption coefficient inside the object, applied to rays transmitted through its front face.
+    fn absorption(&self) -> Color {
+        Color::black()
+    }
+
+    // Medium filling the object, entered like `absorption`.  Scattering media are random walked by the integrator.
+    fn medium(&self) -> Medium {
+        Medium::absorbing(self.absorption())
+    }
+
+    // True if scattering depends on `ray_in.wavelength`, spectral paths then follow only the hero wavelength.
+    fn dispersive(&self) -> bool {
+        false
+    }
+
+    // True if the hit should be ignored and the ray continue, as through the transparent parts of a leaf.
+    // Called by the primitives during intersection, so camera, shadow rays and the BVH all agree.
+    fn cutout(&self, _rec: &HitRecord) -> bool {
+        false
+    }
 }
 
 #[derive(Clone, Default)]
@@ -25,14 +64,149 @@ pub struct Metal {
     pub roughness: f64
 }
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone)]
+pub enum ConductorFresnel {
+    // Complex index of refraction per channel.
+    Complex { eta: Color, k: Color },
+    // Schlick's approximation from reflectance at normal incidence.
+    Schlick { f0: Color }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum ConductorPreset {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver
+}
+
+// Physically based metal using the GGX microfacet distribution.
+#[derive(Clone)]
+pub struct Conductor {
+    pub fresnel: ConductorFresnel,
+    pub distribution: TrowbridgeReitz,
+    // Oxide or coating layer whose interference replaces the bare Fresnel term.
+    pub thin_film: Option<ThinFilm>
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum DielectricPreset {
+    Ice,
+    Water,
+    Acrylic,
+    Glass,
+    Sapphire,
+    Diamond
+}
+
+#[derive(Clone, Default)]
 pub struct Dielectric {
-    pub ior: f64
+    pub ior: f64,
+    pub absorption: Color,
+    // Only used by the spectral integrator, RGB rendering always uses `ior`.
+    pub dispersion: Dispersion,
+    // Coating on the outside of the surface, for soap bubbles and anti-reflective lenses.
+    pub thin_film: Option<ThinFilm>
 }
 
+// Microfacet glass with GGX reflection and transmission, Walter et al. 2007.
 #[derive(Copy, Clone, Default)]
+pub struct RoughDielectric {
+    pub ior: f64,
+    pub distribution: TrowbridgeReitz,
+    pub absorption: Color
+}
+
+// Subsurface scattering by random walk inside a closed object.  The medium is derived from the desired
+// surface colour and the mean free path per channel.  Light crosses the boundary diffusely, as in
+// Cycles, which keeps light sampling on the way out well behaved.  Wrap in `Coated` for a glossy surface.
+#[derive(Copy, Clone)]
+pub struct Subsurface {
+    pub albedo: Color,
+    // Average distance light travels between scattering events, per channel, in world units.
+    pub mean_free_path: Color,
+    // Henyey-Greenstein asymmetry of the phase function, in (-1, 1).
+    pub anisotropy: f64
+}
+
+// Disney style uber material, Burley 2012 and 2015.  Every parameter can be driven by a texture,
+// scalar parameters read the red channel.
+#[derive(Clone)]
+pub struct Principled {
+    pub base_color: Arc<dyn Texture + Sync>,
+    pub metallic: Arc<dyn Texture + Sync>,
+    pub roughness: Arc<dyn Texture + Sync>,
+    // Dielectric reflectance, 0.5 is 4% at normal incidence.
+    pub specular: Arc<dyn Texture + Sync>,
+    // Tints dielectric reflections towards the base colour.
+    pub specular_tint: Arc<dyn Texture + Sync>,
+    pub sheen: Arc<dyn Texture + Sync>,
+    pub clearcoat: Arc<dyn Texture + Sync>,
+    pub transmission: Arc<dyn Texture + Sync>,
+    pub emission: Option<Arc<dyn Texture + Sync>>,
+    pub emission_strength: f64,
+    pub clearcoat_roughness: f64,
+    pub ior: f64
+}
+
+// Stochastically picks `b` with probability `weight`, otherwise `a`.  The weight reads the red channel.  The pick
+// is made once per hit, so a component cut out by its alpha is never the one shading a hit that survived.
+#[derive(Clone)]
+pub struct MixMaterial {
+    pub a: Arc<dyn Material + Sync>,
+    pub b: Arc<dyn Material + Sync>,
+    pub weight: Arc<dyn Texture + Sync>
+}
+
+// A smooth dielectric coat over any base material.  Light reflects off the coat by Fresnel, the rest
+// reaches the base and loses the coat's Fresnel reflection again on the way out.
+#[derive(Clone)]
+pub struct Coated {
+    pub base: Arc<dyn Material + Sync>,
+    pub ior: f64
+}
+
+// Shades `base` with normals from a tangent space normal map, +y following dpdv as in OpenGL.
+#[derive(Clone)]
+pub struct NormalMapped {
+    pub base: Arc<dyn Material + Sync>,
+    pub map: Arc<dyn Texture + Sync>,
+    // Scales the tangent components, zero leaves the surface flat.
+    pub strength: f64
+}
+
+// Shades `base` with normals tilted by the slope of a height map, read from the red channel.
+#[derive(Clone)]
+pub struct BumpMapped {
+    pub base: Arc<dyn Material + Sync>,
+    pub height: Arc<dyn Texture + Sync>,
+    // Displacement in world units for a height of one.
+    pub scale: f64
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum AlphaMode {
+    // Cut out where alpha is below the threshold, crisp edges.
+    Threshold(f64),
+    // Cut out with probability 1 - alpha, converging to smooth partial coverage.
+    Stochastic
+}
+
+// Cuts holes into `base` where the mask texture's alpha channel is transparent.
+#[derive(Clone)]
+pub struct AlphaMasked {
+    pub base: Arc<dyn Material + Sync>,
+    pub mask: Arc<dyn Texture + Sync>,
+    pub mode: AlphaMode
+}
+
+#[derive(Clone, Default)]
 pub struct Emiter {
-    pub emission: Color
+    pub emission: Option<Arc<dyn Texture + Sync>>,
+    pub intensity: f64,
+    pub two_sided: bool,
+    cos_inner: f64,
+    cos_outer: f64
 }
 
 impl Lambertian {
@@ -49,6 +223,544 @@ impl Lambertian {
     }
 }
 
+// Keeps every principled lobe glossy rather than perfectly specular, so scattering always has a pdf.
+const PRINCIPLED_MIN_ROUGHNESS: f64 = 0.05;
+const PRINCIPLED_SHEEN_TINT: f64 = 0.5;
+
+fn texture_value(texture: &Arc<dyn Texture + Sync>, rec: &HitRecord) -> Color {
+    texture.value_at(rec)
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+// Principled parameters looked up at a hit point.
+struct PrincipledLobes {
+    base_color: Color,
+    roughness: f64,
+    sheen_color: Color,
+    specular_f0: Color,
+    clearcoat: f64,
+    diffuse_weight: f64,
+    specular_weight: f64,
+    transmission_weight: f64,
+    specular: TrowbridgeReitz,
+    coat: TrowbridgeReitz,
+    glass: RoughDielectric,
+    eta: f64,
+    // Probabilities of sampling the diffuse, specular, clearcoat and transmission lobes.
+    probabilities: [f64; 4]
+}
+
+impl PrincipledLobes {
+    // Returns f * cos and the pdf of sampling `wi` from the lobe mixture, in the local frame facing `wo`.
+    fn eval_pdf(&self, wo: &Vec3, wi: &Vec3) -> (Color, f64) {
+        if wo.z() <= 0.0 {
+            return (Color::black(), 0.0);
+        }
+        let mut f_cos: Color = Color::black();
+        let mut pdf: f64 = 0.0;
+
+        if wi.z() > 0.0 {
+            let wh: Vec3 = normalize(*wo + *wi);
+            let cos_d: f64 = dot(wi, &wh);
+
+            if self.diffuse_weight > 0.0 {
+                let fd90: f64 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+                let fl: f64 = 1.0 + (fd90 - 1.0) * schlick_weight(wi.z());
+                let fv: f64 = 1.0 + (fd90 - 1.0) * schlick_weight(wo.z());
+                let diffuse: Color = self.base_color * (fl * fv / PI);
+                let sheen: Color = self.sheen_color * schlick_weight(cos_d);
+                f_cos += (diffuse + sheen) * (self.diffuse_weight * wi.z());
+                pdf += self.probabilities[0] * wi.z() / PI;
+            }
+
+            if self.specular_weight > 0.0 {
+                let d: f64 = self.specular.d(&wh);
+                let g: f64 = self.specular.g(wo, wi);
+                f_cos += fresnel_schlick(cos_d, &self.specular_f0) * (self.specular_weight * d * g / (4.0 * wo.z()));
+                pdf += self.probabilities[1] * self.specular.d_visible(wo, &wh) / (4.0 * cos_d.abs());
+            }
+
+            if self.clearcoat > 0.0 {
+                let d: f64 = self.coat.d(&wh);
+                let g: f64 = self.coat.g(wo, wi);
+                let fresnel: f64 = 0.04 + 0.96 * schlick_weight(cos_d);
+                f_cos += Color::white() * (0.25 * self.clearcoat * fresnel * d * g / (4.0 * wo.z()));
+                pdf += self.probabilities[2] * self.coat.d_visible(wo, &wh) / (4.0 * cos_d.abs());
+            }
+        }
+
+        if self.transmission_weight > 0.0 {
+            let (glass_f_cos, glass_pdf) = self.glass.eval_pdf(wo, wi, self.eta);
+            let tint: Color = if wi.z() < 0.0 { self.base_color } else { Color::white() };
+            f_cos += tint * (self.transmission_weight * glass_f_cos);
+            pdf += self.probabilities[3] * glass_pdf;
+        }
+
+        (f_cos, pdf)
+    }
+
+    fn sample(&self, wo: &Vec3) -> Option<Vec3> {
+        let mut rng = rand::thread_rng();
+        let u: f64 = rng.gen();
+        let p: [f64; 4] = self.probabilities;
+        let wi: Vec3 = if u < p[0] {
+            normalize(random_unit_vector() + Vec3::new(0.0, 0.0, 1.0))
+        } else if u < p[0] + p[1] {
+            reflect(&-*wo, &self.specular.sample_wm(wo, rng.gen(), rng.gen()))
+        } else if u < p[0] + p[1] + p[2] {
+            reflect(&-*wo, &self.coat.sample_wm(wo, rng.gen(), rng.gen()))
+        } else {
+            self.glass.sample_local(wo, self.eta)?
+        };
+        if wi.near_zero() { None } else { Some(wi) }
+    }
+}
+
+impl Principled {
+    pub fn new(base_color: Color) -> Principled {
+        Principled::new_texture(&solid_color(base_color))
+    }
+
+    pub fn new_texture(base_color: &Arc<dyn Texture + Sync>) -> Principled {
+        Principled {
+            base_color: base_color.to_owned(),
+            metallic: solid_value(0.0),
+            roughness: solid_value(0.5),
+            specular: solid_value(0.5),
+            specular_tint: solid_value(0.0),
+            sheen: solid_value(0.0),
+            clearcoat: solid_value(0.0),
+            transmission: solid_value(0.0),
+            emission: None,
+            emission_strength: 1.0,
+            clearcoat_roughness: 0.1,
+            ior: 1.5
+        }
+    }
+
+    fn lobes(&self, rec: &HitRecord) -> PrincipledLobes {
+        let base_color: Color = texture_value(&self.base_color, rec);
+        let metallic: f64 = saturate(texture_value(&self.metallic, rec).r());
+        let roughness: f64 = texture_value(&self.roughness, rec).r().clamp(PRINCIPLED_MIN_ROUGHNESS, 1.0);
+        let specular: f64 = texture_value(&self.specular, rec).r().max(0.0);
+        let specular_tint: f64 = saturate(texture_value(&self.specular_tint, rec).r());
+        let sheen: f64 = texture_value(&self.sheen, rec).r().max(0.0);
+        let clearcoat: f64 = texture_value(&self.clearcoat, rec).r().max(0.0);
+        let transmission: f64 = saturate(texture_value(&self.transmission, rec).r());
+
+        // Hue of the base colour, with its luminance normalized out.
+        let luminance: f64 = base_color.luminance();
+        let tint: Color = if luminance > 0.0 { base_color / luminance } else { Color::white() };
+
+        let dielectric_f0: Color = lerp(&Color::white(), &tint, specular_tint) * (0.08 * specular);
+        let diffuse_weight: f64 = (1.0 - metallic) * (1.0 - transmission);
+        let transmission_weight: f64 = (1.0 - metallic) * transmission;
+        let specular_weight: f64 = 1.0 - transmission_weight;
+
+        let weights: [f64; 4] = [diffuse_weight, specular_weight, 0.25 * clearcoat, transmission_weight];
+        let total: f64 = weights.iter().sum();
+        let specular_distribution: TrowbridgeReitz = TrowbridgeReitz::from_roughness(roughness, 0.0);
+
+        PrincipledLobes {
+            base_color: base_color,
+            roughness: roughness,
+            sheen_color: lerp(&Color::white(), &tint, PRINCIPLED_SHEEN_TINT) * sheen,
+            specular_f0: lerp(&dielectric_f0, &base_color, metallic),
+            clearcoat: clearcoat,
+            diffuse_weight: diffuse_weight,
+            specular_weight: specular_weight,
+            transmission_weight: transmission_weight,
+            specular: specular_distribution,
+            coat: TrowbridgeReitz::from_roughness(self.clearcoat_roughness.max(PRINCIPLED_MIN_ROUGHNESS), 0.0),
+            glass: RoughDielectric { ior: self.ior, distribution: specular_distribution, absorption: Color::black() },
+            eta: if rec.front_face { self.ior } else { 1.0 / self.ior },
+            probabilities: weights.map(|w| w / total)
+        }
+    }
+}
+
+// Flags samples from a specular component, so layered materials get no MIS weight against light sampling.
+fn mark_specular(component: &Arc<dyn Material + Sync>, ray_in: &Ray, rec: &HitRecord, scattered: &mut Ray) {
+    if component.scattering_pdf(ray_in, rec, &scattered.direction) <= 0.0 {
+        scattered.specular = true;
+    }
+}
+
+impl MixMaterial {
+    pub fn new(a: &Arc<dyn Material + Sync>, b: &Arc<dyn Material + Sync>, weight: f64) -> MixMaterial {
+        MixMaterial::new_texture(a, b, &solid_value(weight))
+    }
+
+    pub fn new_texture(a: &Arc<dyn Material + Sync>, b: &Arc<dyn Material + Sync>, weight: &Arc<dyn Texture + Sync>) -> MixMaterial {
+        MixMaterial {
+            a: a.to_owned(),
+            b: b.to_owned(),
+            weight: weight.to_owned()
+        }
+    }
+
+    fn weight(&self, rec: &HitRecord) -> f64 {
+        saturate(texture_value(&self.weight, rec).r())
+    }
+
+    // The component this hit uses.  Every method picks the same one, the cutout test during intersection
+    // included, from a value hashed from the hit and this material rather than drawn afresh.
+    fn component(&self, rec: &HitRecord) -> &Arc<dyn Material + Sync> {
+        if hit_random(rec, self as *const MixMaterial as usize) < self.weight(rec) { &self.b } else { &self.a }
+    }
+}
+
+// Uniform value in [0, 1) fixed for one surface point and `salt`.  Uses the hit before instance transforms and
+// its texture coordinates, which the primitive and the integrator both see.
+fn hit_random(rec: &HitRecord, salt: usize) -> f64 {
+    // SplitMix64 finalizer.
+    let mix = |x: u64| -> u64 {
+        let mut z: u64 = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    };
+    let mut h: u64 = mix(salt as u64);
+    for x in [rec.object_p.x(), rec.object_p.y(), rec.object_p.z(), rec.uvw.x(), rec.uvw.y()] {
+        h = mix(h ^ x.to_bits());
+    }
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl Coated {
+    pub fn new(base: &Arc<dyn Material + Sync>, ior: f64) -> Coated {
+        Coated {
+            base: base.to_owned(),
+            ior: ior
+        }
+    }
+
+    fn fresnel(&self, rec: &HitRecord, direction: &Vec3) -> f64 {
+        fresnel_dielectric(dot(&rec.shading_normal, &normalize(*direction)).abs(), self.ior)
+    }
+}
+
+// Step in uv used to differentiate bump maps.
+const BUMP_DELTA: f64 = 0.001;
+
+// Replaces the shading normal, given facing out of the surface, keeping it on the side of `rec.normal`.
+fn with_shading_normal(rec: &HitRecord, outward: &Vec3) -> HitRecord {
+    let mut perturbed: HitRecord = rec.clone();
+    perturbed.shading_normal = if rec.front_face { normalize(*outward) } else { -normalize(*outward) };
+    perturbed
+}
+
+fn outward_normal(rec: &HitRecord) -> Vec3 {
+    if rec.front_face { rec.normal } else { -rec.normal }
+}
+
+impl NormalMapped {
+    pub fn new(base: &Arc<dyn Material + Sync>, map: &Arc<dyn Texture + Sync>, strength: f64) -> NormalMapped {
+        NormalMapped {
+            base: base.to_owned(),
+            map: map.to_owned(),
+            strength: strength
+        }
+    }
+
+    fn perturb(&self, rec: &HitRecord) -> HitRecord {
+        let n: Vec3 = outward_normal(rec);
+        let t: Vec3 = ONB::new_tangent(&n, &rec.dpdu).u;
+        let mut b: Vec3 = cross(&n, &t);
+        if dot(&b, &rec.dpdv) < 0.0 {
+            b = -b;
+        }
+        let m: Color = texture_value(&self.map, rec) * 2.0 - Color::white();
+        let local: Vec3 = Vec3::new(m.x() * self.strength, m.y() * self.strength, m.z().max(1e-3));
+        with_shading_normal(rec, &(t * local.x() + b * local.y() + n * local.z()))
+    }
+}
+
+impl BumpMapped {
+    pub fn new(base: &Arc<dyn Material + Sync>, height: &Arc<dyn Texture + Sync>, scale: f64) -> BumpMapped {
+        BumpMapped {
+            base: base.to_owned(),
+            height: height.to_owned(),
+            scale: scale
+        }
+    }
+
+    // Blinn 1978, the displaced surface p + h n differentiated ignoring the change in n.
+    fn perturb(&self, rec: &HitRecord) -> HitRecord {
+        let (u, v) = (rec.uvw.x(), rec.uvw.y());
+        // Step backwards at the far edge so the lookups stay within [0, 1].
+        let du: f64 = if u + BUMP_DELTA > 1.0 { -BUMP_DELTA } else { BUMP_DELTA };
+        let dv: f64 = if v + BUMP_DELTA > 1.0 { -BUMP_DELTA } else { BUMP_DELTA };
+        // The height at the hit moved a little along the surface, so textures looking at the position or
+        // projecting their own coordinates see the same step.
+        let offset = |step_u: f64, step_v: f64| -> f64 {
+            let mut moved: HitRecord = rec.clone();
+            moved.uvw = rec.uvw + Vec3::new(step_u, step_v, 0.0);
+            moved.p = rec.p + rec.dpdu * step_u + rec.dpdv * step_v;
+            moved.object_p = rec.object_p + rec.object_dpdu * step_u + rec.object_dpdv * step_v;
+            self.height.value_at(&moved).r()
+        };
+        let h: f64 = offset(0.0, 0.0);
+        let dhdu: f64 = (offset(du, 0.0) - h) / du * self.scale;
+        let dhdv: f64 = (offset(0.0, dv) - h) / dv * self.scale;
+
+        let n: Vec3 = outward_normal(rec);
+        let dpdu: Vec3 = rec.dpdu + n * dhdu;
+        let dpdv: Vec3 = rec.dpdv + n * dhdv;
+        let mut bumped: Vec3 = cross(&dpdu, &dpdv);
+        if bumped.near_zero() {
+            return rec.clone();
+        }
+        if dot(&bumped, &n) < 0.0 {
+            bumped = -bumped;
+        }
+        with_shading_normal(rec, &bumped)
+    }
+}
+
+impl AlphaMasked {
+    pub fn new(base: &Arc<dyn Material + Sync>, mask: &Arc<dyn Texture + Sync>, mode: AlphaMode) -> AlphaMasked {
+        AlphaMasked {
+            base: base.to_owned(),
+            mask: mask.to_owned(),
+            mode: mode
+        }
+    }
+}
+
+impl Emiter {
+    pub fn new(emission: Color) -> Emiter {
+        Emiter::new_texture(&(Arc::new(SolidColorTexture{color: emission}) as Arc<dyn Texture + Sync>), 1.0)
+    }
+
+    pub fn new_texture(emission: &Arc<dyn Texture + Sync>, intensity: f64) -> Emiter {
+        Emiter {
+            emission: Some(emission.to_owned()),
+            intensity: intensity,
+            two_sided: false,
+            cos_inner: 0.0,
+            cos_outer: 0.0
+        }
+    }
+
+    // Limits emission to a cone around the normal, full strength inside `inner_angle` and fading
+    // out smoothly by `outer_angle`.  Both are half angles in degrees.
+    pub fn set_cone(&mut self, inner_angle: f64, outer_angle: f64) {
+        self.cos_inner = degrees_to_radians(inner_angle.clamp(0.0, 90.0)).cos();
+        self.cos_outer = degrees_to_radians(outer_angle.clamp(inner_angle, 90.0)).cos();
+    }
+
+    fn cone_falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_inner {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_outer {
+            return 0.0;
+        }
+        let t: f64 = (cos_theta - self.cos_outer) / (self.cos_inner - self.cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl ConductorPreset {
+    // RGB samples of measured eta and k at 650nm, 550nm and 450nm.
+    pub fn eta_k(&self) -> (Color, Color) {
+        match self {
+            ConductorPreset::Gold => (Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603)),
+            ConductorPreset::Copper => (Color::new(0.200, 0.924, 1.102), Color::new(3.912, 2.452, 2.142)),
+            ConductorPreset::Aluminium => (Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837)),
+            ConductorPreset::Silver => (Color::new(0.155, 0.117, 0.138), Color::new(4.828, 3.122, 2.147))
+        }
+    }
+}
+
+impl DielectricPreset {
+    // Index of refraction at the sodium D line, 589nm.
+    pub fn ior(&self) -> f64 {
+        match self {
+            DielectricPreset::Ice => 1.31,
+            DielectricPreset::Water => 1.333,
+            DielectricPreset::Acrylic => 1.49,
+            DielectricPreset::Glass => 1.5,
+            DielectricPreset::Sapphire => 1.77,
+            DielectricPreset::Diamond => 2.417
+        }
+    }
+}
+
+// Absorption coefficient that leaves `color` after travelling `distance` through the medium.
+pub fn absorption_from_transmission(color: &Color, distance: f64) -> Color {
+    let mut absorption: Color = Color::black();
+    for i in 0..3 {
+        absorption[i] = -color[i].clamp(1e-6, 1.0).ln() / distance.max(1e-6);
+    }
+    absorption
+}
+
+impl Dielectric {
+    pub fn new(ior: f64) -> Dielectric {
+        Dielectric { ior: ior, absorption: Color::black(), dispersion: Dispersion::None, thin_film: None }
+    }
+
+    // Glass whose index follows `dispersion`, `ior` is what RGB rendering uses instead.
+    pub fn new_dispersive(ior: f64, dispersion: Dispersion) -> Dielectric {
+        Dielectric { ior: ior, absorption: Color::black(), dispersion: dispersion, thin_film: None }
+    }
+
+    pub fn set_transmission_color(&mut self, color: Color, distance: f64) {
+        self.absorption = absorption_from_transmission(&color, distance);
+    }
+
+    pub fn new_preset(preset: DielectricPreset) -> Dielectric {
+        Dielectric::new(preset.ior())
+    }
+}
+
+impl RoughDielectric {
+    pub fn new(ior: f64, roughness: f64) -> RoughDielectric {
+        RoughDielectric {
+            ior: ior,
+            distribution: TrowbridgeReitz::from_roughness(roughness, 0.0),
+            absorption: Color::black()
+        }
+    }
+
+    pub fn set_transmission_color(&mut self, color: Color, distance: f64) {
+        self.absorption = absorption_from_transmission(&color, distance);
+    }
+
+    pub fn new_preset(preset: DielectricPreset, roughness: f64) -> RoughDielectric {
+        RoughDielectric::new(preset.ior(), roughness)
+    }
+
+    // Relative IOR across the surface for a ray arriving on the side `rec.normal` points to.
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face { self.ior } else { 1.0 / self.ior }
+    }
+
+    // Samples a reflected or transmitted direction in the local frame around the normal facing `wo`.
+    fn sample_local(&self, wo: &Vec3, eta: f64) -> Option<Vec3> {
+        let mut rng = rand::thread_rng();
+        let smooth: bool = self.distribution.effectively_smooth();
+        let wm: Vec3 = if smooth { Vec3::new(0.0, 0.0, 1.0) } else { self.distribution.sample_wm(wo, rng.gen(), rng.gen()) };
+
+        // Pick reflection or transmission proportionally to Fresnel, so it cancels from the weight.
+        let cos_o: f64 = dot(wo, &wm);
+        let r: f64 = fresnel_dielectric(cos_o, eta);
+        if rng.gen::<f64>() < r {
+            let wi: Vec3 = reflect(&-*wo, &wm);
+            if wi.z() <= 0.0 { None } else { Some(wi) }
+        } else {
+            let wi: Vec3 = refract(&-*wo, &wm, 1.0 / eta);
+            if wi.z() >= 0.0 { None } else { Some(wi) }
+        }
+    }
+
+    // Returns f * cos and the pdf of sampling `wi`, both in the local frame around the normal facing `wo`.
+    fn eval_pdf(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> (f64, f64) {
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let reflect: bool = wi.z() > 0.0;
+        let mut wm: Vec3 = if reflect { *wi + *wo } else { *wi * eta + *wo };
+        if wm.near_zero() {
+            return (0.0, 0.0);
+        }
+        wm = normalize(wm);
+        if wm.z() < 0.0 {
+            wm = -wm;
+        }
+
+        // Discard back facing microfacets.
+        if dot(&wm, wi) * wi.z() < 0.0 || dot(&wm, wo) * wo.z() < 0.0 {
+            return (0.0, 0.0);
+        }
+
+        let r: f64 = fresnel_dielectric(dot(wo, &wm), eta);
+        let t: f64 = 1.0 - r;
+        let d: f64 = self.distribution.d(&wm);
+        let g: f64 = self.distribution.g(wo, wi);
+
+        if reflect {
+            let f_cos: f64 = r * d * g / (4.0 * wo.z());
+            let pdf: f64 = r * self.distribution.d_visible(wo, &wm) / (4.0 * dot(wo, &wm).abs());
+            (f_cos, pdf)
+        } else {
+            let denom: f64 = dot(wi, &wm) + dot(wo, &wm) / eta;
+            let denom2: f64 = denom * denom;
+            if denom2 <= 0.0 {
+                return (0.0, 0.0);
+            }
+            // The 1/eta^2 radiance scaling is left out, it cancels for rays entering and leaving
+            // closed objects, matching the smooth Dielectric.
+            let f_cos: f64 = t * d * g * (dot(wi, &wm) * dot(wo, &wm)).abs() / (wo.z() * denom2);
+            let pdf: f64 = t * self.distribution.d_visible(wo, &wm) * dot(wi, &wm).abs() / denom2;
+            (f_cos, pdf)
+        }
+    }
+}
+
+impl Subsurface {
+    pub fn new(albedo: Color, mean_free_path: Color, anisotropy: f64) -> Subsurface {
+        Subsurface {
+            albedo: albedo,
+            mean_free_path: mean_free_path,
+            anisotropy: anisotropy
+        }
+    }
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64, anisotropy: f64) -> Conductor {
+        Conductor {
+            fresnel: ConductorFresnel::Complex { eta: eta, k: k },
+            distribution: TrowbridgeReitz::from_roughness(roughness, anisotropy),
+            thin_film: None
+        }
+    }
+
+    pub fn new_preset(preset: ConductorPreset, roughness: f64, anisotropy: f64) -> Conductor {
+        let (eta, k) = preset.eta_k();
+        Conductor::new(eta, k, roughness, anisotropy)
+    }
+
+    pub fn new_reflectance(f0: Color, roughness: f64, anisotropy: f64) -> Conductor {
+        Conductor {
+            fresnel: ConductorFresnel::Schlick { f0: f0 },
+            distribution: TrowbridgeReitz::from_roughness(roughness, anisotropy),
+            thin_film: None
+        }
+    }
+
+    fn fresnel(&self, cos_theta: f64, ray_in: &Ray, rec: &HitRecord) -> Color {
+        let film: &ThinFilm = match &self.thin_film {
+            None => return self.bare_fresnel(cos_theta),
+            Some(film) => film
+        };
+        film.reflectance(rec, cos_theta, 1.0, ray_in.wavelength, |lambda: f64| -> (f64, f64) {
+            match &self.fresnel {
+                ConductorFresnel::Complex { eta, k } => (channel_at_wavelength(eta, lambda), channel_at_wavelength(k, lambda)),
+                // The real index with the same normal incidence reflectance.
+                ConductorFresnel::Schlick { f0 } => {
+                    let r: f64 = channel_at_wavelength(f0, lambda).clamp(0.0, 0.99).sqrt();
+                    ((1.0 + r) / (1.0 - r), 0.0)
+                }
+            }
+        })
+    }
+
+    fn bare_fresnel(&self, cos_theta: f64) -> Color {
+        match &self.fresnel {
+            ConductorFresnel::Complex { eta, k } => fresnel_conductor_color(cos_theta, eta, k),
+            ConductorFresnel::Schlick { f0 } => fresnel_schlick(cos_theta, f0)
+        }
+    }
+}
+
 impl Metal {
     pub fn new(albedo:Color, roughness:f64) -> Metal {
         Metal{albedo:albedo, roughness: saturate(roughness) }
@@ -57,38 +769,117 @@ impl Metal {
 
 impl Material for Lambertian {
     fn scatter(&self, ray_in: &Ray, rec: &HitRecord, color: &mut Color, scattered: &mut Ray) -> bool {
-        let mut scatter_direction: Vec3 = random_unit_vector() + rec.normal;
+        let mut scatter_direction: Vec3 = random_unit_vector() + rec.shading_normal;
 
         if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
+            scatter_direction = rec.shading_normal;
         }
 
         *scattered = Ray::new(rec.p, scatter_direction, ray_in.time);
-        *color = self.albedo.as_ref().unwrap().value(rec.uvw.x(), rec.uvw.y(), rec.uvw.z());
+        *color = texture_value(self.albedo.as_ref().unwrap(), rec);
 
         return true;
     }
 
-    fn emitted(&self, _rec: &HitRecord) -> Color {
+    fn emitted(&self, _ray_in: &Ray, _rec: &HitRecord) -> Color {
         Color::black()
     }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let pdf: f64 = self.scattering_pdf(ray_in, rec, direction);
+        if pdf <= 0.0 {
+            return Color::black();
+        }
+        texture_value(self.albedo.as_ref().unwrap(), rec) * pdf
+    }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let cos_theta: f64 = dot(&rec.shading_normal, &normalize(*direction));
+        if cos_theta <= 0.0 { 0.0 } else { cos_theta / PI }
+    }
 }
 
 impl Material for Metal {
     fn scatter(&self, ray_in: &Ray, rec: &HitRecord, color: &mut Color, scattered: &mut Ray) -> bool {
         
         let dir = normalize(ray_in.direction);
-        let reflected = reflect(&dir, &rec.normal);       
+        let reflected = reflect(&dir, &rec.shading_normal);       
 
         *scattered = Ray::new(rec.p, reflected + self.roughness * random_unit_vector(), ray_in.time);
         *color = self.albedo;
 
-        return dot(&scattered.direction, &rec.normal) > 0.0;
+        return dot(&scattered.direction, &rec.shading_normal) > 0.0;
+    }
+
+    fn emitted(&self, _ray_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::black()
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, color: &mut Color, scattered: &mut Ray) -> bool {
+        let frame: ONB = rec.shading_frame();
+        let wo: Vec3 = frame.world_to_local(&-normalize(ray_in.direction));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        if self.distribution.effectively_smooth() {
+            *scattered = Ray::new(rec.p, frame.local_to_world(&Vec3::new(-wo.x(), -wo.y(), wo.z())), ray_in.time);
+            *color = self.fresnel(wo.z(), ray_in, rec);
+            return true;
+        }
+
+        let mut rng = rand::thread_rng();
+        let wm: Vec3 = self.distribution.sample_wm(&wo, rng.gen(), rng.gen());
+        let wi: Vec3 = reflect(&-wo, &wm);
+        if wi.z() <= 0.0 {
+            return false;
+        }
+
+        // f * cos / pdf for visible normal sampling reduces to F * G / G1.
+        *scattered = Ray::new(rec.p, frame.local_to_world(&wi), ray_in.time);
+        *color = self.fresnel(dot(&wo, &wm), ray_in, rec) * (self.distribution.g(&wo, &wi) / self.distribution.g1(&wo));
+        return true;
     }
 
-    fn emitted(&self, _rec: &HitRecord) -> Color {
+    fn emitted(&self, _ray_in: &Ray, _rec: &HitRecord) -> Color {
         Color::black()
     }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        if self.distribution.effectively_smooth() {
+            return Color::black();
+        }
+        let frame: ONB = rec.shading_frame();
+        let wo: Vec3 = frame.world_to_local(&-normalize(ray_in.direction));
+        let wi: Vec3 = frame.world_to_local(&normalize(*direction));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::black();
+        }
+        let wm: Vec3 = normalize(wo + wi);
+        let d: f64 = self.distribution.d(&wm);
+        let g: f64 = self.distribution.g(&wo, &wi);
+        self.fresnel(dot(&wo, &wm), ray_in, rec) * (d * g / (4.0 * wo.z()))
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        if self.distribution.effectively_smooth() {
+            return 0.0;
+        }
+        let frame: ONB = rec.shading_frame();
+        let wo: Vec3 = frame.world_to_local(&-normalize(ray_in.direction));
+        let wi: Vec3 = frame.world_to_local(&normalize(*direction));
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let wm: Vec3 = normalize(wo + wi);
+        self.distribution.d_visible(&wo, &wm) / (4.0 * dot(&wo, &wm).abs())
+    }
+
+    fn dispersive(&self) -> bool {
+        self.thin_film.is_some()
+    }
 }
 
 fn reflectence(cos:f64, ref_idx:f64) -> f64{
@@ -102,28 +893,371 @@ impl Material for Dielectric {
 
         *color = Color::white();
 
-        let refraction_ratio: f64 = if rec.front_face {1.0/self.ior} else {self.ior};
+        let ior: f64 = self.dispersion.ior(self.ior, ray_in.wavelength);
+        let refraction_ratio: f64 = if rec.front_face {1.0/ior} else {ior};
 
         let dir: Vec3 = normalize(ray_in.direction);
 
-        let cos_theta: f64 = dot(&-dir, &rec.normal).min(1.0);
+        let cos_theta: f64 = dot(&-dir, &rec.shading_normal).min(1.0);
         let sin_theta: f64 = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract: bool = refraction_ratio * sin_theta > 1.0;
         let rnd_dbl: f64 = rand::thread_rng().gen();
 
-        let should_reflect: bool = cannot_refract || reflectence(cos_theta, refraction_ratio) > rnd_dbl;
+        let should_reflect: bool = match &self.thin_film {
+            None => cannot_refract || reflectence(cos_theta, refraction_ratio) > rnd_dbl,
+            Some(film) => {
+                // The film sits on the outside, between the surrounding air and the glass.
+                let (n_incident, n_substrate) = if rec.front_face { (1.0, ior) } else { (ior, 1.0) };
+                let r: Color = film.reflectance(rec, cos_theta, n_incident, ray_in.wavelength, |_lambda: f64| -> (f64, f64) { (n_substrate, 0.0) });
+                let p: f64 = r.average();
+                // Choose by the average reflectance and reweight each channel.
+                if cannot_refract || p >= 1.0 {
+                    true
+                } else if rnd_dbl < p {
+                    *color = r / p;
+                    true
+                } else {
+                    *color = (Color::white() - r) / (1.0 - p);
+                    false
+                }
+            }
+        };
 
-        let direction: Vec3 = if should_reflect { reflect(&dir, &rec.normal) } else { refract(&dir, &rec.normal, refraction_ratio) };
+        let direction: Vec3 = if should_reflect { reflect(&dir, &rec.shading_normal) } else { refract(&dir, &rec.shading_normal, refraction_ratio) };
 
         *scattered = Ray::new(rec.p, direction, ray_in.time);
         
         return true;
     }
 
-    fn emitted(&self, _rec: &HitRecord) -> Color {
+    fn emitted(&self, _ray_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::black()
+    }
+
+    fn absorption(&self) -> Color {
+        self.absorption
+    }
+
+    fn dispersive(&self) -> bool {
+        self.dispersion.is_dispersive() || self.thin_film.is_some()
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, color: &mut Color, scattered: &mut Ray) -> bool {
+        let frame: ONB = rec.shading_frame();
+        let wo: Vec3 = frame.world_to_local(&-normalize(ray_in.direction));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let wi: Vec3 = match self.sample_local(&wo, self.eta(rec)) {
+            None => return false,
+            Some(wi) => wi
+        };
+        let smooth: bool = self.distribution.effectively_smooth();
+
+        *scattered = Ray::new(rec.p, frame.local_to_world(&wi), ray_in.time);
+        *color = if smooth { Color::white() } else { Color::white() * (self.distribution.g(&wo, &wi) / self.distribution.g1(&wo)) };
+        return true;
+    }
+
+    fn emitted(&self, _ray_in: &Ray, _rec: &HitRecord) -> Color {
         Color::black()
     }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        if self.distribution.effectively_smooth() {
+            return Color::black();
+        }
+        let frame: ONB = rec.shading_frame();
+        let wo: Vec3 = frame.world_to_local(&-normalize(ray_in.direction));
+        let wi: Vec3 = frame.world_to_local(&normalize(*direction));
+        Color::white() * self.eval_pdf(&wo, &wi, self.eta(rec)).0
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        if self.distribution.effectively_smooth() {
+            return 0.0;
+        }
+        let frame: ONB = rec.shading_frame();
+        let wo: Vec3 = frame.world_to_local(&-normalize(ray_in.direction));
+        let wi: Vec3 = frame.world_to_local(&normalize(*direction));
+        self.eval_pdf(&wo, &wi, self.eta(rec)).1
+    }
+
+    fn absorption(&self) -> Color {
+        self.absorption
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, color: &mut Color, scattered: &mut Ray) -> bool {
+        // Cosine distributed transmission through the surface, the pdf cancels the cosine and 1/pi.
+        let mut direction: Vec3 = -(rec.shading_normal + random_unit_vector());
+        if direction.near_zero() || dot(&direction, &rec.normal) >= 0.0 {
+            direction = -rec.normal;
+        }
+
+        *scattered = Ray::new(rec.p, direction, ray_in.time);
+        *color = Color::white();
+        return true;
+    }
+
+    fn emitted(&self, _ray_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::black()
+    }
+
+    fn eval(&self, _ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let cos_theta: f64 = -dot(&rec.shading_normal, &normalize(*direction));
+        if cos_theta <= 0.0 { Color::black() } else { Color::white() * (cos_theta / PI) }
+    }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let cos_theta: f64 = -dot(&rec.shading_normal, &normalize(*direction));
+        if cos_theta <= 0.0 { 0.0 } else { cos_theta / PI }
+    }
+
+    fn medium(&self) -> Medium {
+        Medium::from_albedo(&self.albedo, &self.mean_free_path, self.anisotropy)
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, color: &mut Color, scattered: &mut Ray) -> bool {
+        let frame: ONB = rec.shading_frame();
+        let wo: Vec3 = frame.world_to_local(&-normalize(ray_in.direction));
+        if wo.z() <= 0.0 {
+            return false;
+        }
+
+        let lobes: PrincipledLobes = self.lobes(rec);
+        let wi: Vec3 = match lobes.sample(&wo) {
+            None => return false,
+            Some(wi) => normalize(wi)
+        };
+
+        // Weighting by the pdf of the whole mixture rather than the chosen lobe is one sample MIS.
+        let (f_cos, pdf) = lobes.eval_pdf(&wo, &wi);
+        if pdf <= 0.0 {
+            return false;
+        }
+
+        *scattered = Ray::new(rec.p, frame.local_to_world(&wi), ray_in.time);
+        *color = f_cos / pdf;
+        return true;
+    }
+
+    fn emitted(&self, _ray_in: &Ray, rec: &HitRecord) -> Color {
+        match &self.emission {
+            Some(emission) if rec.front_face => texture_value(emission, rec) * self.emission_strength,
+            _ => Color::black()
+        }
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let frame: ONB = rec.shading_frame();
+        let wo: Vec3 = frame.world_to_local(&-normalize(ray_in.direction));
+        let wi: Vec3 = frame.world_to_local(&normalize(*direction));
+        self.lobes(rec).eval_pdf(&wo, &wi).0
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let frame: ONB = rec.shading_frame();
+        let wo: Vec3 = frame.world_to_local(&-normalize(ray_in.direction));
+        let wi: Vec3 = frame.world_to_local(&normalize(*direction));
+        self.lobes(rec).eval_pdf(&wo, &wi).1
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, color: &mut Color, scattered: &mut Ray) -> bool {
+        let component: &Arc<dyn Material + Sync> = self.component(rec);
+        if !component.scatter(ray_in, rec, color, scattered) {
+            return false;
+        }
+        mark_specular(component, ray_in, rec, scattered);
+        return true;
+    }
+
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        self.component(rec).emitted(ray_in, rec)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.component(rec).eval(ray_in, rec, direction)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        self.component(rec).scattering_pdf(ray_in, rec, direction)
+    }
+
+    fn dispersive(&self) -> bool {
+        self.a.dispersive() || self.b.dispersive()
+    }
+
+    fn cutout(&self, rec: &HitRecord) -> bool {
+        self.component(rec).cutout(rec)
+    }
+}
+
+impl Material for Coated {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, color: &mut Color, scattered: &mut Ray) -> bool {
+        let dir: Vec3 = normalize(ray_in.direction);
+        let f_out: f64 = self.fresnel(rec, &dir);
+
+        // Choosing the coat with probability F cancels F from its weight.
+        if rand::thread_rng().gen::<f64>() < f_out {
+            *scattered = Ray::new(rec.p, reflect(&dir, &rec.shading_normal), ray_in.time);
+            scattered.specular = true;
+            *color = Color::white();
+            return true;
+        }
+
+        if !self.base.scatter(ray_in, rec, color, scattered) {
+            return false;
+        }
+        mark_specular(&self.base, ray_in, rec, scattered);
+        *color *= 1.0 - self.fresnel(rec, &scattered.direction);
+        return true;
+    }
+
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        self.base.emitted(ray_in, rec) * (1.0 - self.fresnel(rec, &ray_in.direction))
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let transmitted: f64 = (1.0 - self.fresnel(rec, &ray_in.direction)) * (1.0 - self.fresnel(rec, direction));
+        self.base.eval(ray_in, rec, direction) * transmitted
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        (1.0 - self.fresnel(rec, &ray_in.direction)) * self.base.scattering_pdf(ray_in, rec, direction)
+    }
+
+    fn absorption(&self) -> Color {
+        self.base.absorption()
+    }
+
+    fn medium(&self) -> Medium {
+        self.base.medium()
+    }
+
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+
+    fn cutout(&self, rec: &HitRecord) -> bool {
+        self.base.cutout(rec)
+    }
+}
+
+impl Material for NormalMapped {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, color: &mut Color, scattered: &mut Ray) -> bool {
+        self.base.scatter(ray_in, &self.perturb(rec), color, scattered)
+    }
+
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        self.base.emitted(ray_in, rec)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.base.eval(ray_in, &self.perturb(rec), direction)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        self.base.scattering_pdf(ray_in, &self.perturb(rec), direction)
+    }
+
+    fn absorption(&self) -> Color {
+        self.base.absorption()
+    }
+
+    fn medium(&self) -> Medium {
+        self.base.medium()
+    }
+
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+
+    fn cutout(&self, rec: &HitRecord) -> bool {
+        self.base.cutout(rec)
+    }
+}
+
+impl Material for BumpMapped {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, color: &mut Color, scattered: &mut Ray) -> bool {
+        self.base.scatter(ray_in, &self.perturb(rec), color, scattered)
+    }
+
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        self.base.emitted(ray_in, rec)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.base.eval(ray_in, &self.perturb(rec), direction)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        self.base.scattering_pdf(ray_in, &self.perturb(rec), direction)
+    }
+
+    fn absorption(&self) -> Color {
+        self.base.absorption()
+    }
+
+    fn medium(&self) -> Medium {
+        self.base.medium()
+    }
+
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+
+    fn cutout(&self, rec: &HitRecord) -> bool {
+        self.base.cutout(rec)
+    }
+}
+
+impl Material for AlphaMasked {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, color: &mut Color, scattered: &mut Ray) -> bool {
+        self.base.scatter(ray_in, rec, color, scattered)
+    }
+
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        self.base.emitted(ray_in, rec)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.base.eval(ray_in, rec, direction)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        self.base.scattering_pdf(ray_in, rec, direction)
+    }
+
+    fn absorption(&self) -> Color {
+        self.base.absorption()
+    }
+
+    fn medium(&self) -> Medium {
+        self.base.medium()
+    }
+
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+
+    fn cutout(&self, rec: &HitRecord) -> bool {
+        let alpha: f64 = self.mask.alpha_at(rec);
+        let transparent: bool = match self.mode {
+            AlphaMode::Threshold(threshold) => alpha < threshold,
+            AlphaMode::Stochastic => rand::thread_rng().gen::<f64>() >= alpha
+        };
+        transparent || self.base.cutout(rec)
+    }
 }
 
 impl Material for Emiter {
@@ -131,11 +1265,115 @@ impl Material for Emiter {
         false
     }
 
-    fn emitted(&self, rec: &HitRecord) -> Color {
-        if rec.front_face{
-            self.emission
-        } else {
-            Color::black()
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        if !(rec.front_face || self.two_sided) {
+            return Color::black();
+        }
+
+        let cos_theta: f64 = dot(&-normalize(ray_in.direction), &rec.normal);
+        let falloff: f64 = self.cone_falloff(cos_theta);
+        if falloff <= 0.0 {
+            return Color::black();
         }
+
+        let emission: Color = texture_value(self.emission.as_ref().unwrap(), rec);
+        emission * (self.intensity * falloff)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hit on the xy plane facing +z, with the tangent along x so anisotropy lines up with the local frame.
+    fn flat_hit() -> HitRecord {
+        let mut rec: HitRecord = HitRecord::default();
+        rec.normal = Vec3::new(0.0, 0.0, 1.0);
+        rec.shading_normal = rec.normal;
+        rec.dpdu = Vec3::new(1.0, 0.0, 0.0);
+        rec.dpdv = Vec3::new(0.0, 1.0, 0.0);
+        rec.front_face = true;
+        rec
+    }
+
+    // Ray arriving at the origin `theta` degrees from the normal, tilted towards `phi` degrees around it.
+    fn incident(theta: f64, phi: f64) -> Ray {
+        let (theta, phi) = (degrees_to_radians(theta), degrees_to_radians(phi));
+        let wo: Vec3 = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+        Ray::new(wo, -wo, 0.0)
+    }
+
+    // Directional albedo by averaging the throughput of importance sampled scattering, zero where it fails.
+    fn sampled_albedo(material: &Conductor, ray_in: &Ray, rec: &HitRecord, samples: usize) -> f64 {
+        let mut total: f64 = 0.0;
+        for _ in 0..samples {
+            let mut color: Color = Color::black();
+            let mut scattered: Ray = Ray::default();
+            if material.scatter(ray_in, rec, &mut color, &mut scattered) {
+                assert!(color.g() <= 1.0 + 1e-9, "single sample throughput {} above one", color.g());
+                total += color.g();
+            }
+        }
+        total / samples as f64
+    }
+
+    // Directional albedo by integrating eval over uniformly sampled directions, independent of the sampler.
+    fn integrated_albedo(material: &Conductor, ray_in: &Ray, rec: &HitRecord, samples: usize) -> f64 {
+        let mut rng = rand::thread_rng();
+        let mut total: f64 = 0.0;
+        for _ in 0..samples {
+            let z: f64 = rng.gen();
+            let phi: f64 = 2.0 * PI * rng.gen::<f64>();
+            let r: f64 = (1.0 - z * z).max(0.0).sqrt();
+            let direction: Vec3 = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            total += material.eval(ray_in, rec, &direction).g() * 2.0 * PI;
+        }
+        total / samples as f64
+    }
+
+    #[test]
+    fn conductor_white_furnace() {
+        let rec: HitRecord = flat_hit();
+        for roughness in [0.0, 0.05, 0.2, 0.5, 0.8, 1.0] {
+            for anisotropy in [0.0, 0.5, 0.9] {
+                let material: Conductor = Conductor::new_reflectance(Color::white(), roughness, anisotropy);
+                for theta in [0.0, 30.0, 60.0, 80.0] {
+                    for phi in [0.0, 45.0, 90.0] {
+                        let ray_in: Ray = incident(theta, phi);
+                        let albedo: f64 = sampled_albedo(&material, &ray_in, &rec, 4000);
+                        assert!(albedo <= 1.0 + 1e-9, "albedo {} above one at roughness {}, anisotropy {}, {} degrees", albedo, roughness, anisotropy, theta);
+                        // Little roughness loses little energy to masking, except at grazing angles.
+                        if roughness <= 0.2 && theta <= 60.0 {
+                            assert!(albedo > 0.95, "albedo {} at roughness {}, anisotropy {}, {} degrees", albedo, roughness, anisotropy, theta);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn conductor_eval_matches_sampling() {
+        let rec: HitRecord = flat_hit();
+        for roughness in [0.5, 0.8, 1.0] {
+            for anisotropy in [0.0, 0.9] {
+                let material: Conductor = Conductor::new_reflectance(Color::white(), roughness, anisotropy);
+                for theta in [0.0, 45.0, 75.0] {
+                    let ray_in: Ray = incident(theta, 30.0);
+                    let sampled: f64 = sampled_albedo(&material, &ray_in, &rec, 20000);
+                    let integrated: f64 = integrated_albedo(&material, &ray_in, &rec, 100000);
+                    assert!(integrated <= 1.02, "integrated albedo {} above one at roughness {}, {} degrees", integrated, roughness, theta);
+                    assert!((sampled - integrated).abs() < 0.03, "sampled {} and integrated {} albedo disagree at roughness {}, anisotropy {}, {} degrees", sampled, integrated, roughness, anisotropy, theta);
+
+                    // The throughput scatter returns is eval over the pdf of the direction it picked.
+                    let mut color: Color = Color::black();
+                    let mut scattered: Ray = Ray::default();
+                    if material.scatter(&ray_in, &rec, &mut color, &mut scattered) {
+                        let ratio: f64 = material.eval(&ray_in, &rec, &scattered.direction).g() / material.scattering_pdf(&ray_in, &rec, &scattered.direction);
+                        assert!((ratio - color.g()).abs() < 1e-6 * color.g().max(1.0), "eval / pdf {} but scatter gave {}", ratio, color.g());
+                    }
+                }
+            }
+        }
+    }
+}
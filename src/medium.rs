@@ -0,0 +1,115 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use crate::onb::ONB;
+use crate::ray::Ray;
+use crate::vec3::*;
+
+// Homogeneous medium filling the inside of a closed object, coefficients are per unit distance.
+#[derive(Copy, Clone, Default)]
+pub struct Medium {
+    pub absorption: Color,
+    pub scattering: Color,
+    // Henyey-Greenstein asymmetry, negative scatters backwards and positive forwards.
+    pub anisotropy: f64
+}
+
+// Outcome of sampling a free flight through the medium, weights are already divided by the sampling pdf.
+pub enum MediumSample {
+    Scatter { t: f64, weight: Color },
+    Pass { weight: Color }
+}
+
+impl Medium {
+    pub fn absorbing(absorption: Color) -> Medium {
+        Medium { absorption: absorption, ..Medium::default() }
+    }
+
+    // Medium whose multiple scattering albedo is `albedo` and whose mean free path is `mean_free_path`,
+    // following Chiang et al. 2016, "Practical and Controllable Subsurface Scattering for Production Path Tracing".
+    pub fn from_albedo(albedo: &Color, mean_free_path: &Color, anisotropy: f64) -> Medium {
+        let mut medium: Medium = Medium { anisotropy: anisotropy.clamp(-0.99, 0.99), ..Medium::default() };
+        for i in 0..3 {
+            let a: f64 = albedo[i].clamp(0.0, 1.0);
+            let s: f64 = 4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt();
+            let single_scatter_albedo: f64 = 1.0 - s * s;
+            let sigma_t: f64 = 1.0 / mean_free_path[i].max(1e-6);
+            medium.scattering[i] = single_scatter_albedo * sigma_t;
+            medium.absorption[i] = (1.0 - single_scatter_albedo) * sigma_t;
+        }
+        medium
+    }
+
+    pub fn extinction(&self) -> Color {
+        self.absorption + self.scattering
+    }
+
+    pub fn is_scattering(&self) -> bool {
+        !self.scattering.is_black()
+    }
+
+    // Beer-Lambert transmittance over `distance`.
+    pub fn transmittance(&self, distance: f64) -> Color {
+        let sigma_t: Color = self.extinction();
+        let mut tr: Color = Color::white();
+        for i in 0..3 {
+            if sigma_t[i] > 0.0 {
+                tr[i] = (-sigma_t[i] * distance).exp();
+            }
+        }
+        tr
+    }
+
+    // Samples where `r` next interacts with the medium before `t_max`.  The distance is sampled from one
+    // channel picked uniformly and weighted by the pdf averaged over all three (spectral MIS).
+    pub fn sample(&self, r: &Ray, t_max: f64) -> MediumSample {
+        if !self.is_scattering() {
+            return MediumSample::Pass { weight: r.transmittance(t_max) };
+        }
+
+        let mut rng = rand::thread_rng();
+        let speed: f64 = r.direction.length();
+        let sigma_t: Color = self.extinction();
+        let channel: usize = rng.gen_range(0..3);
+        let distance: f64 = if sigma_t[channel] > 0.0 {
+            -(1.0 - rng.gen::<f64>()).ln() / sigma_t[channel]
+        } else {
+            f64::INFINITY
+        };
+
+        let t: f64 = distance / speed;
+        if t < t_max {
+            let tr: Color = self.transmittance(distance);
+            let pdf: f64 = (sigma_t * tr).average();
+            if pdf <= 0.0 {
+                return MediumSample::Pass { weight: Color::black() };
+            }
+            return MediumSample::Scatter { t: t, weight: self.scattering * tr / pdf };
+        }
+
+        let tr: Color = self.transmittance(t_max * speed);
+        let probability: f64 = tr.average();
+        if probability <= 0.0 {
+            return MediumSample::Pass { weight: Color::black() };
+        }
+        MediumSample::Pass { weight: tr / probability }
+    }
+
+    // Samples a new direction from the phase function, which is its own pdf so the weight is one.
+    pub fn sample_phase(&self, direction: &Vec3) -> Vec3 {
+        let mut rng = rand::thread_rng();
+        let g: f64 = self.anisotropy;
+        let u: f64 = rng.gen();
+        let cos_theta: f64 = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let s: f64 = (1.0 - g * g) / (1.0 + g - 2.0 * g * u);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta: f64 = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi: f64 = 2.0 * PI * rng.gen::<f64>();
+        let frame: ONB = ONB::new(direction);
+        frame.local_to_world(&Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+    }
+}
@@ -0,0 +1,149 @@
+use std::f64::consts::PI;
+
+use crate::vec3::*;
+
+// Trowbridge-Reitz (GGX) microfacet distribution, evaluated in a local frame where the normal is +z.
+#[derive(Copy, Clone, Default)]
+pub struct TrowbridgeReitz {
+    pub alpha_x: f64,
+    pub alpha_y: f64
+}
+
+// Perceptual roughness to alpha, squaring gives a more even response across the range.
+pub fn roughness_to_alpha(roughness: f64) -> f64 {
+    (roughness * roughness).max(1e-4)
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> TrowbridgeReitz {
+        TrowbridgeReitz {
+            alpha_x: alpha_x.max(1e-4),
+            alpha_y: alpha_y.max(1e-4)
+        }
+    }
+
+    // `anisotropy` in [0, 1) stretches the highlight along the tangent, as in the Disney BRDF.
+    pub fn from_roughness(roughness: f64, anisotropy: f64) -> TrowbridgeReitz {
+        let aspect: f64 = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+        let alpha: f64 = roughness_to_alpha(roughness);
+        TrowbridgeReitz::new(alpha / aspect, alpha * aspect)
+    }
+
+    // Below this the surface is treated as a perfect mirror.
+    pub fn effectively_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        let cos2_theta: f64 = wm.z() * wm.z();
+        if cos2_theta <= 0.0 {
+            return 0.0;
+        }
+        let e: f64 = (wm.x() * wm.x() / (self.alpha_x * self.alpha_x) + wm.y() * wm.y() / (self.alpha_y * self.alpha_y)) / cos2_theta;
+        1.0 / (PI * self.alpha_x * self.alpha_y * cos2_theta * cos2_theta * (1.0 + e) * (1.0 + e))
+    }
+
+    pub fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2_theta: f64 = w.z() * w.z();
+        if cos2_theta <= 0.0 {
+            return f64::INFINITY;
+        }
+        let alpha2_tan2_theta: f64 = (w.x() * w.x() * self.alpha_x * self.alpha_x + w.y() * w.y() * self.alpha_y * self.alpha_y) / cos2_theta;
+        ((1.0 + alpha2_tan2_theta).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height correlated Smith masking-shadowing.
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Distribution of normals visible from `w`.
+    pub fn d_visible(&self, w: &Vec3, wm: &Vec3) -> f64 {
+        let cos_theta: f64 = w.z().abs();
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / cos_theta * self.d(wm) * dot(w, wm).abs()
+    }
+
+    // Samples a visible normal, Heitz 2018 "Sampling the GGX Distribution of Visible Normals".
+    pub fn sample_wm(&self, w: &Vec3, u0: f64, u1: f64) -> Vec3 {
+        let mut wh: Vec3 = normalize(Vec3::new(self.alpha_x * w.x(), self.alpha_y * w.y(), w.z()));
+        if wh.z() < 0.0 {
+            wh = -wh;
+        }
+
+        let len_sq: f64 = wh.x() * wh.x() + wh.y() * wh.y();
+        let t1: Vec3 = if len_sq > 0.0 { Vec3::new(-wh.y(), wh.x(), 0.0) / len_sq.sqrt() } else { Vec3::new(1.0, 0.0, 0.0) };
+        let t2: Vec3 = cross(&wh, &t1);
+
+        let r: f64 = u0.sqrt();
+        let phi: f64 = 2.0 * PI * u1;
+        let p1: f64 = r * phi.cos();
+        let mut p2: f64 = r * phi.sin();
+        let s: f64 = 0.5 * (1.0 + wh.z());
+        p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * p2;
+
+        let nh: Vec3 = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * wh;
+        normalize(Vec3::new(self.alpha_x * nh.x(), self.alpha_y * nh.y(), nh.z().max(1e-6)))
+    }
+}
+
+// Fresnel reflectance of a conductor with complex index of refraction eta + ik.
+pub fn fresnel_conductor(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos_i: f64 = cos_theta_i.clamp(0.0, 1.0);
+    let cos2: f64 = cos_i * cos_i;
+    let sin2: f64 = 1.0 - cos2;
+
+    let t0: f64 = eta * eta - k * k - sin2;
+    let a2b2: f64 = (t0 * t0 + 4.0 * eta * eta * k * k).max(0.0).sqrt();
+    let t1: f64 = a2b2 + cos2;
+    let a: f64 = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+    let t2: f64 = 2.0 * cos_i * a;
+    let rs: f64 = (t1 - t2) / (t1 + t2);
+
+    let t3: f64 = cos2 * a2b2 + sin2 * sin2;
+    let t4: f64 = t2 * sin2;
+    let rp: f64 = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rp + rs)
+}
+
+pub fn fresnel_conductor_color(cos_theta_i: f64, eta: &Color, k: &Color) -> Color {
+    Color::new(
+        fresnel_conductor(cos_theta_i, eta.r(), k.r()),
+        fresnel_conductor(cos_theta_i, eta.g(), k.g()),
+        fresnel_conductor(cos_theta_i, eta.b(), k.b())
+    )
+}
+
+// Unpolarized Fresnel reflectance of a dielectric interface, `eta` is the relative IOR of the transmitted
+// side over the incident side.  Negative cosines mean the incident direction is on the transmitted side.
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let mut cos_i: f64 = cos_theta_i.clamp(-1.0, 1.0);
+    let mut eta: f64 = eta;
+    if cos_i < 0.0 {
+        eta = 1.0 / eta;
+        cos_i = -cos_i;
+    }
+
+    let sin2_i: f64 = 1.0 - cos_i * cos_i;
+    let sin2_t: f64 = sin2_i / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t: f64 = (1.0 - sin2_t).max(0.0).sqrt();
+
+    let r_parallel: f64 = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular: f64 = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+pub fn fresnel_schlick(cos_theta_i: f64, f0: &Color) -> Color {
+    let m: f64 = (1.0 - cos_theta_i.clamp(0.0, 1.0)).powi(5);
+    *f0 + (Color::white() - *f0) * m
+}
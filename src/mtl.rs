@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::color_space::ColorSpace;
+use crate::material::{Material, Principled};
+use crate::texture::{solid_color, solid_value, ImageTexture, Texture};
+use crate::vec3::Color;
+
+// Wavefront MTL material libraries, read into principled materials.
+//
+//     newmtl car_paint
+//     Kd 0.05 0.15 0.6
+//     Pm 0.5
+//     Pr 0.6
+//     Pc 1
+//
+// Besides the classic `Kd`, `Ns`, `Ni` and `Ke` statements this reads the PBR extension: `Pr` roughness,
+// `Pm` metallic, `Ps` sheen, `Pc` clearcoat and `Pcr` clearcoat roughness, with `map_` textures for the
+// colours and the first three.  The average of `Tf` is read as the transmission weight.  Statements for
+// legacy shading models, such as `Ka`, `Ks` and `illum`, have no principled equivalent and are skipped.
+
+fn invalid(line: usize, msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("MTL line {}: {}", line, msg))
+}
+
+// Every material in a library by name.
+#[derive(Default)]
+pub struct MaterialLibrary {
+    pub materials: HashMap<String, Principled>
+}
+
+// Phong exponent to roughness, through the Beckmann width matching the lobe, Walter et al. 2007.
+fn shininess_to_roughness(ns: f64) -> f64 {
+    (2.0 / (ns.max(0.0) + 2.0)).sqrt().sqrt()
+}
+
+fn numbers(values: &[&str], line: usize) -> Result<Vec<f64>, Error> {
+    values.iter().map(|v: &&str| -> Result<f64, Error> {
+        v.parse().map_err(|_| invalid(line, &format!("bad number `{}`", v)))
+    }).collect()
+}
+
+fn number(keyword: &str, values: &[&str], line: usize) -> Result<f64, Error> {
+    match numbers(values, line)?.as_slice() {
+        [n] => Ok(*n),
+        _ => Err(invalid(line, &format!("`{}` takes one number", keyword)))
+    }
+}
+
+// `r`, or `r g b`.
+fn color(keyword: &str, values: &[&str], line: usize) -> Result<Color, Error> {
+    match numbers(values, line)?.as_slice() {
+        [v] => Ok(Color::new(*v, *v, *v)),
+        [r, g, b] => Ok(Color::new(*r, *g, *b)),
+        _ => Err(invalid(line, &format!("`{}` takes one or three numbers", keyword)))
+    }
+}
+
+impl MaterialLibrary {
+    pub fn load(file_path: &Path) -> Result<MaterialLibrary, Error> {
+        let source: String = fs::read_to_string(file_path)?;
+        let base_dir: PathBuf = file_path.parent().map(Path::to_path_buf).unwrap_or_default();
+        MaterialLibrary::parse(&source, &base_dir)
+    }
+
+    // Texture maps are found relative to `base_dir`.
+    pub fn parse(source: &str, base_dir: &Path) -> Result<MaterialLibrary, Error> {
+        let mut library: MaterialLibrary = MaterialLibrary::default();
+        let mut current: Option<(String, Principled)> = None;
+        // Set by `Pr` or `map_Pr`, which win over a roughness from `Ns` wherever it comes.
+        let mut has_roughness: bool = false;
+
+        for (index, text) in source.lines().enumerate() {
+            let line: usize = index + 1;
+            let text: &str = text.split('#').next().unwrap_or("");
+            let tokens: Vec<&str> = text.split_whitespace().collect();
+            let Some((keyword, values)) = tokens.split_first() else {
+                continue;
+            };
+
+            if *keyword == "newmtl" {
+                let [name] = values else {
+                    return Err(invalid(line, "`newmtl` takes one name"));
+                };
+                if let Some((name, material)) = current.take() {
+                    library.materials.insert(name, material);
+                }
+                current = Some((name.to_string(), Principled::new(Color::new(0.8, 0.8, 0.8))));
+                has_roughness = false;
+                continue;
+            }
+
+            let Some((_, material)) = current.as_mut() else {
+                return Err(invalid(line, &format!("`{}` before any `newmtl`", keyword)));
+            };
+            // Maps take the file name last, their options are not supported and skipped.
+            let map = |color_space: Option<ColorSpace>| -> Result<Arc<dyn Texture + Sync>, Error> {
+                let file: &str = values.last().ok_or_else(|| invalid(line, &format!("`{}` needs a file", keyword)))?;
+                let path: PathBuf = base_dir.join(file);
+                let image: ImageTexture = ImageTexture::load(&path, color_space).map_err(|e| -> Error { invalid(line, &format!("{}: {}", path.display(), e)) })?;
+                Ok(Arc::new(image))
+            };
+            let linear: Option<ColorSpace> = Some(ColorSpace::Rec709);
+
+            match *keyword {
+                "Kd" => material.base_color = solid_color(color(keyword, values, line)?),
+                "map_Kd" => material.base_color = map(None)?,
+                "Pm" => material.metallic = solid_value(number(keyword, values, line)?),
+                "map_Pm" => material.metallic = map(linear)?,
+                "Pr" => {
+                    material.roughness = solid_value(number(keyword, values, line)?);
+                    has_roughness = true;
+                }
+                "map_Pr" => {
+                    material.roughness = map(linear)?;
+                    has_roughness = true;
+                }
+                "Ns" => {
+                    let roughness: f64 = shininess_to_roughness(number(keyword, values, line)?);
+                    if !has_roughness {
+                        material.roughness = solid_value(roughness);
+                    }
+                }
+                "Ps" => material.sheen = solid_value(number(keyword, values, line)?),
+                "map_Ps" => material.sheen = map(linear)?,
+                "Pc" => material.clearcoat = solid_value(number(keyword, values, line)?),
+                "Pcr" => material.clearcoat_roughness = number(keyword, values, line)?,
+                "Tf" => material.transmission = solid_value(color(keyword, values, line)?.average()),
+                "Ni" => material.ior = number(keyword, values, line)?,
+                "Ke" => {
+                    let emission: Color = color(keyword, values, line)?;
+                    material.emission = if emission.is_black() { None } else { Some(solid_color(emission)) };
+                }
+                "map_Ke" => material.emission = Some(map(None)?),
+                _ => {}
+            }
+        }
+
+        if let Some((name, material)) = current {
+            library.materials.insert(name, material);
+        }
+        Ok(library)
+    }
+
+    // A material by name, with an error naming the library's materials when it's missing.
+    pub fn get(&self, name: &str) -> Result<Arc<dyn Material + Sync>, Error> {
+        match self.materials.get(name) {
+            Some(material) => Ok(Arc::new(material.clone())),
+            None => {
+                let mut names: Vec<&String> = self.materials.keys().collect();
+                names.sort();
+                Err(Error::new(ErrorKind::NotFound, format!("Material library has no material `{}`, it has {:?}", name, names)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<MaterialLibrary, Error> {
+        MaterialLibrary::parse(source, &Path::new(env!("CARGO_MANIFEST_DIR")).join("input"))
+    }
+
+    fn value(texture: &Arc<dyn Texture + Sync>) -> Color {
+        texture.value(0.5, 0.5, 0.0)
+    }
+
+    fn assert_color(got: Color, want: Color) {
+        assert!((got - want).length() < 1e-9, "{} != {}", got, want);
+    }
+
+    #[test]
+    fn statements_map_to_principled_parameters() {
+        let source: &str = "
+            # Comments, blank lines and legacy statements are skipped.
+            newmtl paint
+            Ka 0.1 0.1 0.1
+            Kd 0.1 0.2 0.3   # trailing comment
+            Pm 0.25
+            Pr 0.4
+            Ps 0.5
+            Pc 1
+            Pcr 0.05
+            Tf 1 0.5 0
+            Ni 1.33
+            Ke 2
+            illum 2
+
+            newmtl earth
+            map_Kd -s 1 1 1 earthmap.tga
+        ";
+        let library: MaterialLibrary = parse(source).unwrap();
+        assert_eq!(library.materials.len(), 2);
+
+        let paint: &Principled = &library.materials["paint"];
+        assert_color(value(&paint.base_color), Color::new(0.1, 0.2, 0.3));
+        assert_eq!(value(&paint.metallic).r(), 0.25);
+        assert_eq!(value(&paint.roughness).r(), 0.4);
+        assert_eq!(value(&paint.sheen).r(), 0.5);
+        assert_eq!(value(&paint.clearcoat).r(), 1.0);
+        assert_eq!(value(&paint.transmission).r(), 0.5);
+        assert_eq!((paint.clearcoat_roughness, paint.ior), (0.05, 1.33));
+        assert_color(value(paint.emission.as_ref().unwrap()), Color::new(2.0, 2.0, 2.0));
+
+        let earth: &Principled = &library.materials["earth"];
+        assert!(earth.emission.is_none());
+        assert!((value(&earth.base_color) - Color::new(0.8, 0.8, 0.8)).length() > 0.01);
+        assert!(library.get("earth").is_ok());
+        let missing: Error = library.get("missing").err().unwrap();
+        assert!(missing.to_string().contains("[\"earth\", \"paint\"]"), "{}", missing);
+    }
+
+    #[test]
+    fn shininess_is_overridden_by_roughness() {
+        let library: MaterialLibrary = parse("newmtl a\nNs 0\nnewmtl b\nPr 0.3\nNs 1000\nnewmtl c\nNs 98").unwrap();
+        assert_eq!(value(&library.materials["a"].roughness).r(), 1.0);
+        assert_eq!(value(&library.materials["b"].roughness).r(), 0.3);
+        assert!((value(&library.materials["c"].roughness).r() - 0.02_f64.sqrt().sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn errors_name_the_line_and_the_problem() {
+        let cases: [(&str, &str); 6] = [
+            ("Kd 1 1 1", "line 1: `Kd` before any `newmtl`"),
+            ("newmtl", "line 1: `newmtl` takes one name"),
+            ("newmtl a\nKd 1 1", "line 2: `Kd` takes one or three numbers"),
+            ("newmtl a\nPr rough", "line 2: bad number `rough`"),
+            ("newmtl a\nNi 1 2", "line 2: `Ni` takes one number"),
+            ("newmtl a\n\nmap_Kd", "line 3: `map_Kd` needs a file")
+        ];
+        for (source, message) in cases {
+            let e: String = match parse(source) {
+                Ok(_) => panic!("`{}` parsed", source),
+                Err(e) => e.to_string()
+            };
+            assert!(e.starts_with("MTL ") && e.contains(message), "`{}` gave `{}`", source, e);
+        }
+        assert!(parse("newmtl a\nmap_Kd missing.tga").is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::vec3::{Color, Point3, Vec3};
+
+// Lattice noise for procedural textures.  Lattice points are hashed rather than looked up in a shuffled
+// table, so patterns don't repeat and need no random state.
+
+// Chris Wellons' lowbias32 integer hash.
+fn hash(x: u32) -> u32 {
+    let mut x: u32 = x;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7FEB_352D);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846C_A68B);
+    x ^= x >> 16;
+    x
+}
+
+pub fn hash3(x: i64, y: i64, z: i64) -> u32 {
+    hash(x as u32 ^ hash(y as u32 ^ hash(z as u32)))
+}
+
+// Three values in [0, 1) from one hash.
+pub fn hash_to_vec3(h: u32) -> Vec3 {
+    let (a, b) = (hash(h), hash(h ^ 0x9E37_79B9));
+    let unit = |x: u32| -> f64 { x as f64 / 4_294_967_296.0 };
+    Vec3::new(unit(h), unit(a), unit(b))
+}
+
+// A random colour for a lattice cell, for tinting cells or bricks apart.
+pub fn cell_color(cell: (i64, i64, i64)) -> Color {
+    hash_to_vec3(hash3(cell.0, cell.1, cell.2))
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+// Dot product with one of the twelve cube edge directions of Perlin's improved noise.
+fn gradient(h: u32, x: f64, y: f64, z: f64) -> f64 {
+    match h % 12 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 => -y + z,
+        10 => y - z,
+        _ => -y - z
+    }
+}
+
+// Gradient noise, roughly in [-1, 1] and zero at lattice points.
+pub fn perlin(p: &Point3) -> f64 {
+    let (fx, fy, fz) = (p.x().floor(), p.y().floor(), p.z().floor());
+    let (x, y, z) = (p.x() - fx, p.y() - fy, p.z() - fz);
+    let (ix, iy, iz) = (fx as i64, fy as i64, fz as i64);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+
+    let corner = |dx: i64, dy: i64, dz: i64| -> f64 {
+        gradient(hash3(ix + dx, iy + dy, iz + dz), x - dx as f64, y - dy as f64, z - dz as f64)
+    };
+    let lerp = |t: f64, a: f64, b: f64| -> f64 { a + t * (b - a) };
+    lerp(w,
+        lerp(v, lerp(u, corner(0, 0, 0), corner(1, 0, 0)), lerp(u, corner(0, 1, 0), corner(1, 1, 0))),
+        lerp(v, lerp(u, corner(0, 0, 1), corner(1, 0, 1)), lerp(u, corner(0, 1, 1), corner(1, 1, 1)))
+    )
+}
+
+// Fractal Brownian motion, octaves of noise each `lacunarity` times the frequency and `gain` times the
+// amplitude of the one before, normalized back to about [-1, 1].
+pub fn fbm(p: &Point3, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+    let mut sum: f64 = 0.0;
+    let mut total: f64 = 0.0;
+    let mut amplitude: f64 = 1.0;
+    let mut frequency: f64 = 1.0;
+    for _ in 0..octaves.max(1) {
+        sum += amplitude * perlin(&(*p * frequency));
+        total += amplitude;
+        amplitude *= gain;
+        frequency *= lacunarity;
+    }
+    sum / total
+}
+
+// Distances to the nearest and second nearest feature points, and the lattice cell of the nearest.
+pub struct WorleySample {
+    pub f1: f64,
+    pub f2: f64,
+    pub cell: (i64, i64, i64)
+}
+
+// Worley cellular noise with one feature point per unit cell.  `jitter` in [0, 1] moves the points from the
+// cell centres to anywhere in the cell.
+pub fn worley(p: &Point3, jitter: f64) -> WorleySample {
+    let (ix, iy, iz) = (p.x().floor() as i64, p.y().floor() as i64, p.z().floor() as i64);
+    let mut sample: WorleySample = WorleySample { f1: f64::INFINITY, f2: f64::INFINITY, cell: (ix, iy, iz) };
+    for dz in -1..=1 {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let cell: (i64, i64, i64) = (ix + dx, iy + dy, iz + dz);
+                let offset: Vec3 = (hash_to_vec3(hash3(cell.0, cell.1, cell.2)) - Vec3::new(0.5, 0.5, 0.5)) * jitter;
+                let feature: Point3 = Point3::new(cell.0 as f64 + 0.5, cell.1 as f64 + 0.5, cell.2 as f64 + 0.5) + offset;
+                let d: f64 = (feature - *p).length();
+                if d < sample.f1 {
+                    sample.f2 = sample.f1;
+                    sample.f1 = d;
+                    sample.cell = cell;
+                } else if d < sample.f2 {
+                    sample.f2 = d;
+                }
+            }
+        }
+    }
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Points spread over a few cells on both sides of zero, none on a cell boundary.
+    fn points() -> impl Iterator<Item = Point3> {
+        (1..200).map(|i: i32| -> Point3 {
+            let t: f64 = i as f64;
+            Point3::new((t * 0.618).sin() * 3.7, (t * 0.414).cos() * 2.9 - 0.3, t * 0.0731 - 5.0)
+        })
+    }
+
+    #[test]
+    fn perlin_is_zero_on_the_lattice() {
+        for x in -3..3 {
+            for y in -3..3 {
+                for z in -3..3 {
+                    assert_eq!(perlin(&Point3::new(x as f64, y as f64, z as f64)), 0.0, "at ({}, {}, {})", x, y, z);
+                }
+            }
+        }
+        // And not everywhere else.
+        assert!(points().any(|p: Point3| -> bool { perlin(&p).abs() > 0.1 }));
+        assert!(points().all(|p: Point3| -> bool { perlin(&p).abs() <= 1.5 && fbm(&p, 5, 2.0, 0.5).abs() <= 1.5 }));
+    }
+
+    #[test]
+    fn worley_distances_are_ordered() {
+        for p in points() {
+            let sample: WorleySample = worley(&p, 1.0);
+            assert!(sample.f1 <= sample.f2, "at {}: {} > {}", p, sample.f1, sample.f2);
+
+            // Without jitter the nearest point is the centre of the cell `p` is in.
+            let grid: WorleySample = worley(&p, 0.0);
+            let cell: (i64, i64, i64) = (p.x().floor() as i64, p.y().floor() as i64, p.z().floor() as i64);
+            assert_eq!(grid.cell, cell);
+            let centre: Point3 = Point3::new(cell.0 as f64 + 0.5, cell.1 as f64 + 0.5, cell.2 as f64 + 0.5);
+            assert!((grid.f1 - (centre - p).length()).abs() < 1e-12);
+            assert!(grid.f1 <= grid.f2);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::vec3::*;
+
+// Orthonormal basis, w is the normal and u, v span the tangent plane.
+#[derive(Copy, Clone, Default)]
+pub struct ONB {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3
+}
+
+impl ONB {
+    // Builds an arbitrary but continuous tangent frame around `n`.
+    // Duff et al. 2017, "Building an Orthonormal Basis, Revisited".
+    pub fn new(n: &Vec3) -> ONB {
+        let w: Vec3 = normalize(*n);
+        let sign: f64 = 1.0_f64.copysign(w.z());
+        let a: f64 = -1.0 / (sign + w.z());
+        let b: f64 = w.x() * w.y() * a;
+        ONB {
+            u: Vec3::new(1.0 + sign * w.x() * w.x() * a, sign * b, -sign * w.x()),
+            v: Vec3::new(b, sign + w.y() * w.y() * a, -w.y()),
+            w: w
+        }
+    }
+
+    // Frame around `n` with u along `tangent` projected onto the tangent plane.  Falls back to an
+    // arbitrary frame when the tangent is missing or parallel to the normal.
+    pub fn new_tangent(n: &Vec3, tangent: &Vec3) -> ONB {
+        let w: Vec3 = normalize(*n);
+        let t: Vec3 = *tangent - w * dot(tangent, &w);
+        if t.length_squared() < 1e-12 {
+            return ONB::new(n);
+        }
+        let u: Vec3 = normalize(t);
+        ONB {
+            u: u,
+            v: cross(&w, &u),
+            w: w
+        }
+    }
+
+    // World to local, the normal becomes +z.
+    pub fn world_to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(dot(a, &self.u), dot(a, &self.v), dot(a, &self.w))
+    }
+
+    pub fn local_to_world(&self, a: &Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+}
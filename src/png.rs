@@ -0,0 +1,355 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+
+use crate::inflate::zlib_decompress;
+
+// PNG reader for every standard colour type and bit depth, with palettes, transparency and Adam7 interlacing.
+// https://www.w3.org/TR/png/
+
+pub const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+// Adam7 passes as (x start, y start, x step, y step).
+const ADAM7: [(usize, usize, usize, usize); 7] = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+
+// Colour types.
+const GREYSCALE: u8 = 0;
+const TRUE_COLOR: u8 = 2;
+const INDEXED: u8 = 3;
+const GREYSCALE_ALPHA: u8 = 4;
+const TRUE_COLOR_ALPHA: u8 = 6;
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg.to_string())
+}
+
+fn u32_be(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+}
+
+fn u16_be(bytes: &[u8], pos: usize) -> u16 {
+    u16::from_be_bytes([bytes[pos], bytes[pos + 1]])
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p: i16 = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+}
+
+// Reverses the per scanline filter in place, `previous` is the unfiltered scanline above, zeros for the first.
+fn unfilter(filter: u8, line: &mut [u8], previous: &[u8], bytes_per_pixel: usize) -> Result<(), Error> {
+    for i in 0..line.len() {
+        let a: u8 = if i >= bytes_per_pixel { line[i - bytes_per_pixel] } else { 0 };
+        let b: u8 = previous[i];
+        let c: u8 = if i >= bytes_per_pixel { previous[i - bytes_per_pixel] } else { 0 };
+        line[i] = line[i].wrapping_add(match filter {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            4 => paeth(a, b, c),
+            _ => return Err(invalid("Bad PNG filter type."))
+        });
+    }
+    Ok(())
+}
+
+// The `index`th sample of `depth` bits in an unfiltered scanline, samples below 8 bits are packed high bit first.
+fn sample(line: &[u8], index: usize, depth: u8) -> u16 {
+    match depth {
+        16 => u16_be(line, index * 2),
+        8 => line[index] as u16,
+        _ => {
+            let bit: usize = index * depth as usize;
+            let shift: usize = 8 - depth as usize - bit % 8;
+            ((line[bit / 8] >> shift) & ((1u8 << depth) - 1)) as u16
+        }
+    }
+}
+
+struct PngHeader {
+    width: usize,
+    height: usize,
+    depth: u8,
+    color_type: u8,
+    interlaced: bool
+}
+
+impl PngHeader {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            TRUE_COLOR => 3,
+            GREYSCALE_ALPHA => 2,
+            TRUE_COLOR_ALPHA => 4,
+            _ => 1
+        }
+    }
+}
+
+// Pixels are returned as RGBA in [0, 1], top row first.  `out_alpha` is set when the image has an alpha channel
+// or transparency chunk.
+pub fn read_png_file(file_path: &PathBuf, out_image_data: &mut Vec<[f32; 4]>, out_width: &mut usize, out_height: &mut usize, out_alpha: &mut bool) -> Result<(), Error> {
+    let file: Vec<u8> = fs::read(file_path)?;
+    if file.len() < PNG_SIGNATURE.len() || file[0..8] != PNG_SIGNATURE {
+        return Err(invalid("Not a PNG file."));
+    }
+
+    let mut header: Option<PngHeader> = None;
+    let mut palette: Vec<[f32; 4]> = Vec::new();
+    let mut transparency: Option<Vec<u8>> = None;
+    let mut compressed: Vec<u8> = Vec::new();
+
+    let mut pos: usize = PNG_SIGNATURE.len();
+    loop {
+        if pos + 8 > file.len() {
+            return Err(invalid("PNG file is truncated."));
+        }
+        let length: usize = u32_be(&file, pos) as usize;
+        let kind: &[u8] = &file[pos + 4..pos + 8];
+        let data: &[u8] = file.get(pos + 8..pos + 8 + length).ok_or_else(|| invalid("PNG file is truncated."))?;
+        // Skips the data and the CRC, which isn't checked.
+        pos += 12 + length;
+
+        match kind {
+            b"IHDR" => {
+                if length < 13 {
+                    return Err(invalid("Bad PNG header."));
+                }
+                let h: PngHeader = PngHeader {
+                    width: u32_be(data, 0) as usize,
+                    height: u32_be(data, 4) as usize,
+                    depth: data[8],
+                    color_type: data[9],
+                    interlaced: data[12] == 1
+                };
+                let valid: bool = match h.color_type {
+                    GREYSCALE => matches!(h.depth, 1 | 2 | 4 | 8 | 16),
+                    INDEXED => matches!(h.depth, 1 | 2 | 4 | 8),
+                    TRUE_COLOR | GREYSCALE_ALPHA | TRUE_COLOR_ALPHA => matches!(h.depth, 8 | 16),
+                    _ => false
+                };
+                if !valid || data[10] != 0 || data[11] != 0 || data[12] > 1 {
+                    return Err(invalid("Unsupported PNG colour type, bit depth or method."));
+                }
+                if h.width == 0 || h.height == 0 {
+                    return Err(invalid("PNG image has no pixels."));
+                }
+                header = Some(h);
+            }
+            b"PLTE" => {
+                palette = data.chunks_exact(3).map(|c: &[u8]| -> [f32; 4] {
+                    [c[0] as f32 / 255.0, c[1] as f32 / 255.0, c[2] as f32 / 255.0, 1.0]
+                }).collect();
+            }
+            b"tRNS" => transparency = Some(data.to_vec()),
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {
+                // Unknown critical chunks change how the image decodes, ancillary ones can be skipped.
+                if kind[0] & 0x20 == 0 {
+                    return Err(invalid("Unsupported critical PNG chunk."));
+                }
+            }
+        }
+    }
+
+    let header: PngHeader = header.ok_or_else(|| invalid("PNG has no header."))?;
+    if header.color_type == INDEXED && palette.is_empty() {
+        return Err(invalid("Indexed PNG has no palette."));
+    }
+    let raw: Vec<u8> = zlib_decompress(&compressed)?;
+
+    let (width, height) = (header.width, header.height);
+    let channels: usize = header.channels();
+    let depth: u8 = header.depth;
+    let bits_per_pixel: usize = channels * depth as usize;
+    let bytes_per_pixel: usize = bits_per_pixel.div_ceil(8);
+    let max_value: f32 = ((1u32 << depth) - 1) as f32;
+
+    // Indexed images carry alpha per palette entry, the others a single transparent colour in sample units.
+    if let (INDEXED, Some(alphas)) = (header.color_type, &transparency) {
+        for (entry, alpha) in palette.iter_mut().zip(alphas.iter()) {
+            entry[3] = *alpha as f32 / 255.0;
+        }
+    }
+    let key: Option<[u16; 3]> = match (header.color_type, &transparency) {
+        (GREYSCALE, Some(t)) if t.len() >= 2 => Some([u16_be(t, 0); 3]),
+        (TRUE_COLOR, Some(t)) if t.len() >= 6 => Some([u16_be(t, 0), u16_be(t, 2), u16_be(t, 4)]),
+        _ => None
+    };
+
+    out_image_data.clear();
+    out_image_data.resize(width * height, [0.0; 4]);
+
+    let passes: Vec<(usize, usize, usize, usize)> = if header.interlaced { ADAM7.to_vec() } else { vec![(0, 0, 1, 1)] };
+    let mut pos: usize = 0;
+    for (x0, y0, dx, dy) in passes {
+        let pass_width: usize = (width + dx - 1 - x0) / dx;
+        let pass_height: usize = (height + dy - 1 - y0) / dy;
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+
+        let stride: usize = (pass_width * bits_per_pixel).div_ceil(8);
+        let mut previous: Vec<u8> = vec![0; stride];
+        for py in 0..pass_height {
+            let filter: u8 = *raw.get(pos).ok_or_else(|| invalid("PNG image data is truncated."))?;
+            let mut line: Vec<u8> = raw.get(pos + 1..pos + 1 + stride).ok_or_else(|| invalid("PNG image data is truncated."))?.to_vec();
+            pos += 1 + stride;
+            unfilter(filter, &mut line, &previous, bytes_per_pixel)?;
+
+            for px in 0..pass_width {
+                let s = |c: usize| -> u16 { sample(&line, px * channels + c, depth) };
+                let value = |c: usize| -> f32 { s(c) as f32 / max_value };
+                let rgba: [f32; 4] = match header.color_type {
+                    GREYSCALE => {
+                        let alpha: f32 = if key.is_some_and(|k: [u16; 3]| -> bool { k[0] == s(0) }) { 0.0 } else { 1.0 };
+                        [value(0), value(0), value(0), alpha]
+                    }
+                    TRUE_COLOR => {
+                        let alpha: f32 = if key.is_some_and(|k: [u16; 3]| -> bool { k == [s(0), s(1), s(2)] }) { 0.0 } else { 1.0 };
+                        [value(0), value(1), value(2), alpha]
+                    }
+                    INDEXED => *palette.get(s(0) as usize).ok_or_else(|| invalid("PNG palette index out of range."))?,
+                    GREYSCALE_ALPHA => [value(0), value(0), value(0), value(1)],
+                    _ => [value(0), value(1), value(2), value(3)]
+                };
+                out_image_data[(y0 + py * dy) * width + x0 + px * dx] = rgba;
+            }
+            previous = line;
+        }
+    }
+
+    *out_width = width;
+    *out_height = height;
+    *out_alpha = transparency.is_some() || header.color_type == GREYSCALE_ALPHA || header.color_type == TRUE_COLOR_ALPHA;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The fixtures were written with every row filter in turn, and the image data split over two IDAT chunks.
+    // Sample c of the pixel at (x, y) is (37 x + 71 y + 53 c + 11) mod 2^depth, and palette entry i is
+    // (3 i, 5 i + 1, 7 i + 2) mod 256.
+    fn value(x: usize, y: usize, c: usize, depth: u32) -> f32 {
+        let max: usize = (1 << depth) - 1;
+        ((x * 37 + y * 71 + c * 53 + 11) & max) as f32 / max as f32
+    }
+
+    fn palette(i: usize) -> [f32; 3] {
+        [((i * 3) % 256) as f32 / 255.0, ((i * 5 + 1) % 256) as f32 / 255.0, ((i * 7 + 2) % 256) as f32 / 255.0]
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+    }
+
+    fn check(name: &str, width: usize, height: usize, alpha: bool, expected: &dyn Fn(usize, usize) -> [f32; 4]) {
+        let mut data: Vec<[f32; 4]> = Vec::new();
+        let (mut w, mut h, mut a) = (0, 0, false);
+        read_png_file(&fixture(name), &mut data, &mut w, &mut h, &mut a).unwrap();
+        assert_eq!((w, h, a), (width, height, alpha), "{}", name);
+        for y in 0..height {
+            for x in 0..width {
+                let want: [f32; 4] = expected(x, y);
+                let got: [f32; 4] = data[y * width + x];
+                assert!((0..4).all(|c: usize| -> bool { (got[c] - want[c]).abs() < 1e-6 }), "{} at ({}, {}): {:?} != {:?}", name, x, y, got, want);
+            }
+        }
+    }
+
+    // The 1x1 image and the 9x7 interlaced one, which leaves some Adam7 passes narrower than others.
+    fn check_both(name: &str, alpha: bool, expected: &dyn Fn(usize, usize) -> [f32; 4]) {
+        check(&format!("{}_1x1.png", name), 1, 1, alpha, expected);
+        check(&format!("{}_interlaced.png", name), 9, 7, alpha, expected);
+    }
+
+    #[test]
+    fn greyscale() {
+        check_both("grey1", false, &|x: usize, y: usize| -> [f32; 4] {
+            let g: f32 = value(x, y, 0, 1);
+            [g, g, g, 1.0]
+        });
+        check_both("grey16", false, &|x: usize, y: usize| -> [f32; 4] {
+            let g: f32 = value(x, y, 0, 16);
+            [g, g, g, 1.0]
+        });
+    }
+
+    #[test]
+    fn greyscale_with_transparent_key() {
+        // The key is the grey of the top left pixel.
+        check_both("grey8", true, &|x: usize, y: usize| -> [f32; 4] {
+            let g: f32 = value(x, y, 0, 8);
+            [g, g, g, if g == value(0, 0, 0, 8) { 0.0 } else { 1.0 }]
+        });
+    }
+
+    #[test]
+    fn true_color() {
+        check_both("rgb8", false, &|x: usize, y: usize| -> [f32; 4] {
+            [value(x, y, 0, 8), value(x, y, 1, 8), value(x, y, 2, 8), 1.0]
+        });
+        // The key is the colour of the top left pixel.
+        check_both("rgb16", true, &|x: usize, y: usize| -> [f32; 4] {
+            let key: bool = (0..3).all(|c: usize| -> bool { value(x, y, c, 16) == value(0, 0, c, 16) });
+            [value(x, y, 0, 16), value(x, y, 1, 16), value(x, y, 2, 16), if key { 0.0 } else { 1.0 }]
+        });
+    }
+
+    #[test]
+    fn indexed() {
+        check_both("indexed8", false, &|x: usize, y: usize| -> [f32; 4] {
+            let c: [f32; 3] = palette((value(x, y, 0, 8) * 255.0).round() as usize);
+            [c[0], c[1], c[2], 1.0]
+        });
+        // Four entries, the first three with alphas 0, 1/3 and 2/3.
+        check_both("indexed2", true, &|x: usize, y: usize| -> [f32; 4] {
+            let i: usize = (value(x, y, 0, 2) * 3.0).round() as usize;
+            let c: [f32; 3] = palette(i);
+            [c[0], c[1], c[2], [0.0, 85.0 / 255.0, 170.0 / 255.0, 1.0][i]]
+        });
+    }
+
+    #[test]
+    fn with_alpha_channel() {
+        check_both("grey_alpha8", true, &|x: usize, y: usize| -> [f32; 4] {
+            let g: f32 = value(x, y, 0, 8);
+            [g, g, g, value(x, y, 1, 8)]
+        });
+        check_both("rgba8", true, &|x: usize, y: usize| -> [f32; 4] {
+            [value(x, y, 0, 8), value(x, y, 1, 8), value(x, y, 2, 8), value(x, y, 3, 8)]
+        });
+        check_both("rgba16", true, &|x: usize, y: usize| -> [f32; 4] {
+            [value(x, y, 0, 16), value(x, y, 1, 16), value(x, y, 2, 16), value(x, y, 3, 16)]
+        });
+    }
+
+    #[test]
+    fn broken_files_are_errors() {
+        let file: Vec<u8> = fs::read(fixture("rgb8_interlaced.png")).unwrap();
+        let path: PathBuf = std::env::temp_dir().join(format!("raytracing_weekend_png_{}.png", std::process::id()));
+        let read = |bytes: &[u8]| -> Result<(), Error> {
+            fs::write(&path, bytes).unwrap();
+            let mut data: Vec<[f32; 4]> = Vec::new();
+            let (mut w, mut h, mut a) = (0, 0, false);
+            read_png_file(&path, &mut data, &mut w, &mut h, &mut a)
+        };
+        assert!(read(&file).is_ok());
+        assert!(read(&file[..file.len() / 2]).is_err());
+        assert!(read(&file[1..]).is_err());
+        // The first IDAT renamed, to an end with no image data and then to an unknown critical chunk.
+        let idat: usize = PNG_SIGNATURE.len() + 12 + 13 + 4;
+        let mut renamed: Vec<u8> = file.clone();
+        renamed[idat..idat + 4].copy_from_slice(b"IEND");
+        assert!(read(&renamed).is_err());
+        renamed[idat..idat + 4].copy_from_slice(b"IDAX");
+        assert!(read(&renamed).is_err());
+        fs::remove_file(&path).unwrap();
+        assert!(unfilter(5, &mut [0; 3], &[0; 3], 1).is_err());
+    }
+}
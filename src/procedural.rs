@@ -0,0 +1,395 @@
+use std::sync::Arc;
+
+use crate::common::saturate;
+use crate::hittable::HitRecord;
+use crate::noise::{cell_color, fbm, hash3, worley, WorleySample};
+use crate::texture::{Texture, UvFootprint};
+use crate::vec3::{dot, lerp, Color, Point3, Vec3};
+
+// Procedural patterns, evaluated at texture coordinates or at the hit position, and nodes for combining
+// textures.  Called through `value`, the patterns take (u, v, w) as the point to evaluate.
+
+// Coordinates a procedural texture is evaluated in.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum TextureSpace {
+    // The surface's texture coordinates.
+    #[default]
+    Uv,
+    // The hit point before instance transforms, so the pattern moves with the object.
+    Object,
+    // The hit point in the scene, so objects look carved from one block.
+    World
+}
+
+fn texture_point(space: TextureSpace, rec: &HitRecord) -> Point3 {
+    match space {
+        TextureSpace::Uv => rec.uvw,
+        TextureSpace::Object => rec.object_p,
+        TextureSpace::World => rec.p
+    }
+}
+
+// A pattern's `value` at the hit's coordinates in `space`.
+fn value_in_space(texture: &dyn Texture, space: TextureSpace, rec: &HitRecord) -> Color {
+    let p: Point3 = texture_point(space, rec);
+    texture.value(p.x(), p.y(), p.z())
+}
+
+fn grey(value: f64) -> Color {
+    Color::new(value, value, value)
+}
+
+// fBm noise as a grey value in [0, 1].
+#[derive(Copy, Clone)]
+pub struct NoiseTexture {
+    pub space: TextureSpace,
+    pub scale: f64,
+    pub octaves: u32,
+    pub lacunarity: f64,
+    pub gain: f64
+}
+
+impl NoiseTexture {
+    pub fn new(space: TextureSpace, scale: f64, octaves: u32) -> NoiseTexture {
+        NoiseTexture { space: space, scale: scale, octaves: octaves, lacunarity: 2.0, gain: 0.5 }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, u:f64, v:f64, w:f64) -> Color {
+        let p: Point3 = Point3::new(u, v, w) * self.scale;
+        grey(saturate(0.5 + 0.5 * fbm(&p, self.octaves, self.lacunarity, self.gain)))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        value_in_space(self, self.space, rec)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum VoronoiOutput {
+    // Distance to the nearest cell centre.
+    #[default]
+    Distance,
+    // Distance to the nearest cell edge, dark lines between cells.
+    Edge,
+    // A random colour per cell.
+    Cell
+}
+
+// Worley cells.
+#[derive(Copy, Clone)]
+pub struct VoronoiTexture {
+    pub space: TextureSpace,
+    pub scale: f64,
+    // 0 lines the cells up in a grid, 1 scatters them fully.
+    pub jitter: f64,
+    pub output: VoronoiOutput
+}
+
+impl VoronoiTexture {
+    pub fn new(space: TextureSpace, scale: f64, output: VoronoiOutput) -> VoronoiTexture {
+        VoronoiTexture { space: space, scale: scale, jitter: 1.0, output: output }
+    }
+}
+
+impl Texture for VoronoiTexture {
+    fn value(&self, u:f64, v:f64, w:f64) -> Color {
+        let sample: WorleySample = worley(&(Point3::new(u, v, w) * self.scale), self.jitter);
+        match self.output {
+            VoronoiOutput::Distance => grey(saturate(sample.f1)),
+            VoronoiOutput::Edge => grey(saturate(sample.f2 - sample.f1)),
+            VoronoiOutput::Cell => cell_color(sample.cell)
+        }
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        value_in_space(self, self.space, rec)
+    }
+}
+
+// Growth rings around the y axis, wobbled by noise.
+#[derive(Copy, Clone)]
+pub struct WoodTexture {
+    pub space: TextureSpace,
+    pub scale: f64,
+    pub light: Color,
+    pub dark: Color,
+    // Rings per unit radius.
+    pub rings: f64,
+    // How far noise pushes the rings around, in rings.
+    pub distortion: f64
+}
+
+impl WoodTexture {
+    pub fn new(space: TextureSpace, light: Color, dark: Color, rings: f64) -> WoodTexture {
+        WoodTexture { space: space, scale: 1.0, light: light, dark: dark, rings: rings, distortion: 1.0 }
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, u:f64, v:f64, w:f64) -> Color {
+        let p: Point3 = Point3::new(u, v, w) * self.scale;
+        // Stretch the noise along the trunk so it reads as grain.
+        let grain: f64 = fbm(&Point3::new(p.x() * 2.0, p.y() * 0.25, p.z() * 2.0), 4, 2.0, 0.5);
+        let radius: f64 = p.x().hypot(p.z()) * self.rings + grain * self.distortion;
+        // Sharp dark latewood at the end of each ring fading into light earlywood.
+        let t: f64 = (radius - radius.floor()).powi(3);
+        lerp(&self.light, &self.dark, t)
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        value_in_space(self, self.space, rec)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum GradientKind {
+    // Along the line from start to end.
+    #[default]
+    Linear,
+    // Outwards from start, reaching the end colour at the distance to end.
+    Radial
+}
+
+// Blend from `color_a` at `start` to `color_b` at `end`, clamped past either.
+#[derive(Copy, Clone)]
+pub struct GradientTexture {
+    pub space: TextureSpace,
+    pub kind: GradientKind,
+    pub start: Point3,
+    pub end: Point3,
+    pub color_a: Color,
+    pub color_b: Color
+}
+
+impl GradientTexture {
+    pub fn new(space: TextureSpace, kind: GradientKind, start: Point3, end: Point3, a: Color, b: Color) -> GradientTexture {
+        GradientTexture { space: space, kind: kind, start: start, end: end, color_a: a, color_b: b }
+    }
+}
+
+impl Texture for GradientTexture {
+    fn value(&self, u:f64, v:f64, w:f64) -> Color {
+        let axis: Vec3 = self.end - self.start;
+        let offset: Vec3 = Point3::new(u, v, w) - self.start;
+        let t: f64 = match self.kind {
+            GradientKind::Linear => dot(&offset, &axis) / axis.length_squared(),
+            GradientKind::Radial => offset.length() / axis.length()
+        };
+        lerp(&self.color_a, &self.color_b, saturate(t))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        value_in_space(self, self.space, rec)
+    }
+}
+
+// Running bond bricks over the first two coordinates, rows along the first.
+#[derive(Copy, Clone)]
+pub struct BrickTexture {
+    pub space: TextureSpace,
+    pub brick_width: f64,
+    pub brick_height: f64,
+    pub mortar_width: f64,
+    // Fraction of a brick every other row is shifted by.
+    pub row_offset: f64,
+    pub brick_color: Color,
+    pub mortar_color: Color,
+    // How much each brick's colour varies, 0 for none.
+    pub variation: f64
+}
+
+impl BrickTexture {
+    pub fn new(space: TextureSpace, brick_width: f64, brick_height: f64, brick_color: Color, mortar_color: Color) -> BrickTexture {
+        BrickTexture {
+            space: space,
+            brick_width: brick_width,
+            brick_height: brick_height,
+            mortar_width: 0.1 * brick_height,
+            row_offset: 0.5,
+            brick_color: brick_color,
+            mortar_color: mortar_color,
+            variation: 0.2
+        }
+    }
+}
+
+impl Texture for BrickTexture {
+    fn value(&self, u:f64, v:f64, _w:f64) -> Color {
+        let row: f64 = (v / self.brick_height).floor();
+        let shift: f64 = if (row as i64).rem_euclid(2) == 1 { self.row_offset * self.brick_width } else { 0.0 };
+        let column: f64 = ((u + shift) / self.brick_width).floor();
+        let x: f64 = u + shift - column * self.brick_width;
+        let y: f64 = v - row * self.brick_height;
+
+        // Half the mortar on each side of a brick.
+        let half: f64 = 0.5 * self.mortar_width;
+        if x < half || x > self.brick_width - half || y < half || y > self.brick_height - half {
+            return self.mortar_color;
+        }
+        let tint: f64 = (hash3(column as i64, row as i64, 0) as f64 / u32::MAX as f64 - 0.5) * 2.0 * self.variation;
+        self.brick_color * (1.0 + tint)
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        value_in_space(self, self.space, rec)
+    }
+}
+
+// Multiplies a texture by another, a solid value or colour for a plain scale.
+pub struct ScaleTexture {
+    pub texture: Arc<dyn Texture + Sync>,
+    pub scale: Arc<dyn Texture + Sync>
+}
+
+impl ScaleTexture {
+    pub fn new(texture: &Arc<dyn Texture + Sync>, scale: &Arc<dyn Texture + Sync>) -> ScaleTexture {
+        ScaleTexture { texture: texture.clone(), scale: scale.clone() }
+    }
+}
+
+impl Texture for ScaleTexture {
+    fn value(&self, u:f64, v:f64, w:f64) -> Color {
+        self.value_filtered(u, v, w, &UvFootprint::default())
+    }
+
+    fn value_filtered(&self, u:f64, v:f64, w:f64, footprint: &UvFootprint) -> Color {
+        self.texture.value_filtered(u, v, w, footprint) * self.scale.value_filtered(u, v, w, footprint)
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.texture.value_at(rec) * self.scale.value_at(rec)
+    }
+}
+
+// Blends from `a` to `b` by the red channel of `factor`.
+pub struct MixTexture {
+    pub a: Arc<dyn Texture + Sync>,
+    pub b: Arc<dyn Texture + Sync>,
+    pub factor: Arc<dyn Texture + Sync>
+}
+
+impl MixTexture {
+    pub fn new(a: &Arc<dyn Texture + Sync>, b: &Arc<dyn Texture + Sync>, factor: &Arc<dyn Texture + Sync>) -> MixTexture {
+        MixTexture { a: a.clone(), b: b.clone(), factor: factor.clone() }
+    }
+}
+
+impl Texture for MixTexture {
+    fn value(&self, u:f64, v:f64, w:f64) -> Color {
+        self.value_filtered(u, v, w, &UvFootprint::default())
+    }
+
+    fn value_filtered(&self, u:f64, v:f64, w:f64, footprint: &UvFootprint) -> Color {
+        let t: f64 = saturate(self.factor.value_filtered(u, v, w, footprint).r());
+        lerp(&self.a.value_filtered(u, v, w, footprint), &self.b.value_filtered(u, v, w, footprint), t)
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        let t: f64 = saturate(self.factor.value_at(rec).r());
+        lerp(&self.a.value_at(rec), &self.b.value_at(rec), t)
+    }
+}
+
+// Maps the red channel of `input` through colour stops, interpolating between them and holding the end
+// colours past the first and last.
+pub struct ColorRamp {
+    pub input: Arc<dyn Texture + Sync>,
+    // Positions and colours, sorted by position.
+    pub stops: Vec<(f64, Color)>
+}
+
+impl ColorRamp {
+    pub fn new(input: &Arc<dyn Texture + Sync>, stops: &[(f64, Color)]) -> ColorRamp {
+        let mut stops: Vec<(f64, Color)> = stops.to_vec();
+        stops.sort_by(|a: &(f64, Color), b: &(f64, Color)| -> std::cmp::Ordering { a.0.total_cmp(&b.0) });
+        ColorRamp { input: input.clone(), stops: stops }
+    }
+
+    pub fn map(&self, t: f64) -> Color {
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return grey(t)
+        };
+        if t <= first.0 {
+            return first.1;
+        }
+        for pair in self.stops.windows(2) {
+            let ((t0, c0), (t1, c1)) = (pair[0], pair[1]);
+            if t <= t1 {
+                let s: f64 = if t1 > t0 { (t - t0) / (t1 - t0) } else { 1.0 };
+                return lerp(&c0, &c1, s);
+            }
+        }
+        last.1
+    }
+}
+
+impl Texture for ColorRamp {
+    fn value(&self, u:f64, v:f64, w:f64) -> Color {
+        self.value_filtered(u, v, w, &UvFootprint::default())
+    }
+
+    fn value_filtered(&self, u:f64, v:f64, w:f64, footprint: &UvFootprint) -> Color {
+        self.map(self.input.value_filtered(u, v, w, footprint).r())
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.map(self.input.value_at(rec).r())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::solid_value;
+
+    fn assert_color(got: Color, want: Color) {
+        assert!((got - want).length() < 1e-9, "{} != {}", got, want);
+    }
+
+    #[test]
+    fn ramp_holds_its_end_stops() {
+        let red: Color = Color::new(1.0, 0.0, 0.0);
+        let blue: Color = Color::new(0.0, 0.0, 1.0);
+        let ramp: ColorRamp = ColorRamp::new(&solid_value(0.0), &[(0.75, blue), (0.25, red)]);
+        assert_color(ramp.map(-10.0), red);
+        assert_color(ramp.map(0.25), red);
+        assert_color(ramp.map(0.5), Color::new(0.5, 0.0, 0.5));
+        assert_color(ramp.map(0.75), blue);
+        assert_color(ramp.map(10.0), blue);
+        assert_color(ColorRamp::new(&solid_value(0.0), &[]).map(0.3), grey(0.3));
+    }
+
+    #[test]
+    fn bricks_and_mortar() {
+        let brick: Color = Color::new(0.6, 0.2, 0.1);
+        let mortar: Color = Color::new(0.8, 0.8, 0.8);
+        let mut bricks: BrickTexture = BrickTexture::new(TextureSpace::World, 1.0, 0.5, brick, mortar);
+        bricks.variation = 0.0;
+        // Mortar is 0.05 wide, half of it along each edge of a brick.
+        assert_color(bricks.value(0.5, 0.25, 0.0), brick);
+        assert_color(bricks.value(0.01, 0.25, 0.0), mortar);
+        assert_color(bricks.value(0.99, 0.25, 0.0), mortar);
+        assert_color(bricks.value(0.5, 0.01, 0.0), mortar);
+        assert_color(bricks.value(0.5, 0.49, 0.0), mortar);
+        assert_color(bricks.value(-0.5, -0.25, 0.0), mortar);
+        // Every other row is shifted by half a brick, moving the joints to the middle of the row below.
+        assert_color(bricks.value(0.5, 0.75, 0.0), mortar);
+        assert_color(bricks.value(0.01, 0.75, 0.0), brick);
+
+        // Variation tints whole bricks, not the mortar.
+        bricks.variation = 0.2;
+        assert_color(bricks.value(0.3, 0.25, 0.0), bricks.value(0.7, 0.2, 0.0));
+        assert_color(bricks.value(0.01, 0.25, 0.0), mortar);
+
+        // `value_at` reads the coordinates of its space.
+        let mut rec: HitRecord = HitRecord { p: Point3::new(0.5, 0.25, 0.0), uvw: Point3::new(0.01, 0.25, 0.0), ..HitRecord::default() };
+        assert_color(bricks.value_at(&rec), bricks.value(0.5, 0.25, 0.0));
+        bricks.space = TextureSpace::Uv;
+        assert_color(bricks.value_at(&rec), mortar);
+        rec.uvw = rec.p;
+        assert_color(bricks.value_at(&rec), bricks.value(0.5, 0.25, 0.0));
+    }
+}
@@ -0,0 +1,211 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::hittable::HitRecord;
+use crate::onb::ONB;
+use crate::procedural::TextureSpace;
+use crate::texture::{Texture, UvFootprint};
+use crate::vec3::{Color, Point3, Vec3};
+
+// Texture coordinates computed from the hit position and normal instead of the primitive's own uvs, so
+// any texture can be laid over any shape.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    // Straight down the axis onto the plane across it.
+    Planar,
+    // Wrapped around the axis, u around it and v along it.
+    Cylindrical,
+    // Longitude and latitude about the axis, v from the bottom pole to the top, like sphere uvs.
+    Spherical,
+    // Planar from the six faces of a box, picked by the normal.  The three axes blend by the normal's
+    // components raised to `sharpness`, infinity gives hard edges.
+    Box { sharpness: f64 }
+}
+
+// Texture seen through a projection.
+pub struct ProjectedTexture {
+    pub texture: Arc<dyn Texture + Sync>,
+    pub projection: Projection,
+    // Object space moves with the object, world space runs on across objects.  Uv space is taken as object space.
+    pub space: TextureSpace,
+    pub origin: Point3,
+    // Up for the projection: what planar projects along and what cylinders and spheres wrap around.
+    pub axis: Vec3,
+    // Units per texture repeat, except around cylinders and spheres which always wrap once.
+    pub scale: f64
+}
+
+impl ProjectedTexture {
+    pub fn new(texture: &Arc<dyn Texture + Sync>, projection: Projection, space: TextureSpace) -> ProjectedTexture {
+        ProjectedTexture {
+            texture: texture.clone(),
+            projection: projection,
+            space: space,
+            origin: Point3::zero(),
+            axis: Vec3::new(0.0, 1.0, 0.0),
+            scale: 1.0
+        }
+    }
+
+    // Position, outward normal and the position change across a pixel, in the projection's space.
+    fn surface(&self, rec: &HitRecord) -> (Point3, Vec3, Vec3, Vec3) {
+        match self.space {
+            TextureSpace::Uv | TextureSpace::Object => (rec.object_p, rec.object_normal, rec.object_dpdx, rec.object_dpdy),
+            TextureSpace::World => {
+                let normal: Vec3 = if rec.front_face { rec.normal } else { -rec.normal };
+                let f: &UvFootprint = &rec.footprint;
+                (rec.p, normal, rec.dpdu * f.dudx + rec.dpdv * f.dvdx, rec.dpdu * f.dudy + rec.dpdv * f.dvdy)
+            }
+        }
+    }
+
+    // Planar, cylindrical and spherical coordinates of a point in the projection frame, z up.
+    fn map(&self, l: &Vec3) -> (f64, f64) {
+        let around: f64 = (l.y().atan2(l.x()) + PI) / (2.0 * PI);
+        match self.projection {
+            Projection::Planar => (l.x() / self.scale, l.y() / self.scale),
+            Projection::Cylindrical => (around, l.z() / self.scale),
+            Projection::Spherical => {
+                let r: f64 = l.length();
+                let v: f64 = if r > 0.0 { (-l.z() / r).clamp(-1.0, 1.0).acos() / PI } else { 0.5 };
+                (around, v)
+            }
+            Projection::Box { .. } => (0.0, 0.0)
+        }
+    }
+
+    // Coordinates on a box face, seen from outside with z up on the sides.
+    fn box_face(&self, axis: usize, positive: bool, l: &Vec3) -> (f64, f64) {
+        let (u, v) = match (axis, positive) {
+            (0, true) => (l.y(), l.z()),
+            (0, false) => (-l.y(), l.z()),
+            (1, true) => (-l.x(), l.z()),
+            (1, false) => (l.x(), l.z()),
+            (_, true) => (l.x(), l.y()),
+            (_, false) => (l.x(), -l.y())
+        };
+        (u / self.scale, v / self.scale)
+    }
+
+    // The hit with the projected coordinates, and the footprint from projecting the pixel's neighbours the
+    // same way.
+    fn projected(&self, rec: &HitRecord, l: &Vec3, ldx: &Vec3, ldy: &Vec3, map: &dyn Fn(&Vec3) -> (f64, f64)) -> HitRecord {
+        let (u, v) = map(l);
+        let (ux, vx) = map(&(*l + *ldx));
+        let (uy, vy) = map(&(*l + *ldy));
+        // Around cylinders and spheres u wraps, neighbours across the seam are a small step the other way.
+        let wraps: bool = matches!(self.projection, Projection::Cylindrical | Projection::Spherical);
+        let du = |d: f64| -> f64 { if wraps { d - d.round() } else { d } };
+
+        let mut projected: HitRecord = rec.clone();
+        projected.uvw = Vec3::new(u, v, 0.0);
+        projected.footprint = UvFootprint { dudx: du(ux - u), dvdx: vx - v, dudy: du(uy - u), dvdy: vy - v };
+        projected
+    }
+
+    // Calls `f` with each projection of the hit and its weight, one for box projections per face the normal
+    // blends across.
+    fn for_each_projection(&self, rec: &HitRecord, f: &mut dyn FnMut(f64, &HitRecord)) {
+        let (p, n, dpdx, dpdy) = self.surface(rec);
+        let frame: ONB = ONB::new(&self.axis);
+        let l: Vec3 = frame.world_to_local(&(p - self.origin));
+        let ldx: Vec3 = frame.world_to_local(&dpdx);
+        let ldy: Vec3 = frame.world_to_local(&dpdy);
+
+        match self.projection {
+            Projection::Box { sharpness } => {
+                let ln: Vec3 = frame.world_to_local(&n);
+                let weights: [f64; 3] = box_weights(&ln, sharpness);
+                for axis in 0..3 {
+                    if weights[axis] == 0.0 {
+                        continue;
+                    }
+                    let positive: bool = ln[axis] >= 0.0;
+                    let face = |q: &Vec3| -> (f64, f64) { self.box_face(axis, positive, q) };
+                    f(weights[axis], &self.projected(rec, &l, &ldx, &ldy, &face));
+                }
+            }
+            _ => f(1.0, &self.projected(rec, &l, &ldx, &ldy, &|q: &Vec3| -> (f64, f64) { self.map(q) }))
+        }
+    }
+}
+
+// Triplanar weights from the normal's components, summing to one.  Faces too faint to matter are dropped.
+fn box_weights(n: &Vec3, sharpness: f64) -> [f64; 3] {
+    let a: [f64; 3] = [n.x().abs(), n.y().abs(), n.z().abs()];
+    let largest: usize = if a[0] >= a[1] && a[0] >= a[2] { 0 } else if a[1] >= a[2] { 1 } else { 2 };
+    let mut weights: [f64; 3] = [0.0; 3];
+    if sharpness.is_infinite() || a[largest] == 0.0 {
+        weights[largest] = 1.0;
+        return weights;
+    }
+    for i in 0..3 {
+        weights[i] = (a[i] / a[largest]).powf(sharpness);
+        if weights[i] < 1e-3 {
+            weights[i] = 0.0;
+        }
+    }
+    let total: f64 = weights[0] + weights[1] + weights[2];
+    weights.map(|w: f64| -> f64 { w / total })
+}
+
+impl Texture for ProjectedTexture {
+    // Without a hit there's nothing to project, the coordinates go straight through.
+    fn value(&self, u:f64, v:f64, w:f64) -> Color {
+        self.texture.value(u, v, w)
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        let mut color: Color = Color::zero();
+        self.for_each_projection(rec, &mut |weight: f64, projected: &HitRecord| { color += self.texture.value_at(projected) * weight; });
+        color
+    }
+
+    fn alpha(&self, u:f64, v:f64, w:f64) -> f64 {
+        self.texture.alpha(u, v, w)
+    }
+
+    fn alpha_at(&self, rec: &HitRecord) -> f64 {
+        let mut alpha: f64 = 0.0;
+        self.for_each_projection(rec, &mut |weight: f64, projected: &HitRecord| { alpha += self.texture.alpha_at(projected) * weight; });
+        alpha
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::normalize;
+
+    // Shows the coordinates it was looked up at, with alpha from u.
+    struct Coordinates;
+
+    impl Texture for Coordinates {
+        fn value(&self, u:f64, v:f64, w:f64) -> Color {
+            Color::new(u, v, w)
+        }
+
+        fn alpha(&self, u:f64, _v:f64, _w:f64) -> f64 {
+            u
+        }
+    }
+
+    #[test]
+    fn alpha_uses_the_projected_coordinates() {
+        let rec: HitRecord = HitRecord {
+            object_p: Point3::new(0.3, 0.6, -0.45),
+            object_normal: normalize(Vec3::new(0.4, 1.0, -0.7)),
+            uvw: Vec3::new(0.9, 0.1, 0.0),
+            ..HitRecord::default()
+        };
+
+        let coordinates: Arc<dyn Texture + Sync> = Arc::new(Coordinates);
+        for projection in [Projection::Planar, Projection::Cylindrical, Projection::Spherical, Projection::Box { sharpness: 4.0 }] {
+            let projected: ProjectedTexture = ProjectedTexture::new(&coordinates, projection, TextureSpace::Object);
+            let u: f64 = projected.value_at(&rec).x();
+            assert!((projected.alpha_at(&rec) - u).abs() < 1e-12, "{:?}", projection);
+            assert!((u - rec.uvw.x()).abs() > 0.01, "{:?}", projection);
+        }
+    }
+}
@@ -1,46 +1,120 @@
+use std::f64::consts::PI;
 use std::fmt;
 use std::sync::Arc;
 
 use crate::hittable_list::HittableList;
 use crate::interval::*;
 use crate::material::Material;
+use crate::onb::ONB;
 use crate::vec3::*;
 use crate::ray::*;
 use crate::hittable::*;
 use crate::aabb::*;
 
-#[derive(Clone)]
-pub struct Quad {
+// The plane through q spanned by u and v, shared by the flat primitives.  Hits are found by their coordinates
+// along u and v, and each shape decides which of those lie on it.
+#[derive(Copy, Clone)]
+pub struct PlanarFrame {
     pub q: Point3,
     pub u: Vec3,
     pub v: Vec3,
-    pub mat: Option<Arc<dyn Material + Sync>>,
-    pub bbox: AABB,
     pub normal: Vec3,
     pub d: f64,
     pub w: Vec3
 }
 
-impl Quad {
-    pub fn new(q:Point3, u:Vec3, v:Vec3, mat: &Arc<dyn Material + Sync>) -> Quad {
+impl PlanarFrame {
+    pub fn new(q:Point3, u:Vec3, v:Vec3) -> PlanarFrame {
         let n: Vec3 = cross(&u,&v);
         let normal: Vec3 = normalize(n);
-        Quad {
+        PlanarFrame {
             q: q,
             u: u,
             v: v,
-            mat: Some(mat.clone()),
-            bbox: AABB::new(&q, &(q+ u + v)).pad(),
             normal: normal,
             d: dot(&normal, &q),
             w: n / dot(&n, &n)
         }
     }
+
+    // Intersects the ray with the plane.  `is_interior` gets the hit's coordinates along u and v, says whether
+    // it's on the shape and sets the texture coordinates, and dpdu and dpdv when they aren't u and v.
+    pub fn hit(&self, r:&Ray, ray_t: Interval, rec: &mut HitRecord, mat: &Option<Arc<dyn Material + Sync>>, is_interior: impl Fn(f64, f64, &mut HitRecord) -> bool) -> bool {
+        let denom: f64 = dot(&self.normal, &r.direction);
+        if denom.abs() < f64::EPSILON {
+            return false;
+        }
+
+        let t: f64 = (self.d - dot(&self.normal, &r.origin)) / denom;
+
+        if !ray_t.contains(t) {
+            return false;
+        }
+
+        let intersection: Vec3 = r.at(t);
+
+        let planar_hitpt_vector: Vec3 = intersection - self.q;
+        let alpha: f64 = dot(&self.w, &cross(&planar_hitpt_vector, &self.v));
+        let beta: f64 = dot(&self.w, &cross(&self.u, &planar_hitpt_vector));
+
+        // Fill in a candidate, `rec` still holds the closest hit so far if this one is cut out.
+        let mut candidate: HitRecord = HitRecord{..HitRecord::default()};
+        candidate.dpdu = self.u;
+        candidate.dpdv = self.v;
+        if !is_interior(alpha, beta, &mut candidate) {
+            return false;
+        }
+
+        candidate.t = t;
+        candidate.p = intersection;
+        candidate.object_p = intersection;
+        candidate.object_normal = self.normal;
+        (candidate.object_dpdu, candidate.object_dpdv) = (candidate.dpdu, candidate.dpdv);
+        candidate.mat = mat.clone();
+        candidate.set_face_normal(r, &self.normal);
+        candidate.set_differentials(r);
+        if candidate.is_cutout() {
+            return false;
+        }
+
+        *rec = candidate;
+        return true;
+    }
+}
+
+impl fmt::Display for PlanarFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "q:{}, u:{}, v:{}, normal:{}, d:{}, w:{}", self.q, self.u, self.v, self.normal, self.d, self.w)
+    }
+}
+
+// Box around an ellipse with conjugate semi-axes u and v, tight whatever way it faces.
+fn ellipse_bbox(center: &Point3, u: &Vec3, v: &Vec3) -> AABB {
+    let extent: Vec3 = Vec3::new(u.x().hypot(v.x()), u.y().hypot(v.y()), u.z().hypot(v.z()));
+    AABB::new(&(*center - extent), &(*center + extent)).pad()
+}
+
+#[derive(Clone)]
+pub struct Quad {
+    pub plane: PlanarFrame,
+    pub mat: Option<Arc<dyn Material + Sync>>,
+    pub bbox: AABB
+}
+
+impl Quad {
+    pub fn new(q:Point3, u:Vec3, v:Vec3, mat: &Arc<dyn Material + Sync>) -> Quad {
+        Quad {
+            plane: PlanarFrame::new(q, u, v),
+            mat: Some(mat.clone()),
+            // Both diagonals, one alone misses corners of a skewed parallelogram.
+            bbox: (AABB::new(&q, &(q + u + v)) + AABB::new(&(q + u), &(q + v))).pad()
+        }
+    }
 }
 
 impl fmt::Display for Quad {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Quad(q:{}, u:{}, v:{}, normal:{}, d:{}, w:{}, bbox:{})", self.q, self.u, self.v, self.normal, self.d, self.w, self.bbox)
+        write!(f, "Quad({}, bbox:{})", self.plane, self.bbox)
     }
 }
 
@@ -63,7 +137,7 @@ pub fn make_box(a: &Point3, b:&Point3, mat: &Arc<dyn Material + Sync>) -> Hittab
     sides.add_obj(Arc::new(Quad::new(Point3::new(max.x(), min.y(), min.z()), -dx, dy, mat)));
     sides.add_obj(Arc::new(Quad::new(Point3::new(min.x(), min.y(), min.z()), dz, dy, mat)));
     sides.add_obj(Arc::new(Quad::new(Point3::new(min.x(), max.y(), max.z()), dx, -dz, mat)));
-    sides.add_obj(Arc::new(Quad::new(Point3::new(min.x(), min.y(), max.z()), dx, dz, mat)));
+    sides.add_obj(Arc::new(Quad::new(Point3::new(min.x(), min.y(), min.z()), dx, dz, mat)));
 
     sides
 }
@@ -78,35 +152,298 @@ pub fn is_interior(a: f64, b: f64, rec: &mut HitRecord) -> bool {
 
 impl Hittable for Quad {
     fn hit(&self, r:&Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let denom: f64 = dot(&self.normal, &r.direction);
-        if denom.abs() < f64::EPSILON {
-            return false;
+        self.plane.hit(r, ray_t, rec, &self.mat, is_interior)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+// Triangle with corners q, q + u and q + v.  Texture coordinates run along the two edges from q.
+#[derive(Clone)]
+pub struct Triangle {
+    pub plane: PlanarFrame,
+    pub mat: Option<Arc<dyn Material + Sync>>,
+    pub bbox: AABB
+}
+
+impl Triangle {
+    pub fn new(q:Point3, u:Vec3, v:Vec3, mat: &Arc<dyn Material + Sync>) -> Triangle {
+        Triangle {
+            plane: PlanarFrame::new(q, u, v),
+            mat: Some(mat.clone()),
+            bbox: (AABB::new(&q, &(q + u)) + AABB::new(&q, &(q + v))).pad()
         }
+    }
+}
 
-        let t: f64 = (self.d - dot(&self.normal, &r.origin)) / denom;
+fn is_interior_triangle(a: f64, b: f64, rec: &mut HitRecord) -> bool {
+    if (a < 0.0) || (b < 0.0) || (1.0 < a + b) {
+        return false;
+    }
+    rec.uvw = Vec3::new(a, b, 0.0);
+    return true;
+}
 
-        if !ray_t.contains(t) {
-            return false;
+impl Hittable for Triangle {
+    fn hit(&self, r:&Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        self.plane.hit(r, ray_t, rec, &self.mat, is_interior_triangle)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+// Ellipse around `center` with semi-axes u and v.  The texture covers the square around it, like a decal.
+#[derive(Clone)]
+pub struct Ellipse {
+    pub plane: PlanarFrame,
+    pub mat: Option<Arc<dyn Material + Sync>>,
+    pub bbox: AABB
+}
+
+impl Ellipse {
+    pub fn new(center:Point3, u:Vec3, v:Vec3, mat: &Arc<dyn Material + Sync>) -> Ellipse {
+        Ellipse {
+            plane: PlanarFrame::new(center, u, v),
+            mat: Some(mat.clone()),
+            bbox: ellipse_bbox(&center, &u, &v)
         }
+    }
+}
 
-        let intersection: Vec3 = r.at(t);
+impl Hittable for Ellipse {
+    fn hit(&self, r:&Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let is_interior = |a: f64, b: f64, rec: &mut HitRecord| -> bool {
+            if a * a + b * b > 1.0 {
+                return false;
+            }
+            // From [-1, 1] along the semi-axes to [0, 1].
+            rec.uvw = Vec3::new(0.5 * (a + 1.0), 0.5 * (b + 1.0), 0.0);
+            rec.dpdu = self.plane.u * 2.0;
+            rec.dpdv = self.plane.v * 2.0;
+            true
+        };
+        self.plane.hit(r, ray_t, rec, &self.mat, is_interior)
+    }
 
-        let planar_hitpt_vector: Vec3 = intersection - self.q;
-        let alpha: f64 = dot(&self.w, &cross(&planar_hitpt_vector, &self.v));
-        let beta: f64 = dot(&self.w, &cross(&self.u, &planar_hitpt_vector));
-        if !is_interior(alpha, beta, rec) {
-            return false;
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+// Polar texture coordinates on a ring from `inner` to 1 in units of the frame's axes, u around from the u axis
+// and v outwards from the inner edge.
+fn is_interior_ring(plane: &PlanarFrame, inner: f64, a: f64, b: f64, rec: &mut HitRecord) -> bool {
+    let r: f64 = a.hypot(b);
+    if r > 1.0 || r < inner {
+        return false;
+    }
+    let mut phi: f64 = b.atan2(a);
+    if phi < 0.0 {
+        phi += 2.0 * PI;
+    }
+    let width: f64 = 1.0 - inner;
+    rec.uvw = Vec3::new(phi / (2.0 * PI), (r - inner) / width, 0.0);
+    rec.dpdu = (plane.v * a - plane.u * b) * (2.0 * PI);
+    rec.dpdv = if r > 0.0 { (plane.u * a + plane.v * b) * (width / r) } else { plane.u * width };
+    true
+}
+
+// Round disk facing along `normal`, with polar texture coordinates.
+#[derive(Clone)]
+pub struct Disk {
+    pub plane: PlanarFrame,
+    pub mat: Option<Arc<dyn Material + Sync>>,
+    pub bbox: AABB
+}
+
+impl Disk {
+    pub fn new(center:Point3, normal:Vec3, radius:f64, mat: &Arc<dyn Material + Sync>) -> Disk {
+        let frame: ONB = ONB::new(&normal);
+        let (u, v) = (frame.u * radius, frame.v * radius);
+        Disk {
+            plane: PlanarFrame::new(center, u, v),
+            mat: Some(mat.clone()),
+            bbox: ellipse_bbox(&center, &u, &v)
         }
+    }
+}
 
-        rec.t = t;
-        rec.p = intersection;
-        rec.mat = self.mat.clone();
-        rec.set_face_normal(r, &self.normal);
+impl Hittable for Disk {
+    fn hit(&self, r:&Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let is_interior = |a: f64, b: f64, rec: &mut HitRecord| -> bool { is_interior_ring(&self.plane, 0.0, a, b, rec) };
+        self.plane.hit(r, ray_t, rec, &self.mat, is_interior)
+    }
 
-        return true;
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+// Flat ring between two radii facing along `normal`, with polar texture coordinates.
+#[derive(Clone)]
+pub struct Annulus {
+    pub plane: PlanarFrame,
+    // Inner radius as a fraction of the outer one.
+    pub inner: f64,
+    pub mat: Option<Arc<dyn Material + Sync>>,
+    pub bbox: AABB
+}
+
+impl Annulus {
+    pub fn new(center:Point3, normal:Vec3, inner_radius:f64, outer_radius:f64, mat: &Arc<dyn Material + Sync>) -> Annulus {
+        assert!(0.0 <= inner_radius && inner_radius < outer_radius, "Annulus needs 0 <= inner radius < outer radius, got {} and {}", inner_radius, outer_radius);
+        let frame: ONB = ONB::new(&normal);
+        let (u, v) = (frame.u * outer_radius, frame.v * outer_radius);
+        Annulus {
+            plane: PlanarFrame::new(center, u, v),
+            inner: inner_radius / outer_radius,
+            mat: Some(mat.clone()),
+            bbox: ellipse_bbox(&center, &u, &v)
+        }
+    }
+}
+
+impl Hittable for Annulus {
+    fn hit(&self, r:&Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        let is_interior = |a: f64, b: f64, rec: &mut HitRecord| -> bool { is_interior_ring(&self.plane, self.inner, a, b, rec) };
+        self.plane.hit(r, ray_t, rec, &self.mat, is_interior)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+}
+
+// Infinite plane through q spanned by u and v, the texture repeating every u and v.  Its box is infinite
+// along the plane, so BVHs keep it out of the tree and test it on its own.
+#[derive(Clone)]
+pub struct Plane {
+    pub plane: PlanarFrame,
+    pub mat: Option<Arc<dyn Material + Sync>>,
+    pub bbox: AABB
+}
+
+impl Plane {
+    pub fn new(q:Point3, u:Vec3, v:Vec3, mat: &Arc<dyn Material + Sync>) -> Plane {
+        let plane: PlanarFrame = PlanarFrame::new(q, u, v);
+        // Flat along an axis the plane is perpendicular to, unbounded along the others.
+        let mut min: Point3 = Point3::new(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY);
+        let mut max: Point3 = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        for axis in 0..3 {
+            if plane.normal[axis].abs() == 1.0 {
+                min[axis] = q[axis];
+                max[axis] = q[axis];
+            }
+        }
+        Plane {
+            plane: plane,
+            mat: Some(mat.clone()),
+            bbox: AABB::new(&min, &max).pad()
+        }
+    }
+}
+
+fn is_interior_plane(a: f64, b: f64, rec: &mut HitRecord) -> bool {
+    rec.uvw = Vec3::new(a, b, 0.0);
+    return true;
+}
+
+impl Hittable for Plane {
+    fn hit(&self, r:&Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+        self.plane.hit(r, ray_t, rec, &self.mat, is_interior_plane)
     }
 
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn material() -> Arc<dyn Material + Sync> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    // Shoots a ray down the z axis at (x, y) onto a shape in the z = 0 plane.
+    fn hit_at(shape: &dyn Hittable, x: f64, y: f64) -> Option<HitRecord> {
+        let r: Ray = Ray::new(Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+        let mut rec: HitRecord = HitRecord::default();
+        if shape.hit(&r, Interval { min: 0.001, max: f64::INFINITY }, &mut rec) { Some(rec) } else { None }
+    }
+
+    fn assert_uv(rec: &HitRecord, u: f64, v: f64) {
+        assert!((rec.uvw.x() - u).abs() < 1e-9 && (rec.uvw.y() - v).abs() < 1e-9, "{} != ({}, {})", rec.uvw, u, v);
+    }
+
+    #[test]
+    fn quads_and_triangles() {
+        let mut rec: HitRecord = HitRecord::default();
+        assert!(is_interior(0.25, 0.75, &mut rec));
+        assert_uv(&rec, 0.25, 0.75);
+        assert!(!is_interior(-0.1, 0.5, &mut rec) && !is_interior(0.5, 1.1, &mut rec));
+
+        assert!(is_interior_triangle(0.25, 0.5, &mut rec));
+        assert_uv(&rec, 0.25, 0.5);
+        assert!(!is_interior_triangle(0.6, 0.6, &mut rec) && !is_interior_triangle(-0.1, 0.5, &mut rec));
+
+        let triangle: Triangle = Triangle::new(Point3::new(1.0, 1.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 4.0, 0.0), &material());
+        assert_uv(&hit_at(&triangle, 1.5, 2.0).unwrap(), 0.25, 0.25);
+        assert!(hit_at(&triangle, 2.5, 4.0).is_none());
+    }
+
+    #[test]
+    fn ellipses_and_disks() {
+        let ellipse: Ellipse = Ellipse::new(Point3::zero(), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), &material());
+        assert_uv(&hit_at(&ellipse, 1.0, 0.5).unwrap(), 0.75, 0.75);
+        assert!(hit_at(&ellipse, 1.9, 0.9).is_none());
+
+        let disk: Disk = Disk::new(Point3::zero(), Vec3::new(0.0, 0.0, 1.0), 2.0, &material());
+        let rec: HitRecord = hit_at(&disk, 0.0, 0.0).unwrap();
+        assert_eq!(rec.uvw.y(), 0.0);
+        // The centre still gets a radial direction.
+        assert!(rec.dpdv.length() > 0.0);
+        assert!((hit_at(&disk, 1.0, 1.0).unwrap().uvw.y() - 2.0_f64.sqrt() / 2.0).abs() < 1e-9);
+        assert!(hit_at(&disk, 1.5, 1.5).is_none());
+    }
+
+    #[test]
+    fn annuli() {
+        let annulus: Annulus = Annulus::new(Point3::zero(), Vec3::new(0.0, 0.0, 1.0), 1.0, 2.0, &material());
+        assert!(hit_at(&annulus, 0.5, 0.0).is_none());
+        assert!(hit_at(&annulus, 2.1, 0.0).is_none());
+        for (x, y) in [(1.5, 0.0), (0.0, 1.5), (-1.5, 0.0), (0.0, -1.5)] {
+            let rec: HitRecord = hit_at(&annulus, x, y).unwrap();
+            assert!((rec.uvw.y() - 0.5).abs() < 1e-9, "{}", rec.uvw);
+            assert!(rec.uvw.x().is_finite() && rec.dpdu.length().is_finite() && rec.dpdv.length().is_finite());
+        }
+        // u runs around the ring, a quarter turn a quarter of the way.
+        let (start, quarter) = (hit_at(&annulus, 1.5, 0.0).unwrap(), hit_at(&annulus, 0.0, 1.5).unwrap());
+        assert!(((quarter.uvw.x() - start.uvw.x()).rem_euclid(1.0) - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    #[should_panic(expected = "inner radius < outer radius")]
+    fn annulus_without_a_width() {
+        Annulus::new(Point3::zero(), Vec3::new(0.0, 0.0, 1.0), 1.0, 1.0, &material());
+    }
+
+    #[test]
+    #[should_panic(expected = "inner radius < outer radius")]
+    fn annulus_inside_out() {
+        Annulus::new(Point3::zero(), Vec3::new(0.0, 0.0, 1.0), 2.0, 1.0, &material());
+    }
+
+    #[test]
+    fn planes_are_unbounded() {
+        let plane: Plane = Plane::new(Point3::zero(), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), &material());
+        assert!(!plane.bounding_box().is_bounded());
+        assert_uv(&hit_at(&plane, 1e6, -3.0).unwrap(), 5e5, -1.5);
+    }
+}
@@ -1,12 +1,30 @@
 use std::fmt;
 
+use crate::medium::Medium;
 use crate::vec3::*;
 
+// Rays offset by one pixel in x and y on screen, traced alongside the main ray to estimate texture footprints.
+#[derive(Copy, Clone, Default)]
+pub struct RayDifferentials {
+    pub rx_origin: Point3,
+    pub rx_direction: Vec3,
+    pub ry_origin: Point3,
+    pub ry_direction: Vec3
+}
+
 #[derive(Copy, Clone, Default)]
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
     pub time: f64,
+    // Medium the ray travels through, vacuum unless it was refracted into an object.
+    pub medium: Medium,
+    // Hero wavelength in nanometres when rendering spectrally, zero in RGB mode.
+    pub wavelength: f64,
+    // Set by materials mixing specular and non-specular lobes when the ray came from a specular one.
+    pub specular: bool,
+    // Only camera rays and their specular bounces carry differentials.
+    pub differentials: Option<RayDifferentials>
 }
 
 impl Ray{
@@ -15,10 +33,37 @@ impl Ray{
         Ray { 
             origin: origin, 
             direction: direction, 
-            time: time
+            time: time,
+            medium: Medium::default(),
+            wavelength: 0.0,
+            specular: false,
+            differentials: None
         }
     }
 
+    // The same ray in another space, `point` and `vector` map positions and directions.  Carries the
+    // differentials along so instanced objects still get texture footprints.
+    pub fn transformed<P, V>(self: &Ray, point: P, vector: V) -> Ray
+    where P: Fn(&Point3) -> Point3, V: Fn(&Vec3) -> Vec3 {
+        let mut out: Ray = *self;
+        out.origin = point(&self.origin);
+        out.direction = vector(&self.direction);
+        out.differentials = self.differentials.map(|d: RayDifferentials| -> RayDifferentials {
+            RayDifferentials {
+                rx_origin: point(&d.rx_origin),
+                rx_direction: vector(&d.rx_direction),
+                ry_origin: point(&d.ry_origin),
+                ry_direction: vector(&d.ry_direction)
+            }
+        });
+        out
+    }
+
+    // Beer-Lambert transmittance of the ray's medium up to `t`.
+    pub fn transmittance(self: &Ray, t: f64) -> Color {
+        self.medium.transmittance(t * self.direction.length())
+    }
+
     pub fn at(self: &Ray, t: f64) -> Point3 {
         let r: Vec3 = t * self.direction;
         r + self.origin
@@ -0,0 +1,267 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use crate::common::{degrees_to_radians, saturate};
+use crate::environment::Environment;
+use crate::mat3::Mat3;
+use crate::color_space::{conversion_matrix, working_space, ColorSpace};
+use crate::tonemap::srgb_to_xyz_matrix;
+use crate::vec3::*;
+
+// Preetham, Shirley and Smits 1999, "A Practical Analytic Model for Daylight".
+#[derive(Copy, Clone)]
+pub struct PreethamSky {
+    pub sun_direction: Vec3,
+    pub turbidity: f64,
+    pub intensity: f64,
+    pub sun_intensity: f64,
+    pub sun_angular_radius: f64,
+    pub ground: Color,
+    sun_color: Color,
+    daylight: f64,
+    sun_probability: f64,
+    cos_sun_radius: f64,
+    theta_s: f64,
+    zenith: Vec3,
+    perez_y: [f64; 5],
+    perez_x: [f64; 5],
+    perez_yy: [f64; 5],
+    xyz_to_rgb: Mat3
+}
+
+// Scales luminance from kcd/m^2 so a clear midday zenith is around 0.5.
+const LUMINANCE_SCALE: f64 = 0.05;
+// Sun luminance above the atmosphere, in kcd/m^2.
+const SUN_LUMINANCE: f64 = 1.6e6;
+// Probability of sampling the sun disk rather than the whole sky for direct lighting.
+const SUN_SAMPLE_PROBABILITY: f64 = 0.5;
+// Sun elevation in degrees at which the sky has faded to black, the end of civil twilight.
+const TWILIGHT_END: f64 = -6.0;
+
+// Direction towards a sun at `elevation` above the horizon and `azimuth` clockwise from -z, both in degrees.
+pub fn sun_direction(elevation: f64, azimuth: f64) -> Vec3 {
+    let el: f64 = degrees_to_radians(elevation);
+    let az: f64 = degrees_to_radians(azimuth);
+    Vec3::new(el.cos() * az.sin(), el.sin(), -el.cos() * az.cos())
+}
+
+pub fn day_of_year(month: u32, day: u32) -> u32 {
+    let days_before_month: [u32; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+    days_before_month[(month.clamp(1, 12) - 1) as usize] + day
+}
+
+// Sun elevation and azimuth in degrees, azimuth measured clockwise from north.
+// `solar_time` is local solar time in hours, noon being when the sun crosses the meridian.
+pub fn sun_position(day_of_year: u32, solar_time: f64, latitude: f64) -> (f64, f64) {
+    let declination: f64 = degrees_to_radians(23.44) * (2.0 * PI * (284.0 + day_of_year as f64) / 365.0).sin();
+    let hour_angle: f64 = degrees_to_radians(15.0 * (solar_time - 12.0));
+    let lat: f64 = degrees_to_radians(latitude);
+
+    let sin_elevation: f64 = lat.sin() * declination.sin() + lat.cos() * declination.cos() * hour_angle.cos();
+    let elevation: f64 = sin_elevation.clamp(-1.0, 1.0).asin();
+
+    let denom: f64 = elevation.cos() * lat.cos();
+    let cos_azimuth: f64 = if denom.abs() < 1e-9 { 1.0 } else { (declination.sin() - sin_elevation * lat.sin()) / denom };
+    let mut azimuth: f64 = cos_azimuth.clamp(-1.0, 1.0).acos().to_degrees();
+    if hour_angle > 0.0 {
+        azimuth = 360.0 - azimuth;
+    }
+
+    (elevation.to_degrees(), azimuth)
+}
+
+fn perez(coeffs: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coeffs;
+    let cos_gamma: f64 = gamma.cos();
+    (1.0 + a * (b / cos_theta.max(0.01)).exp()) * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+fn xyy_to_xyz(x: f64, y: f64, luminance: f64) -> Vec3 {
+    if y <= 0.0 {
+        return Vec3::zero();
+    }
+    Vec3::new(x * luminance / y, luminance, (1.0 - x - y) * luminance / y)
+}
+
+impl PreethamSky {
+    pub fn new(sun_direction: Vec3, turbidity: f64) -> PreethamSky {
+        let mut sky: PreethamSky = PreethamSky {
+            sun_direction: normalize(sun_direction),
+            turbidity: turbidity.clamp(1.7, 10.0),
+            intensity: 1.0,
+            sun_intensity: 1.0,
+            sun_angular_radius: degrees_to_radians(0.27),
+            ground: Color::new(0.1, 0.1, 0.1),
+            sun_color: Color::black(),
+            daylight: 1.0,
+            sun_probability: SUN_SAMPLE_PROBABILITY,
+            cos_sun_radius: 1.0,
+            theta_s: 0.0,
+            zenith: Vec3::zero(),
+            perez_y: [0.0; 5],
+            perez_x: [0.0; 5],
+            perez_yy: [0.0; 5],
+            xyz_to_rgb: conversion_matrix(ColorSpace::Rec709, working_space()) * srgb_to_xyz_matrix().inverse()
+        };
+        sky.initialize();
+        sky
+    }
+
+    pub fn from_angles(elevation: f64, azimuth: f64, turbidity: f64) -> PreethamSky {
+        PreethamSky::new(sun_direction(elevation, azimuth), turbidity)
+    }
+
+    // Recomputes the cached model coefficients, call after changing any public field.
+    pub fn initialize(&mut self) {
+        let t: f64 = self.turbidity;
+        // Keep the sun at or just above the horizon, the model is not defined below it.
+        let theta_s: f64 = self.sun_direction.y().clamp(0.0, 1.0).acos().min(degrees_to_radians(89.5));
+        self.theta_s = theta_s;
+        self.cos_sun_radius = self.sun_angular_radius.cos();
+
+        self.perez_y = [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703];
+        self.perez_x = [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452];
+        self.perez_yy = [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529];
+
+        let chi: f64 = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance: f64 = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let th: [f64; 4] = [theta_s * theta_s * theta_s, theta_s * theta_s, theta_s, 1.0];
+        let tt: [f64; 3] = [t * t, t, 1.0];
+        let mx: [[f64; 4]; 3] = [
+            [ 0.00166, -0.00375,  0.00209, 0.0],
+            [-0.02903,  0.06377, -0.03202, 0.00394],
+            [ 0.11693, -0.21196,  0.06052, 0.25886]
+        ];
+        let my: [[f64; 4]; 3] = [
+            [ 0.00275, -0.00610,  0.00317, 0.0],
+            [-0.04214,  0.08970, -0.04153, 0.00516],
+            [ 0.15346, -0.26756,  0.06670, 0.26688]
+        ];
+        let mut zenith_x: f64 = 0.0;
+        let mut zenith_y: f64 = 0.0;
+        for i in 0..3 {
+            for j in 0..4 {
+                zenith_x += tt[i] * mx[i][j] * th[j];
+                zenith_y += tt[i] * my[i][j] * th[j];
+            }
+        }
+        self.zenith = Vec3::new(zenith_luminance.max(0.0), zenith_x, zenith_y);
+
+        // Below the horizon the model is evaluated for a sun on it, and faded out over twilight.
+        let elevation: f64 = self.sun_direction.y().clamp(-1.0, 1.0).asin().to_degrees();
+        self.daylight = saturate(1.0 - elevation / TWILIGHT_END);
+        if self.sun_direction.y() < 0.0 {
+            self.sun_color = Color::black();
+            self.sun_probability = 0.0;
+        } else {
+            self.sun_color = self.compute_sun_color();
+            self.sun_probability = SUN_SAMPLE_PROBABILITY;
+        }
+    }
+
+    // Sun radiance after Rayleigh and aerosol extinction along the optical air mass.
+    fn compute_sun_color(&self) -> Color {
+        let theta_deg: f64 = self.theta_s.to_degrees();
+        let air_mass: f64 = 1.0 / (self.theta_s.cos() + 0.15 * (93.885 - theta_deg).powf(-1.253));
+        let beta: f64 = 0.04608 * self.turbidity - 0.04586;
+        let alpha: f64 = 1.3;
+
+        // Representative wavelengths in micrometres for the red, green and blue primaries.
+        let wavelengths: [f64; 3] = [0.65, 0.57, 0.475];
+        let mut transmittance: Color = Color::zero();
+        for (i, l) in wavelengths.iter().enumerate() {
+            let rayleigh: f64 = (-0.008735 * l.powf(-4.08) * air_mass).exp();
+            let aerosol: f64 = (-beta * l.powf(-alpha) * air_mass).exp();
+            transmittance[i] = rayleigh * aerosol;
+        }
+        transmittance * (SUN_LUMINANCE * LUMINANCE_SCALE)
+    }
+
+    fn sky_color(&self, d: &Vec3) -> Color {
+        let cos_theta: f64 = d.y().max(0.0);
+        let gamma: f64 = dot(d, &self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let luminance: f64 = self.zenith.x() * perez(&self.perez_y, cos_theta, gamma) / perez(&self.perez_y, 1.0, self.theta_s);
+        let x: f64 = self.zenith.y() * perez(&self.perez_x, cos_theta, gamma) / perez(&self.perez_x, 1.0, self.theta_s);
+        let y: f64 = self.zenith.z() * perez(&self.perez_yy, cos_theta, gamma) / perez(&self.perez_yy, 1.0, self.theta_s);
+
+        let rgb: Color = self.xyz_to_rgb * xyy_to_xyz(x, y, luminance * LUMINANCE_SCALE);
+        Color::new(rgb.r().max(0.0), rgb.g().max(0.0), rgb.b().max(0.0))
+    }
+
+    fn sun_cone_pdf(&self) -> f64 {
+        1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+    }
+}
+
+impl Environment for PreethamSky {
+    fn value(&self, direction: &Vec3) -> Color {
+        let d: Vec3 = normalize(*direction);
+        if d.y() < 0.0 {
+            // Fade the horizon into the ground to avoid a hard seam.
+            let blend: f64 = saturate(-d.y() * 10.0);
+            let horizon: Color = self.sky_color(&Vec3::new(d.x(), 0.0, d.z()));
+            return (horizon * (1.0 - blend) + self.ground * blend) * (self.daylight * self.intensity);
+        }
+
+        let mut color: Color = self.sky_color(&d) * self.daylight;
+        if dot(&d, &self.sun_direction) >= self.cos_sun_radius {
+            color += self.sun_color * self.sun_intensity;
+        }
+        color * self.intensity
+    }
+
+    fn sample(&self) -> (Vec3, f64) {
+        let mut rng = rand::thread_rng();
+        let direction: Vec3 = if rng.gen::<f64>() < self.sun_probability {
+            // Uniform direction within the sun cone.
+            let cos_theta: f64 = 1.0 - rng.gen::<f64>() * (1.0 - self.cos_sun_radius);
+            let sin_theta: f64 = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi: f64 = 2.0 * PI * rng.gen::<f64>();
+            let w: Vec3 = self.sun_direction;
+            let a: Vec3 = if w.x().abs() > 0.9 { Vec3::up() } else { Vec3::new(1.0, 0.0, 0.0) };
+            let v: Vec3 = normalize(cross(&w, &a));
+            let u: Vec3 = cross(&w, &v);
+            u * (sin_theta * phi.cos()) + v * (sin_theta * phi.sin()) + w * cos_theta
+        } else {
+            random_unit_vector()
+        };
+        (direction, self.pdf(&direction))
+    }
+
+    fn pdf(&self, direction: &Vec3) -> f64 {
+        let d: Vec3 = normalize(*direction);
+        let sphere_pdf: f64 = 1.0 / (4.0 * PI);
+        let sun_pdf: f64 = if dot(&d, &self.sun_direction) >= self.cos_sun_radius { self.sun_cone_pdf() } else { 0.0 };
+        self.sun_probability * sun_pdf + (1.0 - self.sun_probability) * sphere_pdf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn night_sky_is_dark_and_skips_the_sun() {
+        let up: Vec3 = Vec3::up();
+        let day: PreethamSky = PreethamSky::from_angles(30.0, 90.0, 3.0);
+        assert!(!day.value(&up).is_black());
+        assert!(day.pdf(&day.sun_direction) > 1.0);
+
+        let dusk: PreethamSky = PreethamSky::from_angles(-3.0, 90.0, 3.0);
+        let sunset: PreethamSky = PreethamSky::from_angles(0.0, 90.0, 3.0);
+        assert!(dusk.value(&up).luminance() < sunset.value(&up).luminance() * 0.6);
+        assert!(dusk.value(&up).luminance() > 0.0);
+
+        let night: PreethamSky = PreethamSky::from_angles(-20.0, 90.0, 3.0);
+        assert!(night.value(&up).is_black());
+        assert!(night.value(&Vec3::new(0.0, -1.0, 0.0)).is_black());
+        assert!(night.value(&night.sun_direction).is_black());
+        assert_eq!(night.pdf(&night.sun_direction), 1.0 / (4.0 * PI));
+        for _ in 0..64 {
+            assert_eq!(night.sample().1, 1.0 / (4.0 * PI));
+        }
+    }
+}
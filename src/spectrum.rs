@@ -0,0 +1,257 @@
+use std::ops;
+
+use crate::mat3::Mat3;
+use crate::color_space::working_to_rec709;
+use crate::tonemap::{chromatic_adaptation, srgb_to_xyz_matrix, xy_to_xyz};
+use crate::vec3::*;
+
+// Hero wavelength spectral sampling, Wilkie et al. 2014.  Each camera path carries a hero wavelength
+// and three more spread evenly over the visible range.
+
+pub const SPECTRUM_SAMPLES: usize = 4;
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+#[derive(Copy, Clone, Default)]
+pub struct SampledSpectrum {
+    pub values: [f64; SPECTRUM_SAMPLES]
+}
+
+#[derive(Copy, Clone, Default)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; SPECTRUM_SAMPLES],
+    pub pdf: [f64; SPECTRUM_SAMPLES]
+}
+
+impl SampledSpectrum {
+    pub fn new(v: f64) -> SampledSpectrum {
+        SampledSpectrum { values: [v; SPECTRUM_SAMPLES] }
+    }
+}
+
+impl ops::Add<SampledSpectrum> for SampledSpectrum {
+    type Output = SampledSpectrum;
+    fn add(self, rhs: SampledSpectrum) -> SampledSpectrum {
+        let mut out: SampledSpectrum = self;
+        for i in 0..SPECTRUM_SAMPLES {
+            out.values[i] += rhs.values[i];
+        }
+        out
+    }
+}
+
+impl ops::AddAssign<SampledSpectrum> for SampledSpectrum {
+    fn add_assign(&mut self, rhs: SampledSpectrum) {
+        *self = *self + rhs;
+    }
+}
+
+impl ops::Mul<f64> for SampledSpectrum {
+    type Output = SampledSpectrum;
+    fn mul(self, rhs: f64) -> SampledSpectrum {
+        let mut out: SampledSpectrum = self;
+        for v in out.values.iter_mut() {
+            *v *= rhs;
+        }
+        out
+    }
+}
+
+impl ops::Mul<SampledSpectrum> for SampledSpectrum {
+    type Output = SampledSpectrum;
+    fn mul(self, rhs: SampledSpectrum) -> SampledSpectrum {
+        let mut out: SampledSpectrum = self;
+        for i in 0..SPECTRUM_SAMPLES {
+            out.values[i] *= rhs.values[i];
+        }
+        out
+    }
+}
+
+// Samples wavelengths proportionally to the visual response, as in pbrt-v4.
+fn sample_visible_wavelength(u: f64) -> f64 {
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+fn visible_wavelength_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) {
+        return 0.0;
+    }
+    let c: f64 = (0.0072 * (lambda - 538.0)).cosh();
+    0.0039398042 / (c * c)
+}
+
+impl SampledWavelengths {
+    pub fn sample_visible(u: f64) -> SampledWavelengths {
+        let mut sw: SampledWavelengths = SampledWavelengths::default();
+        for i in 0..SPECTRUM_SAMPLES {
+            let up: f64 = (u + i as f64 / SPECTRUM_SAMPLES as f64).fract();
+            sw.lambda[i] = sample_visible_wavelength(up);
+            sw.pdf[i] = visible_wavelength_pdf(sw.lambda[i]);
+        }
+        sw
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|p| *p == 0.0)
+    }
+
+    // Wavelength dependent scattering, like dispersion, can only follow the hero wavelength.
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for i in 1..SPECTRUM_SAMPLES {
+            self.pdf[i] = 0.0;
+        }
+        self.pdf[0] /= SPECTRUM_SAMPLES as f64;
+    }
+
+    // Upsampling assumes Rec.709 primaries, other working spaces are converted first.
+    pub fn reflectance(&self, rgb: &Color) -> SampledSpectrum {
+        let rgb: Color = working_to_rec709(rgb);
+        let mut s: SampledSpectrum = SampledSpectrum::default();
+        for i in 0..SPECTRUM_SAMPLES {
+            s.values[i] = rgb_to_spectrum(&rgb, self.lambda[i]);
+        }
+        s
+    }
+
+    // Emitters use the same upsampling, the equal energy white this gives is adapted to D65 on output.
+    pub fn illuminant(&self, rgb: &Color) -> SampledSpectrum {
+        self.reflectance(rgb)
+    }
+
+    // Monte Carlo estimate of the CIE XYZ tristimulus values of the sampled spectrum.
+    pub fn spectrum_to_xyz(&self, s: &SampledSpectrum) -> Vec3 {
+        let mut xyz: Vec3 = Vec3::zero();
+        for i in 0..SPECTRUM_SAMPLES {
+            if self.pdf[i] <= 0.0 {
+                continue;
+            }
+            xyz += cie_xyz(self.lambda[i]) * (s.values[i] / self.pdf[i]);
+        }
+        xyz / (SPECTRUM_SAMPLES as f64 * CIE_Y_INTEGRAL)
+    }
+}
+
+// Integral of the fitted y bar curve, so a constant spectrum of 1.0 has luminance 1.0.
+pub const CIE_Y_INTEGRAL: f64 = 106.922485;
+
+// XYZ to linear sRGB, white balanced so the equal energy white of upsampled spectra maps to D65.
+pub fn xyz_to_srgb_matrix() -> Mat3 {
+    let d65: Vec3 = xy_to_xyz(0.3127, 0.3290);
+    chromatic_adaptation(&Vec3::one(), &d65) * srgb_to_xyz_matrix().inverse()
+}
+
+fn piecewise_gaussian(x: f64, mu: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let sigma: f64 = if x < mu { sigma_low } else { sigma_high };
+    let t: f64 = (x - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+// CIE 1931 colour matching functions, multi-lobe fit from Wyman, Sloan and Shirley 2013.
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    Vec3::new(
+        1.056 * piecewise_gaussian(lambda, 599.8, 37.9, 31.0)
+            + 0.362 * piecewise_gaussian(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * piecewise_gaussian(lambda, 501.1, 20.4, 26.2),
+        0.821 * piecewise_gaussian(lambda, 568.8, 46.9, 40.5)
+            + 0.286 * piecewise_gaussian(lambda, 530.9, 16.3, 31.1),
+        1.217 * piecewise_gaussian(lambda, 437.0, 11.8, 36.0)
+            + 0.681 * piecewise_gaussian(lambda, 459.0, 26.0, 13.8)
+    )
+}
+
+// Smits 1999, "An RGB to Spectrum Conversion for Reflectances".  Ten bins from 380nm to 720nm.
+const SMITS_WHITE:   [f64; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN:    [f64; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f64; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW:  [f64; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED:     [f64; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN:   [f64; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE:    [f64; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+pub fn rgb_to_spectrum(rgb: &Color, lambda: f64) -> f64 {
+    let bin: usize = (((lambda - 380.0) / 34.0).floor().max(0.0) as usize).min(9);
+    let (r, g, b) = (rgb.r(), rgb.g(), rgb.b());
+
+    if r <= g && r <= b {
+        let mut v: f64 = r * SMITS_WHITE[bin];
+        if g <= b {
+            v += (g - r) * SMITS_CYAN[bin] + (b - g) * SMITS_BLUE[bin];
+        } else {
+            v += (b - r) * SMITS_CYAN[bin] + (g - b) * SMITS_GREEN[bin];
+        }
+        v
+    } else if g <= r && g <= b {
+        let mut v: f64 = g * SMITS_WHITE[bin];
+        if r <= b {
+            v += (r - g) * SMITS_MAGENTA[bin] + (b - r) * SMITS_BLUE[bin];
+        } else {
+            v += (b - g) * SMITS_MAGENTA[bin] + (r - b) * SMITS_RED[bin];
+        }
+        v
+    } else {
+        let mut v: f64 = b * SMITS_WHITE[bin];
+        if r <= g {
+            v += (r - b) * SMITS_YELLOW[bin] + (g - r) * SMITS_GREEN[bin];
+        } else {
+            v += (g - b) * SMITS_YELLOW[bin] + (r - g) * SMITS_RED[bin];
+        }
+        v
+    }
+}
+
+// Wavelength dependent index of refraction, wavelengths in nanometres.
+#[derive(Copy, Clone, Debug, Default)]
+pub enum Dispersion {
+    #[default]
+    None,
+    // n = a + b / lambda^2, lambda in micrometres.
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum(b_i lambda^2 / (lambda^2 - c_i)), lambda in micrometres.
+    Sellmeier { b: [f64; 3], c: [f64; 3] }
+}
+
+impl Dispersion {
+    pub fn bk7() -> Dispersion {
+        Dispersion::Sellmeier { b: [1.03961212, 0.231792344, 1.01046945], c: [0.00600069867, 0.0200179144, 103.560653] }
+    }
+
+    pub fn dense_flint() -> Dispersion {
+        Dispersion::Sellmeier { b: [1.73759695, 0.313747346, 1.89878101], c: [0.013188707, 0.0623068142, 155.23629] }
+    }
+
+    pub fn diamond() -> Dispersion {
+        Dispersion::Sellmeier { b: [4.3356, 0.3306, 0.0], c: [0.1060 * 0.1060, 0.1750 * 0.1750, 0.0] }
+    }
+
+    // Returns `ior` for wavelengths of zero, which is how RGB rendering asks.
+    pub fn ior(&self, ior: f64, lambda: f64) -> f64 {
+        if lambda <= 0.0 {
+            return ior;
+        }
+        let l: f64 = lambda / 1000.0;
+        let l2: f64 = l * l;
+        match self {
+            Dispersion::None => ior,
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let mut n2: f64 = 1.0;
+                for i in 0..3 {
+                    n2 += b[i] * l2 / (l2 - c[i]);
+                }
+                n2.max(1.0).sqrt()
+            }
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Dispersion::None)
+    }
+}
@@ -27,6 +27,18 @@ pub fn get_sphere_uvw(p:&Point3) -> Vec3 {
     Vec3::new(u, v, 0.0)
 }
 
+// Derivatives of the point on a sphere of `radius` with respect to the uvs from get_sphere_uvw.
+pub fn get_sphere_tangents(p: &Point3, radius: f64) -> (Vec3, Vec3) {
+    let theta: f64 = (-p.y()).clamp(-1.0, 1.0).acos();
+    let phi: f64 = (-p.z()).atan2(p.x()) + PI;
+    let (sin_theta, cos_theta) = theta.sin_cos();
+    let (sin_phi, cos_phi) = phi.sin_cos();
+
+    let dpdu: Vec3 = Vec3::new(sin_theta * sin_phi, 0.0, sin_theta * cos_phi) * (2.0 * PI * radius);
+    let dpdv: Vec3 = Vec3::new(-cos_theta * cos_phi, sin_theta, cos_theta * sin_phi) * (PI * radius);
+    (dpdu, dpdv)
+}
+
 impl Sphere {
     pub fn new_static(center: Point3, radius: f64, mat: &Arc<dyn Material + Sync>) -> Sphere {
         let rvec = Vec3::new(radius, radius, radius);
@@ -79,24 +91,35 @@ impl Hittable for Sphere {
         } 
 
         let sqrtd = discriminant.sqrt();
+        let center: Point3 = self.center(r.time);
 
-        // Find the nearest root that lies in the acceptable range.
-        let mut root = (-half_b - sqrtd) / a;
-        if !ray_t.surrounds(root) {
-            root = (-half_b -sqrtd ) / a;
+        // Try the near root then the far one, the far side shows through cut out parts of the near side.
+        for root in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
             if !ray_t.surrounds(root) {
-                return false;
+                continue;
             }
-        }
 
-        rec.t = root;
-        rec.p = r.at(rec.t);
-        let outward_normal = (rec.p - self.center(r.time)) /  self.radius;
-        rec.set_face_normal(r, &outward_normal);
-        rec.uvw = get_sphere_uvw(&outward_normal);
-        rec.mat = self.mat.clone();
+            let mut candidate: HitRecord = HitRecord{..HitRecord::default()};
+            candidate.t = root;
+            candidate.p = r.at(root);
+            candidate.object_p = candidate.p - center;
+            let outward_normal = (candidate.p - center) / self.radius;
+            candidate.object_normal = outward_normal;
+            candidate.set_face_normal(r, &outward_normal);
+            candidate.uvw = get_sphere_uvw(&outward_normal);
+            (candidate.dpdu, candidate.dpdv) = get_sphere_tangents(&outward_normal, self.radius);
+            (candidate.object_dpdu, candidate.object_dpdv) = (candidate.dpdu, candidate.dpdv);
+            candidate.set_differentials(r);
+            candidate.mat = self.mat.clone();
+            if candidate.is_cutout() {
+                continue;
+            }
+
+            *rec = candidate;
+            return true;
+        }
 
-        return true;
+        return false;
     }
     
     fn bounding_box(&self) -> AABB {
@@ -1,14 +1,58 @@
-use std::path::PathBuf;
+use std::path::Path;
+use std::sync::Arc;
 
-use crate::{vec3::Color, tga::read_tga_file, common::saturate};
+use crate::{vec3::Color, image::ImageError, common::{saturate, degrees_to_radians}};
+use crate::color_space::ColorSpace;
+use crate::hittable::HitRecord;
+use crate::texture_cache::{texture_cache, CachedImage};
 
 #[derive(Copy, Clone, Default)]
 pub struct SolidColorTexture {
     pub color: Color
 }
 
+// Change in texture coordinates from one pixel to the next, along x and y on screen.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct UvFootprint {
+    pub dudx: f64,
+    pub dvdx: f64,
+    pub dudy: f64,
+    pub dvdy: f64
+}
+
 pub trait Texture : Send {
     fn value(&self, u:f64, v:f64, w:f64) -> Color;
+
+    // Value averaged over the pixel footprint, textures that can't prefilter point sample.
+    fn value_filtered(&self, u:f64, v:f64, w:f64, _footprint: &UvFootprint) -> Color {
+        self.value(u, v, w)
+    }
+
+    // Value at a surface hit.  Textures that look at more than the texture coordinates, like the hit position,
+    // override this.
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.value_filtered(rec.uvw.x(), rec.uvw.y(), rec.uvw.z(), &rec.footprint)
+    }
+
+    // Coverage in [0, 1], textures without an alpha channel are fully opaque.
+    fn alpha(&self, _u:f64, _v:f64, _w:f64) -> f64 {
+        1.0
+    }
+
+    // Coverage at a surface hit, overridden alongside value_at.
+    fn alpha_at(&self, rec: &HitRecord) -> f64 {
+        self.alpha(rec.uvw.x(), rec.uvw.y(), rec.uvw.z())
+    }
+}
+
+// Constant texture, for material parameters that may also be textured.
+pub fn solid_color(color: Color) -> Arc<dyn Texture + Sync> {
+    Arc::new(SolidColorTexture { color: color })
+}
+
+// Constant for scalar parameters, which read the red channel.
+pub fn solid_value(value: f64) -> Arc<dyn Texture + Sync> {
+    solid_color(Color::new(value, value, value))
 }
 
 impl Texture for SolidColorTexture {
@@ -48,49 +92,344 @@ impl Texture for CheckerTexture {
     }
 }
 
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum WrapMode {
+    // Tile the image.
+    #[default]
+    Repeat,
+    // Tile the image, flipping every other copy so the edges match.
+    Mirror,
+    // Extend the edge texels.
+    Clamp
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum FilterMode {
+    Nearest,
+    #[default]
+    Bilinear,
+    // Catmull-Rom over 4x4 texels, sharper than bilinear when magnified.
+    Bicubic
+}
+
+// Scales, then rotates counter clockwise about the origin, then offsets texture coordinates.
+#[derive(Copy, Clone, Debug)]
+pub struct UvTransform {
+    pub scale_u: f64,
+    pub scale_v: f64,
+    pub offset_u: f64,
+    pub offset_v: f64,
+    // In degrees.
+    pub rotation: f64
+}
+
+impl Default for UvTransform {
+    fn default() -> UvTransform {
+        UvTransform {
+            scale_u: 1.0,
+            scale_v: 1.0,
+            offset_u: 0.0,
+            offset_v: 0.0,
+            rotation: 0.0
+        }
+    }
+}
+
+impl UvTransform {
+    pub fn new(scale_u: f64, scale_v: f64, offset_u: f64, offset_v: f64, rotation: f64) -> UvTransform {
+        UvTransform {
+            scale_u: scale_u,
+            scale_v: scale_v,
+            offset_u: offset_u,
+            offset_v: offset_v,
+            rotation: rotation
+        }
+    }
+
+    // Repeats the texture `u` by `v` times across the surface.
+    pub fn tile(u: f64, v: f64) -> UvTransform {
+        UvTransform::new(u, v, 0.0, 0.0, 0.0)
+    }
+
+    pub fn apply(&self, u: f64, v: f64) -> (f64, f64) {
+        let su: f64 = u * self.scale_u;
+        let sv: f64 = v * self.scale_v;
+        let (sin, cos) = degrees_to_radians(self.rotation).sin_cos();
+        (su * cos - sv * sin + self.offset_u, su * sin + sv * cos + self.offset_v)
+    }
+
+    // Transforms a change in texture coordinates, which ignores the offset.
+    pub fn apply_vector(&self, du: f64, dv: f64) -> (f64, f64) {
+        let (u, v) = self.apply(du, dv);
+        (u - self.offset_u, v - self.offset_v)
+    }
+}
+
+fn wrap_texel(i: i64, size: usize, mode: WrapMode) -> usize {
+    let n: i64 = size as i64;
+    let wrapped: i64 = match mode {
+        WrapMode::Repeat => i.rem_euclid(n),
+        WrapMode::Mirror => {
+            let period: i64 = i.rem_euclid(2 * n);
+            if period < n { period } else { 2 * n - 1 - period }
+        }
+        WrapMode::Clamp => i.clamp(0, n - 1)
+    };
+    wrapped as usize
+}
+
+// Catmull-Rom weights for the four texels around a sample `t` in [0, 1) past the second one.
+fn catmull_rom_weights(t: f64) -> [f64; 4] {
+    let t2: f64 = t * t;
+    let t3: f64 = t2 * t;
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2)
+    ]
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum MipFilter {
+    // Always read the full resolution image.
+    None,
+    // Blend the two levels closest to the footprint size.
+    #[default]
+    Trilinear,
+    // Elliptical weighted average over the footprint, sharper at grazing angles.
+    Ewa
+}
+
+// Longest to shortest footprint axis ratio EWA will filter before blurring.
+const EWA_MAX_ANISOTROPY: f64 = 8.0;
+
 #[derive(Clone, Default)]
 pub struct ImageTexture {
-    pub bytes_per_pixel: usize,
-    pub width: usize,
-    pub height: usize,
-    pub data: Vec<u8>,
-    pub bytes_per_scanline: usize
+    pub wrap: WrapMode,
+    pub filter: FilterMode,
+    pub mip_filter: MipFilter,
+    pub transform: UvTransform,
+    // The image and its mip pyramid, shared through the texture cache.  None renders magenta.
+    pub image: Option<Arc<CachedImage>>
 }
 
 impl ImageTexture {
-    pub fn new(file_path: &PathBuf) -> ImageTexture {
-        let mut img: ImageTexture = ImageTexture::default();
-        let _ = read_tga_file(file_path, 
-            &mut img.data, 
-            &mut img.width, 
-            &mut img.height, 
-            &mut img.bytes_per_pixel
-        );
+    // Falls back to an empty texture, which renders magenta, when the file can't be read.
+    pub fn new(file_path: &Path) -> ImageTexture {
+        ImageTexture::new_in(file_path, None)
+    }
+
+    // Overrides the file's usual colour space, for example linear Rec.709 for normal, roughness or height maps.
+    pub fn new_in(file_path: &Path, color_space: Option<ColorSpace>) -> ImageTexture {
+        match ImageTexture::load(file_path, color_space) {
+            Ok(img) => img,
+            Err(e) => {
+                eprintln!("Failed to load texture {}: {}", file_path.display(), e);
+                ImageTexture::default()
+            }
+        }
+    }
+
+    // PNG, JPEG, Radiance HDR or TGA.  Textures loading the same file in the same colour space share it.
+    pub fn load(file_path: &Path, color_space: Option<ColorSpace>) -> Result<ImageTexture, ImageError> {
+        let image: Arc<CachedImage> = texture_cache().get(file_path, color_space)?;
+        Ok(ImageTexture { image: Some(image), ..ImageTexture::default() })
+    }
+
+    fn cached(&self) -> &CachedImage {
+        self.image.as_ref().unwrap()
+    }
+
+    fn level_count(&self) -> usize {
+        self.cached().level_count()
+    }
+
+    fn level_size(&self, level: usize) -> (usize, usize) {
+        self.cached().level_size(level)
+    }
+
+    // Texel as RGBA, coordinates outside the image are wrapped.
+    fn texel(&self, level: usize, x: i64, y: i64) -> [f64; 4] {
+        let (width, height) = self.level_size(level);
+        let (x, y) = (wrap_texel(x, width, self.wrap), wrap_texel(y, height, self.wrap));
+        self.cached().texel(level, x, y).map(|c: f32| -> f64 { c as f64 })
+    }
+
+    // Filtered RGBA from one level at transformed texture coordinates (u, v).
+    fn filter_level(&self, level: usize, u: f64, v: f64) -> [f64; 4] {
+        let level: usize = level.min(self.level_count() - 1);
+        let (width, height) = self.level_size(level);
+        let x: f64 = u * width as f64;
+        let y: f64 = v * height as f64;
+
+        match self.filter {
+            FilterMode::Nearest => self.texel(level, x.floor() as i64, y.floor() as i64),
+            FilterMode::Bilinear => {
+                // Texel centres sit at half integers.
+                let x0: f64 = (x - 0.5).floor();
+                let y0: f64 = (y - 0.5).floor();
+                let tx: f64 = x - 0.5 - x0;
+                let ty: f64 = y - 0.5 - y0;
+                let (xi, yi) = (x0 as i64, y0 as i64);
+                let weights: [(i64, i64, f64); 4] = [
+                    (xi, yi, (1.0 - tx) * (1.0 - ty)),
+                    (xi + 1, yi, tx * (1.0 - ty)),
+                    (xi, yi + 1, (1.0 - tx) * ty),
+                    (xi + 1, yi + 1, tx * ty)
+                ];
+                let mut rgba: [f64; 4] = [0.0; 4];
+                for (tx, ty, w) in weights {
+                    let t: [f64; 4] = self.texel(level, tx, ty);
+                    for c in 0..4 {
+                        rgba[c] += t[c] * w;
+                    }
+                }
+                rgba
+            }
+            FilterMode::Bicubic => {
+                let x0: f64 = (x - 0.5).floor();
+                let y0: f64 = (y - 0.5).floor();
+                let wx: [f64; 4] = catmull_rom_weights(x - 0.5 - x0);
+                let wy: [f64; 4] = catmull_rom_weights(y - 0.5 - y0);
+                let mut rgba: [f64; 4] = [0.0; 4];
+                for (j, weight_y) in wy.iter().enumerate() {
+                    for (i, weight_x) in wx.iter().enumerate() {
+                        let t: [f64; 4] = self.texel(level, x0 as i64 + i as i64 - 1, y0 as i64 + j as i64 - 1);
+                        for c in 0..4 {
+                            rgba[c] += t[c] * weight_x * weight_y;
+                        }
+                    }
+                }
+                // Catmull-Rom overshoots at sharp edges.
+                rgba.map(saturate)
+            }
+        }
+    }
+
+    // Gaussian weighted average over the ellipse spanned by the footprint axes (in uv) on one level.
+    // Heckbert 1989, following pbrt.
+    fn ewa_level(&self, level: usize, u: f64, v: f64, axis0: (f64, f64), axis1: (f64, f64)) -> [f64; 4] {
+        let level: usize = level.min(self.level_count() - 1);
+        let (width, height) = self.level_size(level);
+        let s: f64 = u * width as f64 - 0.5;
+        let t: f64 = v * height as f64 - 0.5;
+        let (ds0, dt0) = (axis0.0 * width as f64, axis0.1 * height as f64);
+        let (ds1, dt1) = (axis1.0 * width as f64, axis1.1 * height as f64);
+
+        // Implicit ellipse a s^2 + b s t + c t^2 = 1, padded by a texel so it never falls between samples.
+        let mut a: f64 = dt0 * dt0 + dt1 * dt1 + 1.0;
+        let mut b: f64 = -2.0 * (ds0 * dt0 + ds1 * dt1);
+        let mut c: f64 = ds0 * ds0 + ds1 * ds1 + 1.0;
+        let inv_f: f64 = 1.0 / (a * c - b * b * 0.25);
+        a *= inv_f;
+        b *= inv_f;
+        c *= inv_f;
+
+        let det: f64 = 4.0 * a * c - b * b;
+        let inv_det: f64 = 1.0 / det;
+        let u_sqrt: f64 = (det * c).sqrt();
+        let v_sqrt: f64 = (a * det).sqrt();
+        let s0: i64 = (s - 2.0 * inv_det * u_sqrt).ceil() as i64;
+        let s1: i64 = (s + 2.0 * inv_det * u_sqrt).floor() as i64;
+        let t0: i64 = (t - 2.0 * inv_det * v_sqrt).ceil() as i64;
+        let t1: i64 = (t + 2.0 * inv_det * v_sqrt).floor() as i64;
 
-        img.bytes_per_scanline = img.width * img.bytes_per_pixel;
-        
-        img
+        let falloff: f64 = (-2.0_f64).exp();
+        let mut sum: [f64; 4] = [0.0; 4];
+        let mut weight_sum: f64 = 0.0;
+        for it in t0..=t1 {
+            let tt: f64 = it as f64 - t;
+            for is in s0..=s1 {
+                let ss: f64 = is as f64 - s;
+                let r2: f64 = a * ss * ss + b * ss * tt + c * tt * tt;
+                if r2 < 1.0 {
+                    let weight: f64 = (-2.0 * r2).exp() - falloff;
+                    let texel: [f64; 4] = self.texel(level, is, it);
+                    for ch in 0..4 {
+                        sum[ch] += texel[ch] * weight;
+                    }
+                    weight_sum += weight;
+                }
+            }
+        }
+        if weight_sum <= 0.0 {
+            return self.filter_level(level, u, v);
+        }
+        sum.map(|c: f64| -> f64 { c / weight_sum })
+    }
+
+    // Filtered RGBA at texture coordinates (u, v) covering `footprint`, both before the uv transform.
+    fn lookup(&self, u: f64, v: f64, footprint: &UvFootprint) -> [f64; 4] {
+        let (u, v) = self.transform.apply(u, v);
+        if self.mip_filter == MipFilter::None {
+            return self.filter_level(0, u, v);
+        }
+
+        // Footprint axes in transformed uv, and their lengths in full resolution texels.
+        let mut axis0: (f64, f64) = self.transform.apply_vector(footprint.dudx, footprint.dvdx);
+        let mut axis1: (f64, f64) = self.transform.apply_vector(footprint.dudy, footprint.dvdy);
+        let (width, height) = self.level_size(0);
+        let texel_length = |axis: &(f64, f64)| -> f64 { (axis.0 * width as f64).hypot(axis.1 * height as f64) };
+        let max_level: f64 = (self.level_count() - 1) as f64;
+
+        if self.mip_filter == MipFilter::Trilinear {
+            let width: f64 = texel_length(&axis0).max(texel_length(&axis1));
+            let level: f64 = width.max(1e-8).log2().clamp(0.0, max_level);
+            let lower: usize = level.floor() as usize;
+            let blend: f64 = level - level.floor();
+            if blend <= 0.0 {
+                return self.filter_level(lower, u, v);
+            }
+            let a: [f64; 4] = self.filter_level(lower, u, v);
+            let b: [f64; 4] = self.filter_level(lower + 1, u, v);
+            return [0, 1, 2, 3].map(|c: usize| -> f64 { a[c] * (1.0 - blend) + b[c] * blend });
+        }
+
+        // EWA picks the level from the minor axis, clamping the eccentricity so the loop stays short.
+        if texel_length(&axis0) < texel_length(&axis1) {
+            std::mem::swap(&mut axis0, &mut axis1);
+        }
+        let major: f64 = texel_length(&axis0);
+        let mut minor: f64 = texel_length(&axis1);
+        if minor <= 0.0 {
+            return self.filter_level(0, u, v);
+        }
+        if minor * EWA_MAX_ANISOTROPY < major {
+            let scale: f64 = major / (minor * EWA_MAX_ANISOTROPY);
+            axis1 = (axis1.0 * scale, axis1.1 * scale);
+            minor *= scale;
+        }
+        let level: f64 = minor.log2().clamp(0.0, max_level);
+        let lower: usize = level.floor() as usize;
+        let blend: f64 = level - level.floor();
+        let a: [f64; 4] = self.ewa_level(lower, u, v, axis0, axis1);
+        if blend <= 0.0 {
+            return a;
+        }
+        let b: [f64; 4] = self.ewa_level(lower + 1, u, v, axis0, axis1);
+        [0, 1, 2, 3].map(|c: usize| -> f64 { a[c] * (1.0 - blend) + b[c] * blend })
     }
 }
 
 impl Texture for ImageTexture {
-    fn value(&self, u:f64, v:f64, _w:f64) -> Color {
-        if self.height == 0 {
+    fn value(&self, u:f64, v:f64, w:f64) -> Color {
+        self.value_filtered(u, v, w, &UvFootprint::default())
+    }
+
+    fn value_filtered(&self, u:f64, v:f64, _w:f64, footprint: &UvFootprint) -> Color {
+        if self.image.is_none() {
             return Color::new(1.0,0.0, 1.0);
         }
 
-        let s_u: f64 = saturate(u);
-        let s_v: f64 = saturate(v);
-
-        let x: usize = (s_u * self.width as f64) as usize;
-        let y: usize = (s_v * self.height as f64) as usize;
-        let pos: usize = x * self.bytes_per_pixel + y * self.bytes_per_scanline;
-        let mut color: Color = Color::black();
-
-        color[0] = self.data[pos + 2] as f64 / 255.0;
-        color[1] = self.data[pos + 1] as f64 / 255.0;
-        color[2] = self.data[pos + 0] as f64 / 255.0;
+        let rgba: [f64; 4] = self.lookup(u, v, footprint);
+        Color::new(rgba[0], rgba[1], rgba[2])
+    }
 
-        color
+    fn alpha(&self, u:f64, v:f64, _w:f64) -> f64 {
+        if !self.image.as_ref().is_some_and(|image: &Arc<CachedImage>| -> bool { image.has_alpha }) {
+            return 1.0;
+        }
+        self.lookup(u, v, &UvFootprint::default())[3]
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,615 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+use crate::color_space::{conversion_matrix, working_space, ColorSpace};
+use crate::image::{load_image, Image, ImageError};
+use crate::mat3::Mat3;
+use crate::vec3::Color;
+
+// Image textures are loaded once per file and colour space and shared by every texture that asks for them.
+// Mip pyramids are stored as square tiles, optionally as half floats.  Under a memory budget the least
+// recently used tiles are dropped.  A lookup that needs one again rebuilds just that tile from the decoded
+// file, which is kept for later misses until it's the least recently used in turn.
+
+pub const TILE_SIZE: usize = 64;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TextureCacheOptions {
+    // Bytes of texel data to keep resident, unlimited when not set.
+    pub memory_budget: Option<usize>,
+    // Store texels as 16-bit floats, half the memory for about three significant digits.
+    pub half: bool
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct CacheKey {
+    path: PathBuf,
+    color_space: Option<ColorSpace>
+}
+
+// One level of the mip pyramid while it's being built, RGBA with the bottom row first.
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<[f32; 4]>
+}
+
+// Pixel of the decoded image in the working space.  Alpha is always linear.
+fn to_working(pixel: &[f32; 4], color_space: ColorSpace, matrix: &Mat3) -> [f32; 4] {
+    let encoded: Color = Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64);
+    let c: Color = *matrix * color_space.decode(&encoded);
+    [c.r() as f32, c.g() as f32, c.b() as f32, pixel[3]]
+}
+
+// Texel (x, y) of a level, the average of its 2x2 block on the level before, read through `at`.  Odd sizes
+// drop their last row or column.
+fn downsample(prev_width: usize, prev_height: usize, x: usize, y: usize, at: impl Fn(usize, usize) -> [f32; 4]) -> [f32; 4] {
+    let mut sum: [f32; 4] = [0.0; 4];
+    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+        let t: [f32; 4] = at((2 * x + dx).min(prev_width - 1), (2 * y + dy).min(prev_height - 1));
+        for c in 0..4 {
+            sum[c] += t[c] * 0.25;
+        }
+    }
+    sum
+}
+
+// Converts to the working space, then box filters each level from the one before.
+fn build_pyramid(image: &Image, color_space: ColorSpace) -> Vec<MipLevel> {
+    let matrix: Mat3 = conversion_matrix(color_space, working_space());
+    let texels: Vec<[f32; 4]> = image.pixels.iter().map(|t: &[f32; 4]| -> [f32; 4] { to_working(t, color_space, &matrix) }).collect();
+
+    let mut levels: Vec<MipLevel> = vec![MipLevel { width: image.width, height: image.height, texels: texels }];
+    while levels.last().is_some_and(|l: &MipLevel| -> bool { l.width > 1 || l.height > 1 }) {
+        let prev: &MipLevel = levels.last().unwrap();
+        let (width, height) = ((prev.width / 2).max(1), (prev.height / 2).max(1));
+        let mut texels: Vec<[f32; 4]> = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                texels.push(downsample(prev.width, prev.height, x, y, |sx: usize, sy: usize| -> [f32; 4] { prev.texels[sx + sy * prev.width] }));
+            }
+        }
+        levels.push(MipLevel { width: width, height: height, texels: texels });
+    }
+    levels
+}
+
+// Texels in columns `xs` and rows `ys` of `level`, filtered down from just the part of the decoded file
+// under them.  The arithmetic is build_pyramid's, so they match the tiles it made.
+fn build_region(source: &Source, layouts: &[LevelLayout], level: usize, xs: Range<usize>, ys: Range<usize>) -> Vec<[f32; 4]> {
+    let mut texels: Vec<[f32; 4]> = Vec::with_capacity(xs.len() * ys.len());
+    if level == 0 {
+        let matrix: Mat3 = conversion_matrix(source.color_space, working_space());
+        for y in ys {
+            for x in xs.clone() {
+                texels.push(to_working(&source.pixel(x + y * source.width), source.color_space, &matrix));
+            }
+        }
+        return texels;
+    }
+
+    let prev: &LevelLayout = &layouts[level - 1];
+    let prev_xs: Range<usize> = (2 * xs.start).min(prev.width - 1)..(2 * xs.end - 1).min(prev.width - 1) + 1;
+    let prev_ys: Range<usize> = (2 * ys.start).min(prev.height - 1)..(2 * ys.end - 1).min(prev.height - 1) + 1;
+    let (x0, y0, stride) = (prev_xs.start, prev_ys.start, prev_xs.len());
+    let above: Vec<[f32; 4]> = build_region(source, layouts, level - 1, prev_xs, prev_ys);
+    for y in ys {
+        for x in xs.clone() {
+            texels.push(downsample(prev.width, prev.height, x, y, |sx: usize, sy: usize| -> [f32; 4] { above[sx - x0 + (sy - y0) * stride] }));
+        }
+    }
+    texels
+}
+
+enum SourcePixels {
+    Bytes(Vec<[u8; 4]>),
+    Full(Vec<[f32; 4]>)
+}
+
+// A decoded file kept to rebuild evicted tiles from.  Images that came from 8-bit samples are kept as bytes,
+// a quarter of the memory.
+struct Source {
+    width: usize,
+    color_space: ColorSpace,
+    pixels: SourcePixels
+}
+
+impl Source {
+    fn new(image: Image, color_space: ColorSpace) -> Source {
+        let byte = |c: f32| -> u8 { (c * 255.0).round().clamp(0.0, 255.0) as u8 };
+        let exact: bool = image.pixels.iter().flatten().all(|c: &f32| -> bool { byte(*c) as f32 / 255.0 == *c });
+        let pixels: SourcePixels = if exact {
+            SourcePixels::Bytes(image.pixels.iter().map(|t: &[f32; 4]| -> [u8; 4] { t.map(byte) }).collect())
+        } else {
+            SourcePixels::Full(image.pixels)
+        };
+        Source { width: image.width, color_space: color_space, pixels: pixels }
+    }
+
+    fn pixel(&self, index: usize) -> [f32; 4] {
+        match &self.pixels {
+            SourcePixels::Bytes(pixels) => pixels[index].map(|c: u8| -> f32 { c as f32 / 255.0 }),
+            SourcePixels::Full(pixels) => pixels[index]
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        match &self.pixels {
+            SourcePixels::Bytes(pixels) => pixels.len() * std::mem::size_of::<[u8; 4]>(),
+            SourcePixels::Full(pixels) => pixels.len() * std::mem::size_of::<[f32; 4]>()
+        }
+    }
+}
+
+// IEEE 754 binary16, rounding to nearest even.  Values too large for it saturate to the largest finite half.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits: u32 = value.to_bits();
+    let sign: u16 = ((bits >> 16) & 0x8000) as u16;
+    let exponent: i32 = ((bits >> 23) & 0xFF) as i32 - 127 + 15;
+    let mantissa: u32 = bits & 0x007F_FFFF;
+
+    if value.is_nan() {
+        return sign | 0x7E00;
+    }
+    if exponent >= 31 {
+        return sign | 0x7BFF;
+    }
+    if exponent <= 0 {
+        // Subnormal, or zero below the smallest one.
+        if exponent < -10 {
+            return sign;
+        }
+        let m: u32 = mantissa | 0x0080_0000;
+        let shift: u32 = (14 - exponent) as u32;
+        let half: u32 = m >> shift;
+        let remainder: u32 = m & ((1 << shift) - 1);
+        let midpoint: u32 = 1 << (shift - 1);
+        let round: u32 = if remainder > midpoint || (remainder == midpoint && half & 1 == 1) { 1 } else { 0 };
+        return sign | (half + round) as u16;
+    }
+
+    let half: u32 = ((exponent as u32) << 10) | (mantissa >> 13);
+    let remainder: u32 = mantissa & 0x1FFF;
+    let round: u32 = if remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1) { 1 } else { 0 };
+    // Rounding can carry into the exponent, which saturates like the values above.
+    (sign as u32 | (half + round).min(0x7BFF)) as u16
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign: u32 = ((half & 0x8000) as u32) << 16;
+    let exponent: u32 = ((half >> 10) & 0x1F) as u32;
+    let mantissa: u32 = (half & 0x03FF) as u32;
+    let magnitude: f32 = match exponent {
+        0 => mantissa as f32 * 2.0_f32.powi(-24),
+        31 => if mantissa == 0 { f32::INFINITY } else { f32::NAN },
+        _ => f32::from_bits(((exponent + 127 - 15) << 23) | (mantissa << 13))
+    };
+    f32::from_bits(sign | magnitude.to_bits())
+}
+
+enum TileData {
+    Full(Vec<[f32; 4]>),
+    Half(Vec<[u16; 4]>)
+}
+
+impl TileData {
+    fn new(texels: Vec<[f32; 4]>, half: bool) -> TileData {
+        if half {
+            return TileData::Half(texels.iter().map(|t: &[f32; 4]| -> [u16; 4] { t.map(f32_to_f16) }).collect());
+        }
+        TileData::Full(texels)
+    }
+
+    fn texel(&self, index: usize) -> [f32; 4] {
+        match self {
+            TileData::Full(texels) => texels[index],
+            TileData::Half(texels) => texels[index].map(f16_to_f32)
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        match self {
+            TileData::Full(texels) => texels.len() * std::mem::size_of::<[f32; 4]>(),
+            TileData::Half(texels) => texels.len() * std::mem::size_of::<[u16; 4]>()
+        }
+    }
+}
+
+#[derive(Default)]
+struct Tile {
+    data: RwLock<Option<TileData>>,
+    // Cache clock when the tile was last looked up under a budget, the lowest are evicted first.
+    last_used: AtomicU64
+}
+
+struct LevelLayout {
+    width: usize,
+    height: usize,
+    tiles_x: usize,
+    first_tile: usize
+}
+
+// A mip pyramid in the working space, shared between textures.
+pub struct CachedImage {
+    pub has_alpha: bool,
+    key: CacheKey,
+    levels: Vec<LevelLayout>,
+    tiles: Vec<Tile>,
+    // The decoded file, kept after a miss so the next ones don't decode it again.
+    source: RwLock<Option<Source>>,
+    source_last_used: AtomicU64,
+    // Held while reading evicted tiles back so only one thread decodes the file.
+    reloading: Mutex<()>
+}
+
+impl CachedImage {
+    fn new(key: CacheKey, has_alpha: bool, pyramid: &[MipLevel]) -> CachedImage {
+        let mut levels: Vec<LevelLayout> = Vec::with_capacity(pyramid.len());
+        let mut tile_count: usize = 0;
+        for level in pyramid {
+            let tiles_x: usize = level.width.div_ceil(TILE_SIZE);
+            levels.push(LevelLayout { width: level.width, height: level.height, tiles_x: tiles_x, first_tile: tile_count });
+            tile_count += tiles_x * level.height.div_ceil(TILE_SIZE);
+        }
+        CachedImage {
+            has_alpha: has_alpha,
+            key: key,
+            levels: levels,
+            tiles: (0..tile_count).map(|_| -> Tile { Tile::default() }).collect(),
+            source: RwLock::new(None),
+            source_last_used: AtomicU64::new(0),
+            reloading: Mutex::new(())
+        }
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn level_size(&self, level: usize) -> (usize, usize) {
+        (self.levels[level].width, self.levels[level].height)
+    }
+
+    // Level a tile index belongs to.
+    fn tile_level(&self, tile: usize) -> usize {
+        self.levels.iter().rposition(|l: &LevelLayout| -> bool { l.first_tile <= tile }).unwrap_or(0)
+    }
+
+    // Tile index of texel (x, y) on `level` and the texel's index within it.
+    fn locate(&self, level: usize, x: usize, y: usize) -> (usize, usize) {
+        let layout: &LevelLayout = &self.levels[level];
+        let (tx, ty) = (x / TILE_SIZE, y / TILE_SIZE);
+        let tile_width: usize = TILE_SIZE.min(layout.width - tx * TILE_SIZE);
+        (layout.first_tile + tx + ty * layout.tiles_x, (x % TILE_SIZE) + (y % TILE_SIZE) * tile_width)
+    }
+
+    // RGBA of texel (x, y) on `level`, which must be inside the level.
+    pub fn texel(&self, level: usize, x: usize, y: usize) -> [f32; 4] {
+        let (tile_index, index) = self.locate(level, x, y);
+        let tile: &Tile = &self.tiles[tile_index];
+        let cache: &TextureCache = texture_cache();
+        cache.touch(tile);
+        if let Some(data) = tile.data.read().unwrap().as_ref() {
+            return data.texel(index);
+        }
+        cache.reload(self, tile_index, index)
+    }
+}
+
+#[derive(Default)]
+struct CacheStats {
+    requests: AtomicUsize,
+    files_read: AtomicUsize,
+    reloads: AtomicUsize,
+    tiles_evicted: AtomicUsize,
+    peak_bytes: AtomicUsize
+}
+
+// Something eviction can drop.
+enum Resident<'a> {
+    Tile(&'a Tile),
+    Source(&'a CachedImage)
+}
+
+pub struct TextureCache {
+    pub options: TextureCacheOptions,
+    images: Mutex<HashMap<CacheKey, Arc<CachedImage>>>,
+    resident_bytes: AtomicUsize,
+    // Advanced whenever something is loaded, not per lookup, so texel reads don't contend on it.  Tiles
+    // looked up between two loads share a stamp, which is as fine a recency as eviction needs.
+    clock: AtomicU64,
+    evicting: Mutex<()>,
+    stats: CacheStats
+}
+
+static TEXTURE_CACHE: OnceLock<TextureCache> = OnceLock::new();
+
+// Must be called before any texture is loaded, returns false if the cache already exists.
+pub fn set_texture_cache_options(options: TextureCacheOptions) -> bool {
+    TEXTURE_CACHE.set(TextureCache::new(options)).is_ok()
+}
+
+pub fn texture_cache() -> &'static TextureCache {
+    TEXTURE_CACHE.get_or_init(|| -> TextureCache { TextureCache::new(TextureCacheOptions::default()) })
+}
+
+fn megabytes(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+impl TextureCache {
+    fn new(options: TextureCacheOptions) -> TextureCache {
+        TextureCache {
+            options: options,
+            images: Mutex::new(HashMap::new()),
+            resident_bytes: AtomicUsize::new(0),
+            clock: AtomicU64::new(1),
+            evicting: Mutex::new(()),
+            stats: CacheStats::default()
+        }
+    }
+
+    // The shared image for a file, decoded in its usual colour space unless `color_space` overrides it.
+    pub fn get(&self, file_path: &Path, color_space: Option<ColorSpace>) -> Result<Arc<CachedImage>, ImageError> {
+        self.stats.requests.fetch_add(1, Ordering::Relaxed);
+        let path: PathBuf = file_path.canonicalize().unwrap_or(file_path.to_path_buf());
+        let key: CacheKey = CacheKey { path: path, color_space: color_space };
+        if let Some(image) = self.images.lock().unwrap().get(&key) {
+            return Ok(image.clone());
+        }
+
+        let image: Image = self.decode(&key)?;
+        let color_space: ColorSpace = key.color_space.unwrap_or(image.color_space);
+        let pyramid: Vec<MipLevel> = build_pyramid(&image, color_space);
+        let cached: CachedImage = CachedImage::new(key.clone(), image.has_alpha, &pyramid);
+
+        // Under a budget the coarse levels go in first, and tiles that don't fit wait for a lookup to need
+        // them.  The decoded file is kept for those lookups to rebuild from.
+        let mut room: usize = self.options.memory_budget.map_or(usize::MAX, |budget: usize| -> usize {
+            budget.saturating_sub(self.resident_bytes.load(Ordering::Relaxed))
+        });
+        let texel_count: usize = pyramid.iter().map(|l: &MipLevel| -> usize { l.texels.len() }).sum();
+        if texel_count * self.texel_bytes() > room {
+            let source: Source = Source::new(image, color_space);
+            if source.bytes() < room {
+                room -= source.bytes();
+                self.add_resident(source.bytes());
+                *cached.source.write().unwrap() = Some(source);
+                cached.source_last_used.store(self.tick(), Ordering::Relaxed);
+            }
+        }
+        for (level, layout) in cached.levels.iter().enumerate().rev() {
+            let level_tiles: usize = layout.tiles_x * layout.height.div_ceil(TILE_SIZE);
+            for tile in layout.first_tile..layout.first_tile + level_tiles {
+                let (xs, ys) = tile_bounds(layout, tile);
+                let bytes: usize = xs.len() * ys.len() * self.texel_bytes();
+                if bytes > room {
+                    continue;
+                }
+                room -= bytes;
+                let mut texels: Vec<[f32; 4]> = Vec::with_capacity(xs.len() * ys.len());
+                for y in ys {
+                    texels.extend_from_slice(&pyramid[level].texels[xs.start + y * layout.width..xs.end + y * layout.width]);
+                }
+                self.store(&cached.tiles[tile], texels);
+            }
+        }
+        let cached: Arc<CachedImage> = Arc::new(cached);
+        self.images.lock().unwrap().insert(key, cached.clone());
+        Ok(cached)
+    }
+
+    fn decode(&self, key: &CacheKey) -> Result<Image, ImageError> {
+        self.stats.files_read.fetch_add(1, Ordering::Relaxed);
+        load_image(&key.path)
+    }
+
+    fn texel_bytes(&self) -> usize {
+        if self.options.half { std::mem::size_of::<[u16; 4]>() } else { std::mem::size_of::<[f32; 4]>() }
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    // Stamps a tile as used now.  Without a budget nothing is evicted, so nothing needs the stamp.
+    fn touch(&self, tile: &Tile) {
+        if self.options.memory_budget.is_none() {
+            return;
+        }
+        let now: u64 = self.clock.load(Ordering::Relaxed);
+        if tile.last_used.load(Ordering::Relaxed) != now {
+            tile.last_used.store(now, Ordering::Relaxed);
+        }
+    }
+
+    fn add_resident(&self, bytes: usize) {
+        let resident: usize = self.resident_bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.stats.peak_bytes.fetch_max(resident, Ordering::Relaxed);
+    }
+
+    fn store(&self, tile: &Tile, texels: Vec<[f32; 4]>) {
+        let data: TileData = TileData::new(texels, self.options.half);
+        let bytes: usize = data.bytes();
+        *tile.data.write().unwrap() = Some(data);
+        tile.last_used.store(self.tick(), Ordering::Relaxed);
+        self.add_resident(bytes);
+    }
+
+    // Restores the evicted tile a lookup missed and returns the texel from it.  The tile is rebuilt from the
+    // decoded file, which is only read again if it was evicted too.
+    fn reload(&self, image: &CachedImage, tile_index: usize, index: usize) -> [f32; 4] {
+        let _guard: std::sync::MutexGuard<'_, ()> = image.reloading.lock().unwrap();
+        // Another thread may have read it back while this one waited.
+        if let Some(data) = image.tiles[tile_index].data.read().unwrap().as_ref() {
+            return data.texel(index);
+        }
+
+        self.stats.reloads.fetch_add(1, Ordering::Relaxed);
+        let level: usize = image.tile_level(tile_index);
+        let rebuild = |source: &Source| -> Vec<[f32; 4]> {
+            let (xs, ys) = tile_bounds(&image.levels[level], tile_index);
+            build_region(source, &image.levels, level, xs, ys)
+        };
+        let kept: Option<Vec<[f32; 4]>> = image.source.read().unwrap().as_ref().map(rebuild);
+        let texels: Vec<[f32; 4]> = match kept {
+            Some(texels) => texels,
+            None => match self.decode(&image.key) {
+                Ok(decoded) => {
+                    let color_space: ColorSpace = image.key.color_space.unwrap_or(decoded.color_space);
+                    let source: Source = Source::new(decoded, color_space);
+                    let texels: Vec<[f32; 4]> = rebuild(&source);
+                    self.add_resident(source.bytes());
+                    *image.source.write().unwrap() = Some(source);
+                    texels
+                }
+                Err(e) => {
+                    eprintln!("Failed to reload texture {}: {}", image.key.path.display(), e);
+                    return [1.0, 0.0, 1.0, 1.0];
+                }
+            }
+        };
+        image.source_last_used.store(self.tick(), Ordering::Relaxed);
+
+        let texel: [f32; 4] = texels[index];
+        self.store(&image.tiles[tile_index], texels);
+        self.evict(&image.tiles[tile_index]);
+        texel
+    }
+
+    // Drops the least recently used tiles and decoded files until the resident texels fit the budget.
+    // Only `keep`, the tile a lookup is about to read from, stays regardless.
+    fn evict(&self, keep: &Tile) {
+        let Some(budget) = self.options.memory_budget else {
+            return;
+        };
+        if self.resident_bytes.load(Ordering::Relaxed) <= budget {
+            return;
+        }
+        let _guard: std::sync::MutexGuard<'_, ()> = self.evicting.lock().unwrap();
+        let images: Vec<Arc<CachedImage>> = self.images.lock().unwrap().values().cloned().collect();
+        let mut resident: Vec<(u64, Resident)> = Vec::new();
+        for image in images.iter() {
+            if image.source.read().unwrap().is_some() {
+                resident.push((image.source_last_used.load(Ordering::Relaxed), Resident::Source(image)));
+            }
+            for tile in image.tiles.iter() {
+                if std::ptr::eq(keep, tile) {
+                    continue;
+                }
+                if tile.data.read().unwrap().is_some() {
+                    resident.push((tile.last_used.load(Ordering::Relaxed), Resident::Tile(tile)));
+                }
+            }
+        }
+        resident.sort_by_key(|(last_used, _)| -> u64 { *last_used });
+
+        for (_, entry) in resident {
+            if self.resident_bytes.load(Ordering::Relaxed) <= budget {
+                break;
+            }
+            let freed: Option<usize> = match entry {
+                Resident::Tile(tile) => {
+                    let freed: Option<usize> = tile.data.write().unwrap().take().map(|data: TileData| -> usize { data.bytes() });
+                    if freed.is_some() {
+                        self.stats.tiles_evicted.fetch_add(1, Ordering::Relaxed);
+                    }
+                    freed
+                }
+                Resident::Source(image) => image.source.write().unwrap().take().map(|source: Source| -> usize { source.bytes() })
+            };
+            if let Some(bytes) = freed {
+                self.resident_bytes.fetch_sub(bytes, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn print_stats(&self) {
+        let images: std::sync::MutexGuard<'_, HashMap<CacheKey, Arc<CachedImage>>> = self.images.lock().unwrap();
+        let requests: usize = self.stats.requests.load(Ordering::Relaxed);
+        if requests == 0 {
+            return;
+        }
+        let budget: String = match self.options.memory_budget {
+            Some(bytes) => format!("{:.1} MB", megabytes(bytes)),
+            None => String::from("unlimited")
+        };
+        println!("Texture cache: {} textures sharing {} images, {} file reads, {} reloads after eviction",
+            requests,
+            images.len(),
+            self.stats.files_read.load(Ordering::Relaxed),
+            self.stats.reloads.load(Ordering::Relaxed)
+        );
+        println!("Texture memory: {:.1} MB resident, {:.1} MB peak, budget {}, {} tiles evicted{}",
+            megabytes(self.resident_bytes.load(Ordering::Relaxed)),
+            megabytes(self.stats.peak_bytes.load(Ordering::Relaxed)),
+            budget,
+            self.stats.tiles_evicted.load(Ordering::Relaxed),
+            if self.options.half { ", half float" } else { "" }
+        );
+    }
+}
+
+// Columns and rows of the level `layout` describes that tile `tile`, an index into the image's tile list, covers.
+fn tile_bounds(layout: &LevelLayout, tile: usize) -> (Range<usize>, Range<usize>) {
+    let local: usize = tile - layout.first_tile;
+    let (x0, y0) = ((local % layout.tiles_x) * TILE_SIZE, (local / layout.tiles_x) * TILE_SIZE);
+    (x0..(x0 + TILE_SIZE).min(layout.width), y0..(y0 + TILE_SIZE).min(layout.height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_rebuilt_tiles(width: usize, height: usize, pixel: impl Fn(usize, usize) -> [f32; 4], bytes: bool) {
+        let image: Image = Image {
+            width: width,
+            height: height,
+            has_alpha: true,
+            color_space: ColorSpace::Srgb,
+            pixels: (0..width * height).map(|i: usize| -> [f32; 4] { pixel(i % width, i / width) }).collect()
+        };
+        let pyramid: Vec<MipLevel> = build_pyramid(&image, ColorSpace::Srgb);
+        let cached: CachedImage = CachedImage::new(CacheKey { path: PathBuf::new(), color_space: None }, true, &pyramid);
+        let source: Source = Source::new(image, ColorSpace::Srgb);
+        assert_eq!(matches!(source.pixels, SourcePixels::Bytes(_)), bytes);
+
+        for (level, layout) in cached.levels.iter().enumerate() {
+            let level_tiles: usize = layout.tiles_x * layout.height.div_ceil(TILE_SIZE);
+            for tile in layout.first_tile..layout.first_tile + level_tiles {
+                let (xs, ys) = tile_bounds(layout, tile);
+                let rebuilt: Vec<[f32; 4]> = build_region(&source, &cached.levels, level, xs.clone(), ys.clone());
+                let mut i: usize = 0;
+                for y in ys {
+                    for x in xs.clone() {
+                        assert_eq!(rebuilt[i].map(f32::to_bits), pyramid[level].texels[x + y * layout.width].map(f32::to_bits), "level {} texel ({}, {})", level, x, y);
+                        i += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rebuilt_tiles_match_the_pyramid() {
+        let byte = |v: usize| -> f32 { (v % 256) as f32 / 255.0 };
+        check_rebuilt_tiles(150, 70, |x: usize, y: usize| -> [f32; 4] { [byte(x * 7 + y), byte(y * 13), byte(x * y), byte(x + 3 * y)] }, true);
+        // Odd sizes and a level one texel high before the pyramid ends.
+        check_rebuilt_tiles(301, 3, |x: usize, y: usize| -> [f32; 4] { [byte(x), byte(y * 100), byte(x * 31 + y), 1.0] }, true);
+        let float = |v: usize| -> f32 { (v % 97) as f32 * 0.0173 };
+        check_rebuilt_tiles(97, 130, |x: usize, y: usize| -> [f32; 4] { [float(x * 5 + y), float(y * 3), 2.5, float(x)] }, false);
+    }
+
+    #[test]
+    fn half_float_round_trip() {
+        for value in [0.0_f32, 1.0, -2.5, 0.333, 65504.0, 6.1e-5, 3.0e-7] {
+            let back: f32 = f16_to_f32(f32_to_f16(value));
+            assert!((back - value).abs() <= value.abs() / 1024.0 + 6.0e-8, "{} -> {}", value, back);
+        }
+        assert_eq!(f16_to_f32(f32_to_f16(1.0e6)), 65504.0);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+    }
+}
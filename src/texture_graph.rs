@@ -0,0 +1,764 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use clap::ValueEnum;
+
+use crate::color_space::ColorSpace;
+use crate::hittable::HitRecord;
+use crate::procedural::*;
+use crate::projection::{Projection, ProjectedTexture};
+use crate::texture::{solid_color, solid_value, CheckerTexture, ImageTexture, Texture, UvFootprint};
+use crate::vec3::{Color, Point3};
+
+// Texture node graphs, written as text and compiled into a tree of textures evaluated per hit.
+//
+// Each statement names a node, later statements can use it by name:
+//
+//     # Veined marble
+//     n = noise(object, scale=2.5, octaves=6)
+//     marble = ramp(n, 0.45, rgb(0.8, 0.8, 0.78), 0.5, rgb(0.2, 0.2, 0.25), 0.55, rgb(0.8, 0.8, 0.78))
+//     rough = clamp(add(multiply(n, 0.5), 0.2), max=0.6)
+//
+// Arguments are positional or `name=value`.  Numbers are grey constants, scalar inputs read the red channel.
+
+// Binary operations on two textures, per channel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MathOp {
+    Add,
+    Subtract,
+    Multiply,
+    Power,
+    Minimum,
+    Maximum
+}
+
+pub struct MathTexture {
+    pub op: MathOp,
+    pub a: Arc<dyn Texture + Sync>,
+    pub b: Arc<dyn Texture + Sync>
+}
+
+impl MathTexture {
+    pub fn new(op: MathOp, a: &Arc<dyn Texture + Sync>, b: &Arc<dyn Texture + Sync>) -> MathTexture {
+        MathTexture { op: op, a: a.clone(), b: b.clone() }
+    }
+
+    fn apply(&self, a: &Color, b: &Color) -> Color {
+        let f = |x: f64, y: f64| -> f64 {
+            match self.op {
+                MathOp::Add => x + y,
+                MathOp::Subtract => x - y,
+                MathOp::Multiply => x * y,
+                // Negative bases would go complex, they're clamped to zero.
+                MathOp::Power => x.max(0.0).powf(y),
+                MathOp::Minimum => x.min(y),
+                MathOp::Maximum => x.max(y)
+            }
+        };
+        Color::new(f(a.r(), b.r()), f(a.g(), b.g()), f(a.b(), b.b()))
+    }
+}
+
+impl Texture for MathTexture {
+    fn value(&self, u:f64, v:f64, w:f64) -> Color {
+        self.value_filtered(u, v, w, &UvFootprint::default())
+    }
+
+    fn value_filtered(&self, u:f64, v:f64, w:f64, footprint: &UvFootprint) -> Color {
+        self.apply(&self.a.value_filtered(u, v, w, footprint), &self.b.value_filtered(u, v, w, footprint))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.apply(&self.a.value_at(rec), &self.b.value_at(rec))
+    }
+}
+
+pub struct ClampTexture {
+    pub input: Arc<dyn Texture + Sync>,
+    pub min: f64,
+    pub max: f64
+}
+
+impl ClampTexture {
+    fn apply(&self, c: &Color) -> Color {
+        Color::new(c.r().clamp(self.min, self.max), c.g().clamp(self.min, self.max), c.b().clamp(self.min, self.max))
+    }
+}
+
+impl Texture for ClampTexture {
+    fn value(&self, u:f64, v:f64, w:f64) -> Color {
+        self.value_filtered(u, v, w, &UvFootprint::default())
+    }
+
+    fn value_filtered(&self, u:f64, v:f64, w:f64, footprint: &UvFootprint) -> Color {
+        self.apply(&self.input.value_filtered(u, v, w, footprint))
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        self.apply(&self.input.value_at(rec))
+    }
+}
+
+// The coordinates a texture would be evaluated at, as a colour.  Texture coordinates for `Uv`.
+#[derive(Copy, Clone, Default)]
+pub struct CoordinateTexture {
+    pub space: TextureSpace
+}
+
+impl Texture for CoordinateTexture {
+    fn value(&self, u:f64, v:f64, w:f64) -> Color {
+        Color::new(u, v, w)
+    }
+
+    fn value_at(&self, rec: &HitRecord) -> Color {
+        match self.space {
+            TextureSpace::Uv => rec.uvw,
+            TextureSpace::Object => rec.object_p,
+            TextureSpace::World => rec.p
+        }
+    }
+}
+
+fn invalid(line: usize, msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Texture graph line {}: {}", line, msg))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Name(String),
+    Number(f64),
+    Text(String),
+    Open,
+    Close,
+    Comma,
+    Equals
+}
+
+fn tokenize(line: &str, line_number: usize) -> Result<Vec<Token>, Error> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let chars: Vec<char> = line.chars().collect();
+    let mut i: usize = 0;
+    while i < chars.len() {
+        let c: char = chars[i];
+        match c {
+            '#' => break,
+            ' ' | '\t' | '\r' => i += 1,
+            '(' => { tokens.push(Token::Open); i += 1; }
+            ')' => { tokens.push(Token::Close); i += 1; }
+            ',' => { tokens.push(Token::Comma); i += 1; }
+            '=' => { tokens.push(Token::Equals); i += 1; }
+            '"' => {
+                let end: usize = chars[i + 1..].iter().position(|c: &char| -> bool { *c == '"' })
+                    .ok_or_else(|| invalid(line_number, "unterminated string"))?;
+                tokens.push(Token::Text(chars[i + 1..i + 1 + end].iter().collect()));
+                i += end + 2;
+            }
+            _ if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let start: usize = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == 'e' || (chars[i] == '-' && chars[i - 1] == 'e')) {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let number: f64 = text.parse().map_err(|_| invalid(line_number, &format!("bad number `{}`", text)))?;
+                tokens.push(Token::Number(number));
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let start: usize = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '-') {
+                    i += 1;
+                }
+                tokens.push(Token::Name(chars[start..i].iter().collect()));
+            }
+            _ => return Err(invalid(line_number, &format!("unexpected `{}`", c)))
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(f64),
+    Text(String),
+    // A node defined earlier, or a keyword like `object`.
+    Name(String),
+    Call(String, Vec<(Option<String>, Expr)>)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    line: usize
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token: Option<Token> = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset)
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), Error> {
+        match self.next() {
+            Some(t) if t == token => Ok(()),
+            _ => Err(invalid(self.line, &format!("expected {:?}", token)))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, Error> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Text(s)) => Ok(Expr::Text(s)),
+            Some(Token::Name(name)) => {
+                if self.peek(0) != Some(&Token::Open) {
+                    return Ok(Expr::Name(name));
+                }
+                self.pos += 1;
+                let mut args: Vec<(Option<String>, Expr)> = Vec::new();
+                if self.peek(0) == Some(&Token::Close) {
+                    self.pos += 1;
+                    return Ok(Expr::Call(name, args));
+                }
+                loop {
+                    let key: Option<String> = match (self.peek(0), self.peek(1)) {
+                        (Some(Token::Name(key)), Some(Token::Equals)) => Some(key.clone()),
+                        _ => None
+                    };
+                    if key.is_some() {
+                        self.pos += 2;
+                    }
+                    args.push((key, self.expr()?));
+                    match self.next() {
+                        Some(Token::Comma) => continue,
+                        Some(Token::Close) => break,
+                        _ => return Err(invalid(self.line, &format!("expected `,` or `)` in `{}`", name)))
+                    }
+                }
+                Ok(Expr::Call(name, args))
+            }
+            _ => Err(invalid(self.line, "expected a value"))
+        }
+    }
+}
+
+// Arguments of one node, taken by name or else by position as the node's builder asks for them.
+struct Args {
+    node: String,
+    line: usize,
+    positional: Vec<Expr>,
+    named: HashMap<String, Expr>
+}
+
+impl Args {
+    fn new(node: &str, line: usize, arguments: &[(Option<String>, Expr)]) -> Args {
+        let mut args: Args = Args { node: node.to_string(), line: line, positional: Vec::new(), named: HashMap::new() };
+        for (key, value) in arguments {
+            match key {
+                Some(key) => { args.named.insert(key.clone(), value.clone()); }
+                None => args.positional.push(value.clone())
+            }
+        }
+        args
+    }
+
+    fn take(&mut self, name: &str) -> Option<Expr> {
+        if let Some(expr) = self.named.remove(name) {
+            return Some(expr);
+        }
+        if self.positional.is_empty() {
+            return None;
+        }
+        Some(self.positional.remove(0))
+    }
+
+    fn error(&self, msg: &str) -> Error {
+        invalid(self.line, &format!("{}: {}", self.node, msg))
+    }
+
+    // Complains about arguments no parameter asked for.
+    fn finish(&self) -> Result<(), Error> {
+        if let Some(name) = self.named.keys().next() {
+            return Err(self.error(&format!("unknown argument `{}`", name)));
+        }
+        if !self.positional.is_empty() {
+            return Err(self.error("too many arguments"));
+        }
+        Ok(())
+    }
+}
+
+// Compiled graph, every named node by name.
+#[derive(Default)]
+pub struct TextureGraph {
+    pub nodes: HashMap<String, Arc<dyn Texture + Sync>>,
+    // Images are found relative to the graph file.
+    base_dir: PathBuf
+}
+
+impl TextureGraph {
+    pub fn load(file_path: &Path) -> Result<TextureGraph, Error> {
+        let source: String = fs::read_to_string(file_path)?;
+        let base_dir: PathBuf = file_path.parent().map(Path::to_path_buf).unwrap_or_default();
+        TextureGraph::parse(&source, &base_dir)
+    }
+
+    pub fn parse(source: &str, base_dir: &Path) -> Result<TextureGraph, Error> {
+        let mut graph: TextureGraph = TextureGraph { nodes: HashMap::new(), base_dir: base_dir.to_path_buf() };
+        for (index, line) in source.lines().enumerate() {
+            let tokens: Vec<Token> = tokenize(line, index + 1)?;
+            if tokens.is_empty() {
+                continue;
+            }
+            let mut parser: Parser = Parser { tokens: tokens, pos: 0, line: index + 1 };
+            let name: String = match parser.next() {
+                Some(Token::Name(name)) => name,
+                _ => return Err(invalid(index + 1, "expected a node name"))
+            };
+            parser.expect(Token::Equals)?;
+            let expr: Expr = parser.expr()?;
+            if parser.pos < parser.tokens.len() {
+                return Err(invalid(index + 1, "unexpected text after the node"));
+            }
+            let texture: Arc<dyn Texture + Sync> = graph.compile(&expr, index + 1)?;
+            graph.nodes.insert(name, texture);
+        }
+        Ok(graph)
+    }
+
+    // A node by name, with an error naming the file's nodes when it's missing.
+    pub fn get(&self, name: &str) -> Result<Arc<dyn Texture + Sync>, Error> {
+        self.nodes.get(name).cloned().ok_or_else(|| {
+            let mut names: Vec<&String> = self.nodes.keys().collect();
+            names.sort();
+            Error::new(ErrorKind::NotFound, format!("Texture graph has no node `{}`, it has {:?}", name, names))
+        })
+    }
+
+    fn compile(&self, expr: &Expr, line: usize) -> Result<Arc<dyn Texture + Sync>, Error> {
+        match expr {
+            Expr::Number(n) => Ok(solid_value(*n)),
+            Expr::Text(_) => Err(invalid(line, "a path can only be an image argument")),
+            Expr::Name(name) => self.nodes.get(name).cloned().ok_or_else(|| invalid(line, &format!("no node named `{}`", name))),
+            Expr::Call(node, arguments) => {
+                let mut args: Args = Args::new(node, line, arguments);
+                let texture: Arc<dyn Texture + Sync> = self.build(&mut args)?;
+                args.finish()?;
+                Ok(texture)
+            }
+        }
+    }
+
+    fn input(&self, args: &mut Args, name: &str) -> Result<Arc<dyn Texture + Sync>, Error> {
+        let expr: Expr = args.take(name).ok_or_else(|| args.error(&format!("missing `{}`", name)))?;
+        self.compile(&expr, args.line)
+    }
+
+    fn number(&self, args: &mut Args, name: &str, default: f64) -> Result<f64, Error> {
+        match args.take(name) {
+            None => Ok(default),
+            Some(Expr::Number(n)) => Ok(n),
+            Some(_) => Err(args.error(&format!("`{}` must be a number", name)))
+        }
+    }
+
+    // A constant colour or point, as a number or `rgb(r, g, b)`.
+    fn vector(&self, args: &mut Args, name: &str, default: Color) -> Result<Color, Error> {
+        let expr: Expr = match args.take(name) {
+            None => return Ok(default),
+            Some(expr) => expr
+        };
+        match expr {
+            Expr::Number(n) => Ok(Color::new(n, n, n)),
+            Expr::Call(ref call, ref values) if call == "rgb" => {
+                // Channels are taken like the rgb node takes them, but must be numbers.
+                let mut rgb: Args = Args::new(call, args.line, values);
+                let mut c: [f64; 3] = [0.0; 3];
+                for (i, channel) in ["r", "g", "b"].iter().enumerate() {
+                    match rgb.take(channel) {
+                        None => {}
+                        Some(Expr::Number(n)) => c[i] = n,
+                        Some(_) => return Err(args.error(&format!("`{}` must be constant", name)))
+                    }
+                }
+                rgb.finish()?;
+                Ok(Color::new(c[0], c[1], c[2]))
+            }
+            _ => Err(args.error(&format!("`{}` must be a number or rgb(r, g, b)", name)))
+        }
+    }
+
+    fn keyword(&self, args: &mut Args, name: &str, default: &str) -> Result<String, Error> {
+        match args.take(name) {
+            None => Ok(default.to_string()),
+            Some(Expr::Name(keyword)) | Some(Expr::Text(keyword)) => Ok(keyword),
+            Some(_) => Err(args.error(&format!("`{}` must be a keyword", name)))
+        }
+    }
+
+    fn space(&self, args: &mut Args) -> Result<TextureSpace, Error> {
+        match self.keyword(args, "space", "uv")?.as_str() {
+            "uv" => Ok(TextureSpace::Uv),
+            "object" => Ok(TextureSpace::Object),
+            "world" => Ok(TextureSpace::World),
+            other => Err(args.error(&format!("unknown space `{}`, expected uv, object or world", other)))
+        }
+    }
+
+    fn build(&self, args: &mut Args) -> Result<Arc<dyn Texture + Sync>, Error> {
+        let math = |op: MathOp, args: &mut Args| -> Result<Arc<dyn Texture + Sync>, Error> {
+            let a: Arc<dyn Texture + Sync> = self.input(args, "a")?;
+            let b: Arc<dyn Texture + Sync> = self.input(args, "b")?;
+            Ok(Arc::new(MathTexture::new(op, &a, &b)))
+        };
+
+        let texture: Arc<dyn Texture + Sync> = match args.node.as_str() {
+            "rgb" => {
+                let r: f64 = self.number(args, "r", 0.0)?;
+                let g: f64 = self.number(args, "g", 0.0)?;
+                let b: f64 = self.number(args, "b", 0.0)?;
+                solid_color(Color::new(r, g, b))
+            }
+            "uv" => Arc::new(CoordinateTexture { space: TextureSpace::Uv }),
+            "position" => {
+                let space: TextureSpace = match self.keyword(args, "space", "world")?.as_str() {
+                    "object" => TextureSpace::Object,
+                    "world" => TextureSpace::World,
+                    other => return Err(args.error(&format!("unknown space `{}`, expected object or world", other)))
+                };
+                Arc::new(CoordinateTexture { space: space })
+            }
+            "add" => math(MathOp::Add, args)?,
+            "subtract" => math(MathOp::Subtract, args)?,
+            "multiply" => math(MathOp::Multiply, args)?,
+            "power" => math(MathOp::Power, args)?,
+            "min" => math(MathOp::Minimum, args)?,
+            "max" => math(MathOp::Maximum, args)?,
+            "mix" => {
+                let a: Arc<dyn Texture + Sync> = self.input(args, "a")?;
+                let b: Arc<dyn Texture + Sync> = self.input(args, "b")?;
+                let factor: Arc<dyn Texture + Sync> = self.input(args, "factor")?;
+                Arc::new(MixTexture::new(&a, &b, &factor))
+            }
+            "clamp" => {
+                let input: Arc<dyn Texture + Sync> = self.input(args, "input")?;
+                let min: f64 = self.number(args, "min", 0.0)?;
+                let max: f64 = self.number(args, "max", 1.0)?;
+                Arc::new(ClampTexture { input: input, min: min, max: max })
+            }
+            "ramp" => {
+                // Input then position and colour pairs.
+                let input: Arc<dyn Texture + Sync> = self.input(args, "input")?;
+                let mut stops: Vec<(f64, Color)> = Vec::new();
+                while !args.positional.is_empty() {
+                    let position: f64 = self.number(args, "position", 0.0)?;
+                    if args.positional.is_empty() {
+                        return Err(args.error("stop has no colour"));
+                    }
+                    stops.push((position, self.vector(args, "color", Color::black())?));
+                }
+                if stops.is_empty() {
+                    return Err(args.error("needs at least one stop"));
+                }
+                Arc::new(ColorRamp::new(&input, &stops))
+            }
+            "checker" => {
+                let a: Color = self.vector(args, "a", Color::black())?;
+                let b: Color = self.vector(args, "b", Color::white())?;
+                let scale: f64 = self.number(args, "scale", 1.0)?;
+                Arc::new(CheckerTexture::new(a, b, scale))
+            }
+            "noise" => {
+                let mut noise: NoiseTexture = NoiseTexture::new(self.space(args)?, 1.0, 4);
+                noise.scale = self.number(args, "scale", noise.scale)?;
+                noise.octaves = self.number(args, "octaves", noise.octaves as f64)?.max(1.0) as u32;
+                noise.lacunarity = self.number(args, "lacunarity", noise.lacunarity)?;
+                noise.gain = self.number(args, "gain", noise.gain)?;
+                Arc::new(noise)
+            }
+            "voronoi" => {
+                let mut voronoi: VoronoiTexture = VoronoiTexture::new(self.space(args)?, 1.0, VoronoiOutput::Distance);
+                voronoi.scale = self.number(args, "scale", voronoi.scale)?;
+                voronoi.output = match self.keyword(args, "output", "distance")?.as_str() {
+                    "distance" => VoronoiOutput::Distance,
+                    "edge" => VoronoiOutput::Edge,
+                    "cells" => VoronoiOutput::Cell,
+                    other => return Err(args.error(&format!("unknown output `{}`, expected distance, edge or cells", other)))
+                };
+                voronoi.jitter = self.number(args, "jitter", voronoi.jitter)?;
+                Arc::new(voronoi)
+            }
+            "wood" => {
+                let mut wood: WoodTexture = WoodTexture::new(self.space(args)?, Color::new(0.75, 0.52, 0.3), Color::new(0.35, 0.18, 0.08), 6.0);
+                wood.light = self.vector(args, "light", wood.light)?;
+                wood.dark = self.vector(args, "dark", wood.dark)?;
+                wood.rings = self.number(args, "rings", wood.rings)?;
+                wood.distortion = self.number(args, "distortion", wood.distortion)?;
+                wood.scale = self.number(args, "scale", wood.scale)?;
+                Arc::new(wood)
+            }
+            "gradient" => {
+                let space: TextureSpace = self.space(args)?;
+                let kind: GradientKind = match self.keyword(args, "kind", "linear")?.as_str() {
+                    "linear" => GradientKind::Linear,
+                    "radial" => GradientKind::Radial,
+                    other => return Err(args.error(&format!("unknown kind `{}`, expected linear or radial", other)))
+                };
+                let start: Point3 = self.vector(args, "start", Point3::zero())?;
+                let end: Point3 = self.vector(args, "end", Point3::new(1.0, 0.0, 0.0))?;
+                let a: Color = self.vector(args, "a", Color::black())?;
+                let b: Color = self.vector(args, "b", Color::white())?;
+                Arc::new(GradientTexture::new(space, kind, start, end, a, b))
+            }
+            "bricks" => {
+                let space: TextureSpace = self.space(args)?;
+                let width: f64 = self.number(args, "width", 0.5)?;
+                let height: f64 = self.number(args, "height", 0.25)?;
+                let mut bricks: BrickTexture = BrickTexture::new(space, width, height, Color::new(0.55, 0.2, 0.12), Color::new(0.7, 0.68, 0.62));
+                bricks.brick_color = self.vector(args, "brick", bricks.brick_color)?;
+                bricks.mortar_color = self.vector(args, "mortar", bricks.mortar_color)?;
+                bricks.mortar_width = self.number(args, "mortar_width", bricks.mortar_width)?;
+                bricks.row_offset = self.number(args, "offset", bricks.row_offset)?;
+                bricks.variation = self.number(args, "variation", bricks.variation)?;
+                Arc::new(bricks)
+            }
+            "image" => {
+                let path: PathBuf = match args.take("path") {
+                    Some(Expr::Text(path)) => self.base_dir.join(path),
+                    _ => return Err(args.error("needs a path in quotes"))
+                };
+                // The file's usual colour space unless overridden, linear for data like roughness.
+                let color_space: Option<ColorSpace> = match args.take("color_space") {
+                    None => None,
+                    Some(Expr::Name(name)) => Some(ColorSpace::from_str(&name, true).map_err(|e: String| -> Error { args.error(&e) })?),
+                    Some(_) => return Err(args.error("`color_space` must be a keyword"))
+                };
+                Arc::new(ImageTexture::load(&path, color_space).map_err(|e| -> Error { args.error(&format!("{}: {}", path.display(), e)) })?)
+            }
+            "project" => {
+                let input: Arc<dyn Texture + Sync> = self.input(args, "input")?;
+                let projection: Projection = match self.keyword(args, "mapping", "planar")?.as_str() {
+                    "planar" => Projection::Planar,
+                    "cylindrical" => Projection::Cylindrical,
+                    "spherical" => Projection::Spherical,
+                    "box" => Projection::Box { sharpness: f64::INFINITY },
+                    "triplanar" => Projection::Box { sharpness: self.number(args, "sharpness", 4.0)? },
+                    other => return Err(args.error(&format!("unknown mapping `{}`, expected planar, cylindrical, spherical, box or triplanar", other)))
+                };
+                let space: TextureSpace = match self.keyword(args, "space", "object")?.as_str() {
+                    "object" => TextureSpace::Object,
+                    "world" => TextureSpace::World,
+                    other => return Err(args.error(&format!("unknown space `{}`, expected object or world", other)))
+                };
+                let mut projected: ProjectedTexture = ProjectedTexture::new(&input, projection, space);
+                projected.scale = self.number(args, "scale", projected.scale)?;
+                projected.axis = self.vector(args, "axis", projected.axis)?;
+                projected.origin = self.vector(args, "origin", projected.origin)?;
+                Arc::new(projected)
+            }
+            other => return Err(args.error(&format!("unknown node `{}`", other)))
+        };
+        Ok(texture)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+
+    fn parse(source: &str) -> Result<TextureGraph, Error> {
+        TextureGraph::parse(source, &Path::new(env!("CARGO_MANIFEST_DIR")).join("input"))
+    }
+
+    fn node(source: &str, name: &str) -> Arc<dyn Texture + Sync> {
+        parse(source).unwrap().get(name).unwrap()
+    }
+
+    fn error(source: &str) -> String {
+        match parse(source) {
+            Ok(_) => panic!("`{}` parsed", source),
+            Err(e) => e.to_string()
+        }
+    }
+
+    fn assert_color(got: Color, want: Color) {
+        assert!((got - want).length() < 1e-9, "{} != {}", got, want);
+    }
+
+    fn hit() -> HitRecord {
+        let mut rec: HitRecord = HitRecord::default();
+        rec.p = Point3::new(1.0, 2.0, 3.0);
+        rec.object_p = Point3::new(0.5, -0.25, 0.125);
+        rec.object_normal = Vec3::new(0.0, 1.0, 0.0);
+        rec.normal = rec.object_normal;
+        rec.front_face = true;
+        rec.uvw = Vec3::new(0.3, 0.7, 0.0);
+        rec
+    }
+
+    #[test]
+    fn constants_and_math() {
+        let source: &str = "
+            a = rgb(0.2, 0.4, 0.6)
+            sum = add(a, 0.1)
+            difference = subtract(1, a)
+            product = multiply(a, rgb(2, 3, 4))
+            root = power(rgb(-1, 4, 9), 0.5)
+            low = min(a, 0.3)
+            high = max(a, 0.3)
+            mixed = mix(0, a, 0.5)
+            clamped = clamp(rgb(-1, 0.5, 2))
+            number = 0.75
+        ";
+        let graph: TextureGraph = parse(source).unwrap();
+        let value = |name: &str| -> Color { graph.get(name).unwrap().value(0.0, 0.0, 0.0) };
+        assert_color(value("a"), Color::new(0.2, 0.4, 0.6));
+        assert_color(value("sum"), Color::new(0.3, 0.5, 0.7));
+        assert_color(value("difference"), Color::new(0.8, 0.6, 0.4));
+        assert_color(value("product"), Color::new(0.4, 1.2, 2.4));
+        // Negative bases are clamped to zero.
+        assert_color(value("root"), Color::new(0.0, 2.0, 3.0));
+        assert_color(value("low"), Color::new(0.2, 0.3, 0.3));
+        assert_color(value("high"), Color::new(0.3, 0.4, 0.6));
+        assert_color(value("mixed"), Color::new(0.1, 0.2, 0.3));
+        assert_color(value("clamped"), Color::new(0.0, 0.5, 1.0));
+        assert_color(value("number"), Color::new(0.75, 0.75, 0.75));
+        assert_eq!(graph.nodes.len(), 10);
+    }
+
+    #[test]
+    fn named_and_positional_arguments() {
+        // Named arguments are taken first, the positional ones fill the rest in order.
+        assert_color(node("c = rgb(b=0.3, 0.1, 0.2)", "c").value(0.0, 0.0, 0.0), Color::new(0.1, 0.2, 0.3));
+        assert_color(node("c = rgb(g=1)", "c").value(0.0, 0.0, 0.0), Color::new(0.0, 1.0, 0.0));
+        assert_color(node("c = clamp(max=0.6, input=rgb(1, 0.5, -1))", "c").value(0.0, 0.0, 0.0), Color::new(0.6, 0.5, 0.0));
+        assert_color(node("c = clamp(rgb(2, 0.5, -1), -0.5, 1.5)", "c").value(0.0, 0.0, 0.0), Color::new(1.5, 0.5, -0.5));
+        assert_color(node("c = mix(factor=0.25, 0, 1)", "c").value(0.0, 0.0, 0.0), Color::new(0.25, 0.25, 0.25));
+        assert_color(node("c = subtract(b=1, a=3)", "c").value(0.0, 0.0, 0.0), Color::new(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn constant_parameters_take_named_channels() {
+        let rec: HitRecord = hit();
+        // uv (0.3, 0.7) lands on an `a` square of a scale 1 checker.
+        let checker: Arc<dyn Texture + Sync> = node("c = checker(a=rgb(b=1, 0, 0), scale=1)", "c");
+        assert_color(checker.value_at(&rec), node("c = rgb(b=1, 0, 0)", "c").value_at(&rec));
+        assert_color(checker.value_at(&rec), Color::new(0.0, 0.0, 1.0));
+        let ramp: Arc<dyn Texture + Sync> = node("r = ramp(uv(), 0, rgb(g=1))", "r");
+        assert_color(ramp.value(0.5, 0.0, 0.0), Color::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn nodes_refer_to_earlier_nodes() {
+        let source: &str = "
+            # Comments and blank lines are skipped.
+
+            base = rgb(0.5, 0.5, 0.5)  # so is the rest of a line
+            brighter = multiply(base, 2)
+            tinted = add(brighter, rgb(0, 0, 1e-1))
+        ";
+        let graph: TextureGraph = parse(source).unwrap();
+        assert_eq!(graph.nodes.len(), 3);
+        assert_color(graph.get("tinted").unwrap().value(0.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.1));
+        let missing: Error = graph.get("missing").err().unwrap();
+        assert_eq!(missing.kind(), ErrorKind::NotFound);
+        assert!(missing.to_string().contains("[\"base\", \"brighter\", \"tinted\"]"), "{}", missing);
+    }
+
+    #[test]
+    fn ramp_stops() {
+        let ramp: Arc<dyn Texture + Sync> = node("r = ramp(uv(), 0.2, rgb(1, 0, 0), 0.6, 0.5, 1.0, rgb(0, 0, 1))", "r");
+        assert_color(ramp.value(0.0, 0.0, 0.0), Color::new(1.0, 0.0, 0.0));
+        assert_color(ramp.value(0.4, 0.0, 0.0), Color::new(0.75, 0.25, 0.25));
+        assert_color(ramp.value(0.8, 0.0, 0.0), Color::new(0.25, 0.25, 0.75));
+        assert_color(ramp.value(1.5, 0.0, 0.0), Color::new(0.0, 0.0, 1.0));
+
+        // Stops are sorted by position, and named ones go with the positional.
+        let reversed: Arc<dyn Texture + Sync> = node("r = ramp(uv(), 1, 1, 0, 0)", "r");
+        assert_color(reversed.value(0.25, 0.0, 0.0), Color::new(0.25, 0.25, 0.25));
+        let named: Arc<dyn Texture + Sync> = node("r = ramp(input=uv(), 0.5, rgb(0, 1, 0))", "r");
+        assert_color(named.value(0.9, 0.0, 0.0), Color::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn coordinates_and_gradients() {
+        let rec: HitRecord = hit();
+        assert_color(node("p = uv()", "p").value_at(&rec), rec.uvw);
+        assert_color(node("p = position()", "p").value_at(&rec), rec.p);
+        assert_color(node("p = position(object)", "p").value_at(&rec), rec.object_p);
+        assert_color(node("p = position(space=world)", "p").value_at(&rec), rec.p);
+
+        let linear: Arc<dyn Texture + Sync> = node("g = gradient(object, start=0, end=rgb(2, 0, 0), a=0, b=rgb(1, 1, 0))", "g");
+        assert_color(linear.value_at(&rec), Color::new(0.25, 0.25, 0.0));
+        let radial: Arc<dyn Texture + Sync> = node("g = gradient(world, kind=radial, end=rgb(0, 0, 6))", "g");
+        assert_color(radial.value_at(&rec), Color::new(1.0, 1.0, 1.0) * (14.0_f64.sqrt() / 6.0));
+    }
+
+    #[test]
+    fn every_node_builds() {
+        let source: &str = "
+            checker_node = checker(rgb(1, 0, 0), b=0.5, scale=4)
+            noise_node = noise(object, scale=2, octaves=3, lacunarity=2.5, gain=0.4)
+            distance = voronoi(world, scale=3, output=distance, jitter=0.5)
+            edge = voronoi(output=edge)
+            cells = voronoi(object, output=cells)
+            wood_node = wood(object, light=rgb(0.9, 0.7, 0.5), dark=0.2, rings=8, distortion=0.3, scale=2)
+            bricks_node = bricks(uv, 0.4, 0.2, brick=rgb(0.6, 0.2, 0.1), mortar=0.7, mortar_width=0.02, offset=0.5, variation=0.1)
+            earth = image(\"earthmap.tga\")
+            linear_earth = image(path=\"earthmap.tga\", color_space=rec709)
+            planar = project(earth)
+            cylindrical = project(earth, mapping=cylindrical, space=world, scale=2, axis=rgb(0, 0, 1), origin=rgb(1, 0, 0))
+            spherical = project(checker_node, spherical)
+            box = project(checker_node, box)
+            triplanar = project(checker_node, triplanar, sharpness=8)
+        ";
+        let graph: TextureGraph = parse(source).unwrap();
+        assert_eq!(graph.nodes.len(), 14);
+        let rec: HitRecord = hit();
+        for (name, texture) in graph.nodes.iter() {
+            let c: Color = texture.value_at(&rec);
+            assert!(c.r().is_finite() && c.g().is_finite() && c.b().is_finite(), "{}: {}", name, c);
+        }
+    }
+
+    #[test]
+    fn errors_name_the_line_and_the_problem() {
+        let cases: [(&str, &str); 21] = [
+            ("a = 1\nb = sparkle(a)", "line 2: sparkle: unknown node `sparkle`"),
+            ("e = image(\"earthmap.tga)", "line 1: unterminated string"),
+            ("a = rgb(1, 2, 3) 4", "line 1: unexpected text after the node"),
+            ("a = 1 b = 2", "line 1: unexpected text after the node"),
+            ("r = ramp(uv(), 0.2, rgb(1, 0, 0), 0.8)", "line 1: ramp: stop has no colour"),
+            ("r = ramp(uv())", "line 1: ramp: needs at least one stop"),
+            ("r = ramp(uv(), 0.5, uv())", "line 1: ramp: `color` must be a number or rgb(r, g, b)"),
+            ("r = ramp(uv(), 0.5, rgb(uv(), 0, 0))", "line 1: ramp: `color` must be constant"),
+            ("r = ramp(uv(), 0.5, rgb(1, 0, 0, 1))", "line 1: rgb: too many arguments"),
+            ("c = clamp(uv(), mix=1)", "line 1: clamp: unknown argument `mix`"),
+            ("c = rgb(1, 2, 3, 4)", "line 1: rgb: too many arguments"),
+            ("c = add(1)", "line 1: add: missing `b`"),
+            ("c = add(x, 1)", "line 1: no node named `x`"),
+            ("c = clamp(uv(), min=uv())", "line 1: clamp: `min` must be a number"),
+            ("n = noise(space=nowhere)", "line 1: noise: unknown space `nowhere`, expected uv, object or world"),
+            ("p = project(uv(), mapping=cubic)", "line 1: project: unknown mapping `cubic`"),
+            ("e = image(earthmap)", "line 1: image: needs a path in quotes"),
+            ("c = 1.2.3", "line 1: bad number `1.2.3`"),
+            ("c = add(1 2)", "line 1: expected `,` or `)` in `add`"),
+            ("c = $", "line 1: unexpected `$`"),
+            ("= 1", "line 1: expected a node name")
+        ];
+        for (source, message) in cases {
+            let e: String = error(source);
+            assert!(e.starts_with("Texture graph ") && e.contains(message), "`{}` gave `{}`", source, e);
+        }
+        assert!(error("c = add(1,").contains("expected a value"));
+        assert!(error("c rgb(1, 1, 1)").contains("expected Equals"));
+        assert!(error("c = \"earthmap.tga\"").contains("a path can only be an image argument"));
+    }
+}
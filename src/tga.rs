@@ -1,16 +1,88 @@
-use std::fs::File;
-use std::io::{Write, Read};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
 use std::mem;
 use std::path::PathBuf;
 use std::slice;
 
+// Truevision TGA reader and writer.
+// http://www.paulbourke.net/dataformats/tga/
+
+const HEADER_SIZE: usize = 18;
+const FOOTER_SIZE: usize = 26;
+const FOOTER_SIGNATURE: &[u8; 18] = b"TRUEVISION-XFILE.\0";
+const EXTENSION_SIZE: usize = 495;
+// Offset of the attributes type inside the extension area, which says what the alpha channel holds.
+const EXTENSION_ATTRIBUTES: usize = 494;
+const SOFTWARE_ID: &str = "raytracing_weekend";
+
+// Image types.
+const COLOR_MAPPED: u8 = 1;
+const TRUE_COLOR: u8 = 2;
+const GREYSCALE: u8 = 3;
+const RLE_COLOR_MAPPED: u8 = 9;
+const RLE_TRUE_COLOR: u8 = 10;
+const RLE_GREYSCALE: u8 = 11;
+
+// Descriptor bits.
+const ALPHA_BITS_MASK: u8 = 0x0F;
+const RIGHT_TO_LEFT: u8 = 0x10;
+const TOP_TO_BOTTOM: u8 = 0x20;
+
+#[derive(Debug)]
+pub enum TgaError {
+    Io(std::io::Error),
+    Truncated,
+    UnsupportedImageType(u8),
+    UnsupportedPixelDepth(u8),
+    InvalidColorMap,
+    ColorIndexOutOfRange(usize),
+    EmptyImage
+}
+
+impl fmt::Display for TgaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TgaError::Io(e) => write!(f, "{}", e),
+            TgaError::Truncated => write!(f, "TGA file is truncated."),
+            TgaError::UnsupportedImageType(t) => write!(f, "Unsupported TGA image type {}.", t),
+            TgaError::UnsupportedPixelDepth(d) => write!(f, "Unsupported TGA pixel depth of {} bits.", d),
+            TgaError::InvalidColorMap => write!(f, "TGA colour map is missing or has an unsupported depth."),
+            TgaError::ColorIndexOutOfRange(i) => write!(f, "TGA colour index {} is outside the colour map.", i),
+            TgaError::EmptyImage => write!(f, "TGA image has no pixels.")
+        }
+    }
+}
+
+impl std::error::Error for TgaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TgaError::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for TgaError {
+    fn from(e: std::io::Error) -> TgaError {
+        TgaError::Io(e)
+    }
+}
 
 // https://gist.github.com/jonvaldes/607fbc380f816d205afb#file-test-rs-L16
 unsafe fn struct_to_u8_slice<T>(s: &T) -> &[u8] {
-    let data_ptr: *const u8 = mem::transmute(s);
+    let data_ptr: *const u8 = s as *const T as *const u8;
     slice::from_raw_parts(data_ptr, mem::size_of::<T>())
 }
 
+fn u16_le(bytes: &[u8], pos: usize) -> u16 {
+    u16::from_le_bytes([bytes[pos], bytes[pos + 1]])
+}
+
+fn u32_le(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+}
+
 #[repr(C, packed)]
 #[derive(Default)]
 struct TgaColorMapSpec {
@@ -33,14 +105,46 @@ struct TgaImageSpec {
 #[repr(C, packed)]
 #[derive(Default)]
 struct TgaHeader {
-    id_legnth: u8,
+    id_length: u8,
     color_map_type: u8,
     image_type: u8,
     color_map_spec: TgaColorMapSpec,
     image_spec: TgaImageSpec,
 }
 
-fn get_tga_header(width: i32, height: i32) -> TgaHeader {
+impl TgaHeader {
+    fn from_bytes(bytes: &[u8]) -> TgaHeader {
+        TgaHeader {
+            id_length: bytes[0],
+            color_map_type: bytes[1],
+            image_type: bytes[2],
+            color_map_spec: TgaColorMapSpec {
+                map_start: u16_le(bytes, 3),
+                map_length: u16_le(bytes, 5),
+                map_depth: bytes[7]
+            },
+            image_spec: TgaImageSpec {
+                x_origin: u16_le(bytes, 8),
+                y_origin: u16_le(bytes, 10),
+                image_width: u16_le(bytes, 12),
+                image_height: u16_le(bytes, 14),
+                pixel_depth: bytes[16],
+                descriptor: bytes[17]
+            }
+        }
+    }
+}
+
+// Options for `write_tga_file`.
+#[derive(Copy, Clone, Default)]
+pub struct TgaOptions {
+    // Run length encode each scanline.
+    pub rle: bool,
+    // The image data is BGRA instead of BGR.
+    pub alpha: bool
+}
+
+fn get_tga_header(width: i32, height: i32, options: &TgaOptions) -> TgaHeader {
 
     let color_spec: TgaColorMapSpec = TgaColorMapSpec{
         ..TgaColorMapSpec::default()
@@ -49,12 +153,13 @@ fn get_tga_header(width: i32, height: i32) -> TgaHeader {
     let img_spec: TgaImageSpec = TgaImageSpec {
         image_width: width as u16,
         image_height: height as u16,
-        pixel_depth: 24,
+        pixel_depth: if options.alpha { 32 } else { 24 },
+        descriptor: if options.alpha { 8 } else { 0 },
         ..TgaImageSpec::default()
     };
 
     let header: TgaHeader = TgaHeader{
-        image_type: 2,
+        image_type: if options.rle { RLE_TRUE_COLOR } else { TRUE_COLOR },
         color_map_spec: color_spec,
         image_spec: img_spec,
         ..TgaHeader::default()
@@ -63,42 +168,404 @@ fn get_tga_header(width: i32, height: i32) -> TgaHeader {
     header
 }
 
-pub fn write_tga_file(width: i32, height: i32, image_data: &Vec<u8>, file_path: &PathBuf) -> Result<(), std::io::Error> {
+// Packets never cross a scanline, as the specification recommends.
+fn rle_encode_scanline(scanline: &[u8], bytes_per_pixel: usize, out: &mut Vec<u8>) {
+    let pixels: Vec<&[u8]> = scanline.chunks_exact(bytes_per_pixel).collect();
+    let run_length = |start: usize| -> usize {
+        let mut n: usize = 1;
+        while start + n < pixels.len() && n < 128 && pixels[start + n] == pixels[start] {
+            n += 1;
+        }
+        n
+    };
+
+    let mut i: usize = 0;
+    while i < pixels.len() {
+        let run: usize = run_length(i);
+        if run > 1 {
+            out.push(0x80 | (run - 1) as u8);
+            out.extend_from_slice(pixels[i]);
+            i += run;
+            continue;
+        }
+
+        // Raw packet up to the start of the next run.
+        let start: usize = i;
+        while i < pixels.len() && i - start < 128 && (i == start || run_length(i) == 1) {
+            i += 1;
+        }
+        out.push((i - start - 1) as u8);
+        for pixel in &pixels[start..i] {
+            out.extend_from_slice(pixel);
+        }
+    }
+}
+
+// TGA 2.0 extension area, only the software id and the alpha attributes type are filled in.
+fn extension_area(options: &TgaOptions) -> Vec<u8> {
+    let mut ext: Vec<u8> = vec![0; EXTENSION_SIZE];
+    ext[0..2].copy_from_slice(&(EXTENSION_SIZE as u16).to_le_bytes());
+    let software_id: usize = 2 + 41 + 324 + 12 + 41 + 6;
+    ext[software_id..software_id + SOFTWARE_ID.len()].copy_from_slice(SOFTWARE_ID.as_bytes());
+    ext[EXTENSION_ATTRIBUTES] = if options.alpha { 3 } else { 0 };
+    ext
+}
+
+// Writes BGR, or BGRA with `options.alpha`, pixels with the bottom scanline first.
+pub fn write_tga_file(width: i32, height: i32, image_data: &[u8], options: &TgaOptions, file_path: &PathBuf) -> Result<(), std::io::Error> {
 
     let mut file: File = File::create(file_path)?;
 
-    let header: TgaHeader = get_tga_header(width, height);
+    let header: TgaHeader = get_tga_header(width, height, options);
     let header_bytes: &[u8] = unsafe{ struct_to_u8_slice(&header) };
 
+    let bytes_per_pixel: usize = if options.alpha { 4 } else { 3 };
+    let mut body: Vec<u8> = Vec::with_capacity(image_data.len());
+    if options.rle {
+        for scanline in image_data.chunks_exact(width as usize * bytes_per_pixel).take(height as usize) {
+            rle_encode_scanline(scanline, bytes_per_pixel, &mut body);
+        }
+    } else {
+        body.extend_from_slice(&image_data[0..(width * height) as usize * bytes_per_pixel]);
+    }
+
+    let extension_offset: u32 = (header_bytes.len() + body.len()) as u32;
+
     file.write_all(header_bytes)?;
-    file.write_all(image_data)?;
+    file.write_all(&body)?;
+    file.write_all(&extension_area(options))?;
+    file.write_all(&extension_offset.to_le_bytes())?;
+    file.write_all(&0u32.to_le_bytes())?;
+    file.write_all(FOOTER_SIGNATURE)?;
 
     Ok(())
 }
 
-pub fn read_tga_file(file_path: &PathBuf, out_image_data: &mut Vec<u8>, out_width: &mut usize, out_height: &mut usize, out_bpp: &mut usize) -> Result<(), std::io::Error> {
+// Converts one stored pixel of `depth` bits to BGRA.
+fn decode_pixel(bytes: &[u8], depth: u8, greyscale: bool) -> [u8; 4] {
+    match (depth, greyscale) {
+        (8, true) => [bytes[0], bytes[0], bytes[0], 255],
+        (16, true) => [bytes[0], bytes[0], bytes[0], bytes[1]],
+        (15, false) | (16, false) => {
+            // ARRRRRGG GGGBBBBB
+            let v: u16 = u16_le(bytes, 0);
+            let expand = |c: u16| -> u8 { ((c & 0x1F) * 255 / 31) as u8 };
+            let alpha: u8 = if depth == 15 || v & 0x8000 != 0 { 255 } else { 0 };
+            [expand(v), expand(v >> 5), expand(v >> 10), alpha]
+        }
+        (24, false) => [bytes[0], bytes[1], bytes[2], 255],
+        _ => [bytes[0], bytes[1], bytes[2], bytes[3]]
+    }
+}
+
+fn has_footer(file: &[u8]) -> bool {
+    file.len() >= HEADER_SIZE + FOOTER_SIZE && &file[file.len() - FOOTER_SIGNATURE.len()..] == FOOTER_SIGNATURE
+}
+
+// Alpha attributes type from the TGA 2.0 extension area: 0 no alpha, 1 and 2 undefined and to be ignored,
+// 3 straight alpha and 4 premultiplied alpha.
+fn alpha_attributes(file: &[u8]) -> Option<u8> {
+    if !has_footer(file) {
+        return None;
+    }
+    let offset: usize = u32_le(file, file.len() - FOOTER_SIZE) as usize;
+    if offset == 0 || offset + EXTENSION_SIZE > file.len() - FOOTER_SIZE {
+        return None;
+    }
+    Some(file[offset + EXTENSION_ATTRIBUTES])
+}
 
-    let mut file: File = File::open(file_path)?;
+// Expands RLE packets, which may run across scanlines, into `count` pixels of `bytes_per_pixel` bytes.
+fn rle_decode(data: &[u8], count: usize, bytes_per_pixel: usize) -> Result<Vec<u8>, TgaError> {
+    let mut out: Vec<u8> = Vec::with_capacity(count * bytes_per_pixel);
+    let mut pos: usize = 0;
+    while out.len() < count * bytes_per_pixel {
+        let packet: u8 = *data.get(pos).ok_or(TgaError::Truncated)?;
+        pos += 1;
+        let n: usize = (packet & 0x7F) as usize + 1;
+        if packet & 0x80 != 0 {
+            let pixel: &[u8] = data.get(pos..pos + bytes_per_pixel).ok_or(TgaError::Truncated)?;
+            for _ in 0..n {
+                out.extend_from_slice(pixel);
+            }
+            pos += bytes_per_pixel;
+        } else {
+            let pixels: &[u8] = data.get(pos..pos + n * bytes_per_pixel).ok_or(TgaError::Truncated)?;
+            out.extend_from_slice(pixels);
+            pos += n * bytes_per_pixel;
+        }
+    }
+    out.truncate(count * bytes_per_pixel);
+    Ok(out)
+}
 
-    let mut header: TgaHeader = TgaHeader::default();
-    let header_slice: &mut [u8] = unsafe {slice::from_raw_parts_mut(&mut header as *mut _ as *mut u8, mem::size_of::<TgaHeader>() ) };
-    file.read_exact(header_slice)?;
-    
+// Pixels are returned as BGR, or BGRA when the image has alpha, with the bottom scanline first.
+pub fn read_tga_file(file_path: &PathBuf, out_image_data: &mut Vec<u8>, out_width: &mut usize, out_height: &mut usize, out_bpp: &mut usize) -> Result<(), TgaError> {
+
+    let file: Vec<u8> = fs::read(file_path)?;
+    if file.len() < HEADER_SIZE {
+        return Err(TgaError::Truncated);
+    }
+    let header: TgaHeader = TgaHeader::from_bytes(&file);
+
+    let image_type: u8 = header.image_type;
+    let depth: u8 = header.image_spec.pixel_depth;
+    let descriptor: u8 = header.image_spec.descriptor;
     let width: usize = header.image_spec.image_width as usize;
     let height: usize = header.image_spec.image_height as usize;
+    if width == 0 || height == 0 {
+        return Err(TgaError::EmptyImage);
+    }
 
-    let bytes_per_pixel: usize = (header.image_spec.pixel_depth / 8) as usize;
+    let color_mapped: bool = image_type == COLOR_MAPPED || image_type == RLE_COLOR_MAPPED;
+    let greyscale: bool = image_type == GREYSCALE || image_type == RLE_GREYSCALE;
+    let rle: bool = image_type >= RLE_COLOR_MAPPED;
+    let supported_depth: bool = match image_type {
+        COLOR_MAPPED | RLE_COLOR_MAPPED => depth == 8 || depth == 16,
+        TRUE_COLOR | RLE_TRUE_COLOR => matches!(depth, 15 | 16 | 24 | 32),
+        GREYSCALE | RLE_GREYSCALE => depth == 8 || depth == 16,
+        _ => return Err(TgaError::UnsupportedImageType(image_type))
+    };
+    if !supported_depth {
+        return Err(TgaError::UnsupportedPixelDepth(depth));
+    }
 
-    let length: usize = width * height * bytes_per_pixel;
+    // The colour map follows the image id and is present in any image type, but only used by colour mapped ones.
+    let mut pos: usize = HEADER_SIZE + header.id_length as usize;
+    let map_start: usize = header.color_map_spec.map_start as usize;
+    let map_length: usize = header.color_map_spec.map_length as usize;
+    let map_depth: u8 = header.color_map_spec.map_depth;
+    let mut color_map: Vec<[u8; 4]> = Vec::new();
+    if header.color_map_type == 1 {
+        if !matches!(map_depth, 15 | 16 | 24 | 32) {
+            return Err(TgaError::InvalidColorMap);
+        }
+        let entry_size: usize = (map_depth as usize).div_ceil(8);
+        let entries: &[u8] = file.get(pos..pos + map_length * entry_size).ok_or(TgaError::Truncated)?;
+        color_map = entries.chunks_exact(entry_size).map(|e: &[u8]| -> [u8; 4] { decode_pixel(e, map_depth, false) }).collect();
+        pos += map_length * entry_size;
+    } else if color_mapped {
+        return Err(TgaError::InvalidColorMap);
+    }
 
-    out_image_data.resize(length, 0);
+    let bytes_per_pixel: usize = (depth as usize).div_ceil(8);
+    let count: usize = width * height;
+    let pixels: Vec<u8> = if rle {
+        rle_decode(file.get(pos..).ok_or(TgaError::Truncated)?, count, bytes_per_pixel)?
+    } else {
+        file.get(pos..pos + count * bytes_per_pixel).ok_or(TgaError::Truncated)?.to_vec()
+    };
 
-    file.read_exact(out_image_data)?;
+    // Whether the stored pixels carry alpha, and what the extension area says it means.
+    let attributes: Option<u8> = alpha_attributes(&file);
+    let stored_alpha: bool = match image_type {
+        COLOR_MAPPED | RLE_COLOR_MAPPED => map_depth == 32 || (map_depth == 16 && descriptor & ALPHA_BITS_MASK > 0),
+        GREYSCALE | RLE_GREYSCALE => depth == 16,
+        _ => depth == 32 || (depth == 16 && descriptor & ALPHA_BITS_MASK > 0)
+    };
+    let has_alpha: bool = stored_alpha && !matches!(attributes, Some(0..=2));
+    let premultiplied: bool = has_alpha && attributes == Some(4);
+
+    let out_bytes: usize = if has_alpha { 4 } else { 3 };
+    out_image_data.clear();
+    out_image_data.resize(count * out_bytes, 0);
+    for (i, stored) in pixels.chunks_exact(bytes_per_pixel).enumerate() {
+        let mut bgra: [u8; 4] = if color_mapped {
+            let index: usize = if depth == 8 { stored[0] as usize } else { u16_le(stored, 0) as usize };
+            *index.checked_sub(map_start).and_then(|i: usize| -> Option<&[u8; 4]> { color_map.get(i) })
+                .ok_or(TgaError::ColorIndexOutOfRange(index))?
+        } else {
+            decode_pixel(stored, depth, greyscale)
+        };
+        if !has_alpha {
+            bgra[3] = 255;
+        } else if premultiplied && bgra[3] > 0 {
+            let alpha: u32 = bgra[3] as u32;
+            for c in bgra.iter_mut().take(3) {
+                *c = (*c as u32 * 255 / alpha).min(255) as u8;
+            }
+        }
+
+        // Store bottom to top and left to right whatever the origin.
+        let (x, y) = (i % width, i / width);
+        let x: usize = if descriptor & RIGHT_TO_LEFT != 0 { width - 1 - x } else { x };
+        let y: usize = if descriptor & TOP_TO_BOTTOM != 0 { height - 1 - y } else { y };
+        let out: usize = (x + y * width) * out_bytes;
+        out_image_data[out..out + out_bytes].copy_from_slice(&bgra[0..out_bytes]);
+    }
 
     *out_width = width;
     *out_height = height;
-    *out_bpp = bytes_per_pixel;
-
+    *out_bpp = out_bytes;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Decoded {
+        data: Vec<u8>,
+        width: usize,
+        height: usize,
+        bpp: usize
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("raytracing_weekend_tga_{}_{}.tga", name, std::process::id()))
+    }
+
+    fn read_bytes(name: &str, bytes: &[u8]) -> Result<Decoded, TgaError> {
+        let path: PathBuf = temp_path(name);
+        fs::write(&path, bytes).unwrap();
+        let mut decoded: Decoded = Decoded { data: Vec::new(), width: 0, height: 0, bpp: 0 };
+        let result: Result<(), TgaError> = read_tga_file(&path, &mut decoded.data, &mut decoded.width, &mut decoded.height, &mut decoded.bpp);
+        fs::remove_file(&path).unwrap();
+        result.map(|_| decoded)
+    }
+
+    // 18 byte header for a `width` by `height` image.
+    fn header(image_type: u8, color_map: Option<(u16, u16, u8)>, width: u16, height: u16, depth: u8, descriptor: u8) -> Vec<u8> {
+        let (map_start, map_length, map_depth) = color_map.unwrap_or((0, 0, 0));
+        let mut bytes: Vec<u8> = vec![0, color_map.is_some() as u8, image_type];
+        bytes.extend_from_slice(&map_start.to_le_bytes());
+        bytes.extend_from_slice(&map_length.to_le_bytes());
+        bytes.push(map_depth);
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.push(depth);
+        bytes.push(descriptor);
+        bytes
+    }
+
+    fn round_trip(name: &str, width: i32, height: i32, pixels: &[u8], options: &TgaOptions) -> Decoded {
+        let path: PathBuf = temp_path(name);
+        write_tga_file(width, height, pixels, options, &path).unwrap();
+        let mut decoded: Decoded = Decoded { data: Vec::new(), width: 0, height: 0, bpp: 0 };
+        read_tga_file(&path, &mut decoded.data, &mut decoded.width, &mut decoded.height, &mut decoded.bpp).unwrap();
+        fs::remove_file(&path).unwrap();
+        decoded
+    }
+
+    // Runs, single pixels and a run longer than one packet, across several scanlines.
+    fn test_pixels(width: usize, height: usize, bpp: usize) -> Vec<u8> {
+        let mut pixels: Vec<u8> = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let (value, alpha) = if x < width / 2 { ((y * 40) as u8, 255) } else { ((x * 7 + y * 3) as u8, (x * 5) as u8) };
+                pixels.extend_from_slice(&[value, value.wrapping_add(1), 255 - value, alpha][0..bpp]);
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn round_trip_rle_with_alpha() {
+        let pixels: Vec<u8> = test_pixels(300, 4, 4);
+        let decoded: Decoded = round_trip("rle_alpha", 300, 4, &pixels, &TgaOptions { rle: true, alpha: true });
+        assert_eq!((decoded.width, decoded.height, decoded.bpp), (300, 4, 4));
+        assert_eq!(decoded.data, pixels);
+    }
+
+    #[test]
+    fn round_trip_uncompressed() {
+        let pixels: Vec<u8> = test_pixels(7, 5, 3);
+        let decoded: Decoded = round_trip("plain", 7, 5, &pixels, &TgaOptions { rle: false, alpha: false });
+        assert_eq!((decoded.width, decoded.height, decoded.bpp), (7, 5, 3));
+        assert_eq!(decoded.data, pixels);
+
+        let rle: Decoded = round_trip("rle", 7, 5, &pixels, &TgaOptions { rle: true, alpha: false });
+        assert_eq!(rle.data, pixels);
+    }
+
+    #[test]
+    fn written_footer_marks_straight_alpha() {
+        let path: PathBuf = temp_path("footer");
+        write_tga_file(1, 1, &[1, 2, 3, 4], &TgaOptions { rle: false, alpha: true }, &path).unwrap();
+        let file: Vec<u8> = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(has_footer(&file));
+        assert_eq!(alpha_attributes(&file), Some(3));
+    }
+
+    #[test]
+    fn top_left_origin_is_flipped() {
+        // Top row red then green, bottom row blue then white, stored top row first.
+        let mut bytes: Vec<u8> = header(TRUE_COLOR, None, 2, 2, 24, TOP_TO_BOTTOM);
+        bytes.extend_from_slice(&[0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255]);
+        let decoded: Decoded = read_bytes("top_left", &bytes).unwrap();
+        assert_eq!(decoded.bpp, 3);
+        assert_eq!(decoded.data, vec![255, 0, 0, 255, 255, 255, 0, 0, 255, 0, 255, 0]);
+    }
+
+    #[test]
+    fn right_to_left_is_mirrored() {
+        let mut bytes: Vec<u8> = header(TRUE_COLOR, None, 2, 1, 24, RIGHT_TO_LEFT);
+        bytes.extend_from_slice(&[1, 1, 1, 2, 2, 2]);
+        let decoded: Decoded = read_bytes("right_to_left", &bytes).unwrap();
+        assert_eq!(decoded.data, vec![2, 2, 2, 1, 1, 1]);
+    }
+
+    #[test]
+    fn greyscale() {
+        let mut bytes: Vec<u8> = header(GREYSCALE, None, 3, 1, 8, 0);
+        bytes.extend_from_slice(&[0, 128, 255]);
+        let decoded: Decoded = read_bytes("grey", &bytes).unwrap();
+        assert_eq!(decoded.bpp, 3);
+        assert_eq!(decoded.data, vec![0, 0, 0, 128, 128, 128, 255, 255, 255]);
+
+        // Grey and alpha pairs, run length encoded: a run of two then one raw pixel.
+        let mut bytes: Vec<u8> = header(RLE_GREYSCALE, None, 3, 1, 16, 8);
+        bytes.extend_from_slice(&[0x81, 50, 200, 0x00, 90, 10]);
+        let decoded: Decoded = read_bytes("grey_rle", &bytes).unwrap();
+        assert_eq!(decoded.bpp, 4);
+        assert_eq!(decoded.data, vec![50, 50, 50, 200, 50, 50, 50, 200, 90, 90, 90, 10]);
+    }
+
+    #[test]
+    fn color_mapped() {
+        // Three 24-bit entries starting at index 2.
+        let mut bytes: Vec<u8> = header(COLOR_MAPPED, Some((2, 3, 24)), 4, 1, 8, 0);
+        bytes.extend_from_slice(&[10, 20, 30, 40, 50, 60, 70, 80, 90]);
+        bytes.extend_from_slice(&[2, 4, 3, 2]);
+        let decoded: Decoded = read_bytes("mapped", &bytes).unwrap();
+        assert_eq!(decoded.bpp, 3);
+        assert_eq!(decoded.data, vec![10, 20, 30, 70, 80, 90, 40, 50, 60, 10, 20, 30]);
+
+        let mut bytes: Vec<u8> = header(RLE_COLOR_MAPPED, Some((0, 2, 24)), 3, 1, 8, 0);
+        bytes.extend_from_slice(&[1, 1, 1, 9, 9, 9]);
+        bytes.extend_from_slice(&[0x82, 1]);
+        let decoded: Decoded = read_bytes("mapped_rle", &bytes).unwrap();
+        assert_eq!(decoded.data, vec![9, 9, 9, 9, 9, 9, 9, 9, 9]);
+
+        let mut bytes: Vec<u8> = header(COLOR_MAPPED, Some((2, 3, 24)), 1, 1, 8, 0);
+        bytes.extend_from_slice(&[10, 20, 30, 40, 50, 60, 70, 80, 90]);
+        bytes.push(1);
+        assert!(matches!(read_bytes("mapped_range", &bytes), Err(TgaError::ColorIndexOutOfRange(1))));
+
+        let mut bytes: Vec<u8> = header(COLOR_MAPPED, None, 1, 1, 8, 0);
+        bytes.push(0);
+        assert!(matches!(read_bytes("mapped_missing", &bytes), Err(TgaError::InvalidColorMap)));
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        // The image id runs past the end of the file.
+        let mut bytes: Vec<u8> = header(RLE_TRUE_COLOR, None, 2, 2, 24, 0);
+        bytes[0] = 40;
+        assert!(matches!(read_bytes("truncated_id", &bytes), Err(TgaError::Truncated)));
+
+        // A run packet without its pixel, and raw data short of the image.
+        let mut bytes: Vec<u8> = header(RLE_TRUE_COLOR, None, 2, 2, 24, 0);
+        bytes.extend_from_slice(&[0x83, 1]);
+        assert!(matches!(read_bytes("truncated_rle", &bytes), Err(TgaError::Truncated)));
+
+        let mut bytes: Vec<u8> = header(TRUE_COLOR, None, 2, 2, 24, 0);
+        bytes.extend_from_slice(&[1, 2, 3]);
+        assert!(matches!(read_bytes("truncated_raw", &bytes), Err(TgaError::Truncated)));
+
+        assert!(matches!(read_bytes("truncated_header", &[0, 0, 2]), Err(TgaError::Truncated)));
+    }
+}
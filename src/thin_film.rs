@@ -0,0 +1,185 @@
+use std::f64::consts::PI;
+use std::ops;
+use std::sync::Arc;
+
+use crate::hittable::HitRecord;
+use crate::color_space::xyz_to_working_matrix;
+use crate::spectrum::{cie_xyz, CIE_Y_INTEGRAL};
+use crate::texture::Texture;
+use crate::vec3::*;
+
+// Wavelengths integrated per lookup in RGB mode, enough for films up to a couple of microns.
+const RGB_FILM_SAMPLES: usize = 32;
+const RGB_FILM_LAMBDA_MIN: f64 = 380.0;
+const RGB_FILM_LAMBDA_MAX: f64 = 780.0;
+
+// Just enough complex arithmetic for Fresnel amplitudes over absorbing substrates.
+#[derive(Copy, Clone, Default)]
+struct Complex {
+    re: f64,
+    im: f64
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Complex {
+        Complex { re: re, im: im }
+    }
+
+    fn real(re: f64) -> Complex {
+        Complex::new(re, 0.0)
+    }
+
+    fn norm_sqr(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // Principal square root.
+    fn sqrt(&self) -> Complex {
+        let r: f64 = self.norm_sqr().sqrt();
+        let re: f64 = (0.5 * (r + self.re)).max(0.0).sqrt();
+        let im: f64 = (0.5 * (r - self.re)).max(0.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    // e^(i z)
+    fn exp_i(&self) -> Complex {
+        let magnitude: f64 = (-self.im).exp();
+        Complex::new(magnitude * self.re.cos(), magnitude * self.re.sin())
+    }
+}
+
+impl ops::Add<Complex> for Complex {
+    type Output = Complex;
+    fn add(self, rhs: Complex) -> Complex {
+        Complex::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl ops::Sub<Complex> for Complex {
+    type Output = Complex;
+    fn sub(self, rhs: Complex) -> Complex {
+        Complex::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl ops::Mul<Complex> for Complex {
+    type Output = Complex;
+    fn mul(self, rhs: Complex) -> Complex {
+        Complex::new(self.re * rhs.re - self.im * rhs.im, self.re * rhs.im + self.im * rhs.re)
+    }
+}
+
+impl ops::Div<Complex> for Complex {
+    type Output = Complex;
+    fn div(self, rhs: Complex) -> Complex {
+        let d: f64 = rhs.norm_sqr();
+        Complex::new((self.re * rhs.re + self.im * rhs.im) / d, (self.im * rhs.re - self.re * rhs.im) / d)
+    }
+}
+
+// Amplitude reflection coefficients (s, p) from medium i into medium j.
+fn fresnel_amplitudes(n_i: Complex, cos_i: Complex, n_j: Complex, cos_j: Complex) -> (Complex, Complex) {
+    let s: Complex = (n_i * cos_i - n_j * cos_j) / (n_i * cos_i + n_j * cos_j);
+    let p: Complex = (n_j * cos_i - n_i * cos_j) / (n_j * cos_i + n_i * cos_j);
+    (s, p)
+}
+
+// Cosine of the refracted angle, complex past the critical angle or inside absorbing media.
+fn refracted_cos(n_i: f64, sin_i: f64, n_t: Complex) -> Complex {
+    let ratio: Complex = Complex::real(n_i * sin_i) / n_t;
+    (Complex::real(1.0) - ratio * ratio).sqrt()
+}
+
+// Unpolarized reflectance of a film of index `n_film` and `thickness` nanometres between an incident medium
+// `n_incident` and a substrate `n_substrate`, which may absorb.  Sums the reflections inside the film (Airy).
+pub fn thin_film_reflectance(cos_theta_i: f64, n_incident: f64, n_film: f64, n_substrate: (f64, f64), thickness: f64, lambda: f64) -> f64 {
+    let cos_i: f64 = cos_theta_i.clamp(0.0, 1.0);
+    let sin_i: f64 = (1.0 - cos_i * cos_i).max(0.0).sqrt();
+
+    let n1: Complex = Complex::real(n_incident);
+    let n2: Complex = Complex::real(n_film);
+    let n3: Complex = Complex::new(n_substrate.0, n_substrate.1);
+    let cos1: Complex = Complex::real(cos_i);
+    let cos2: Complex = refracted_cos(n_incident, sin_i, n2);
+    let cos3: Complex = refracted_cos(n_incident, sin_i, n3);
+
+    let (r12_s, r12_p) = fresnel_amplitudes(n1, cos1, n2, cos2);
+    let (r23_s, r23_p) = fresnel_amplitudes(n2, cos2, n3, cos3);
+
+    // Phase difference picked up by one round trip through the film.
+    let phase: Complex = Complex::real(4.0 * PI * n_film * thickness / lambda) * cos2;
+    let shift: Complex = phase.exp_i();
+    let one: Complex = Complex::real(1.0);
+    let r_s: Complex = (r12_s + r23_s * shift) / (one + r12_s * r23_s * shift);
+    let r_p: Complex = (r12_p + r23_p * shift) / (one + r12_p * r23_p * shift);
+
+    (0.5 * (r_s.norm_sqr() + r_p.norm_sqr())).clamp(0.0, 1.0)
+}
+
+// Per channel optical constants spread over the spectrum, blue at 450nm, green at 550nm and red at 650nm.
+pub fn channel_at_wavelength(c: &Color, lambda: f64) -> f64 {
+    if lambda <= 450.0 {
+        c.b()
+    } else if lambda <= 550.0 {
+        c.b() + (c.g() - c.b()) * (lambda - 450.0) / 100.0
+    } else if lambda <= 650.0 {
+        c.g() + (c.r() - c.g()) * (lambda - 550.0) / 100.0
+    } else {
+        c.r()
+    }
+}
+
+// A thin transparent layer on top of a surface, such as soap, oil, oxide or an anti-reflective coating.
+#[derive(Clone)]
+pub struct ThinFilm {
+    // Film thickness in nanometres.
+    pub thickness: f64,
+    // Scales the thickness by the red channel, for swirling soap films.
+    pub thickness_map: Option<Arc<dyn Texture + Sync>>,
+    pub ior: f64
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, ior: f64) -> ThinFilm {
+        ThinFilm {
+            thickness: thickness,
+            thickness_map: None,
+            ior: ior
+        }
+    }
+
+    pub fn new_texture(thickness: f64, thickness_map: &Arc<dyn Texture + Sync>, ior: f64) -> ThinFilm {
+        ThinFilm {
+            thickness: thickness,
+            thickness_map: Some(thickness_map.to_owned()),
+            ior: ior
+        }
+    }
+
+    pub fn thickness_at(&self, rec: &HitRecord) -> f64 {
+        match &self.thickness_map {
+            None => self.thickness,
+            Some(map) => self.thickness * map.value_at(rec).r().max(0.0)
+        }
+    }
+
+    // Reflectance over a substrate whose complex IOR at each wavelength is given by `substrate`.  At a single
+    // wavelength in spectral mode (`lambda` > 0), otherwise integrated against the CIE observer into sRGB.
+    pub fn reflectance<F>(&self, rec: &HitRecord, cos_theta_i: f64, n_incident: f64, lambda: f64, substrate: F) -> Color
+    where F: Fn(f64) -> (f64, f64) {
+        let thickness: f64 = self.thickness_at(rec);
+        if lambda > 0.0 {
+            return Color::white() * thin_film_reflectance(cos_theta_i, n_incident, self.ior, substrate(lambda), thickness, lambda);
+        }
+
+        let step: f64 = (RGB_FILM_LAMBDA_MAX - RGB_FILM_LAMBDA_MIN) / RGB_FILM_SAMPLES as f64;
+        let mut xyz: Vec3 = Vec3::zero();
+        for i in 0..RGB_FILM_SAMPLES {
+            let lambda: f64 = RGB_FILM_LAMBDA_MIN + (i as f64 + 0.5) * step;
+            let r: f64 = thin_film_reflectance(cos_theta_i, n_incident, self.ior, substrate(lambda), thickness, lambda);
+            xyz += cie_xyz(lambda) * (r * step);
+        }
+        let rgb: Color = xyz_to_working_matrix() * (xyz / CIE_Y_INTEGRAL);
+        Color::new(rgb.r().clamp(0.0, 1.0), rgb.g().clamp(0.0, 1.0), rgb.b().clamp(0.0, 1.0))
+    }
+}
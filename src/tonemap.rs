@@ -0,0 +1,227 @@
+use clap::ValueEnum;
+
+use crate::common::saturate;
+use crate::mat3::Mat3;
+use crate::vec3::*;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, ValueEnum)]
+pub enum ToneMapper {
+    /// No tone mapping, values above 1.0 are clipped.
+    #[default]
+    Clip,
+    Reinhard,
+    ReinhardExtended,
+    Aces,
+    Agx,
+    Hable
+}
+
+#[derive(Copy, Clone)]
+pub struct ToneMapping {
+    pub operator: ToneMapper,
+    pub exposure: f64,
+    pub white_point: f64,
+    pub white_balance: Mat3
+}
+
+impl Default for ToneMapping {
+    fn default() -> ToneMapping {
+        ToneMapping {
+            operator: ToneMapper::Clip,
+            exposure: 0.0,
+            white_point: 4.0,
+            white_balance: Mat3::identity()
+        }
+    }
+}
+
+impl ToneMapping {
+    pub fn new(operator: ToneMapper, exposure: f64, white_point: f64) -> ToneMapping {
+        ToneMapping {
+            operator: operator,
+            exposure: exposure,
+            white_point: white_point,
+            ..ToneMapping::default()
+        }
+    }
+
+    // Neutralize a light source of the given colour temperature, in Kelvin.
+    pub fn set_white_balance(&mut self, kelvin: f64) {
+        self.white_balance = white_balance_matrix(kelvin, 6500.0);
+    }
+
+    // Maps a linear HDR colour to linear [0, 1] display values.
+    pub fn apply(&self, color: &Color) -> Color {
+        let exposed: Color = self.white_balance * *color * 2.0_f64.powf(self.exposure);
+        let mapped: Color = match self.operator {
+            ToneMapper::Clip => exposed,
+            ToneMapper::Reinhard => reinhard(&exposed),
+            ToneMapper::ReinhardExtended => reinhard_extended(&exposed, self.white_point),
+            ToneMapper::Aces => aces_fitted(&exposed),
+            ToneMapper::Agx => agx(&exposed),
+            ToneMapper::Hable => hable(&exposed, self.white_point)
+        };
+        Color::new(saturate(mapped.r()), saturate(mapped.g()), saturate(mapped.b()))
+    }
+}
+
+fn scale_luminance(color: &Color, mapped_luminance: f64) -> Color {
+    let l: f64 = color.luminance();
+    if l <= 0.0 {
+        return Color::black();
+    }
+    *color * (mapped_luminance / l)
+}
+
+fn reinhard(color: &Color) -> Color {
+    let l: f64 = color.luminance();
+    scale_luminance(color, l / (1.0 + l))
+}
+
+// Reinhard et al. 2002, luminance at `white` maps to 1.0.
+fn reinhard_extended(color: &Color, white: f64) -> Color {
+    let l: f64 = color.luminance();
+    let numerator: f64 = l * (1.0 + l / (white * white));
+    scale_luminance(color, numerator / (1.0 + l))
+}
+
+// Stephen Hill's fit of the ACES RRT and sRGB ODT.
+fn aces_fitted(color: &Color) -> Color {
+    let input: Mat3 = Mat3::new([
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777]
+    ]);
+    let output: Mat3 = Mat3::new([
+        [ 1.60475, -0.53108, -0.07367],
+        [-0.10208,  1.10813, -0.00605],
+        [-0.00327, -0.07276,  1.07602]
+    ]);
+
+    let rrt_and_odt_fit = |v: f64| -> f64 {
+        let a: f64 = v * (v + 0.0245786) - 0.000090537;
+        let b: f64 = v * (0.983729 * v + 0.4329510) + 0.238081;
+        a / b
+    };
+
+    let v: Color = input * *color;
+    output * Color::new(rrt_and_odt_fit(v.r()), rrt_and_odt_fit(v.g()), rrt_and_odt_fit(v.b()))
+}
+
+// Troy Sobotka's AgX, using Benjamin Wrensch's polynomial approximation of the base contrast curve.
+fn agx(color: &Color) -> Color {
+    let inset: Mat3 = Mat3::new([
+        [0.842479062253094,  0.0784335999999992, 0.0792237451477643],
+        [0.0423282422610123, 0.878468636469772,  0.0791661274605434],
+        [0.0423756549057051, 0.0784336,          0.879142973793104]
+    ]);
+    let outset: Mat3 = Mat3::new([
+        [ 1.19687900512017,   -0.0980208811401368, -0.0990297440797205],
+        [-0.0528968517574562,  1.15190312990417,   -0.0989611768448433],
+        [-0.0529716355144438, -0.0980434501171241,  1.15107367264116]
+    ]);
+    let min_ev: f64 = -12.47393;
+    let max_ev: f64 = 4.026069;
+
+    let contrast = |v: f64| -> f64 {
+        let log: f64 = v.max(1e-10).log2();
+        let x: f64 = saturate((log - min_ev) / (max_ev - min_ev));
+        let x2: f64 = x * x;
+        let x4: f64 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+    };
+
+    let v: Color = inset * *color;
+    let curved: Color = outset * Color::new(contrast(v.r()), contrast(v.g()), contrast(v.b()));
+    // The curve is display encoded, undo the 2.2 gamma so output is linear like the other operators.
+    Color::new(
+        curved.r().max(0.0).powf(2.2),
+        curved.g().max(0.0).powf(2.2),
+        curved.b().max(0.0).powf(2.2)
+    )
+}
+
+// John Hable's Uncharted 2 filmic curve.
+fn hable(color: &Color, white: f64) -> Color {
+    let curve = |x: f64| -> f64 {
+        let a: f64 = 0.15;
+        let b: f64 = 0.50;
+        let c: f64 = 0.10;
+        let d: f64 = 0.20;
+        let e: f64 = 0.02;
+        let f: f64 = 0.30;
+        ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+    };
+    let exposure_bias: f64 = 2.0;
+    let white_scale: f64 = 1.0 / curve(white);
+    Color::new(
+        curve(color.r() * exposure_bias) * white_scale,
+        curve(color.g() * exposure_bias) * white_scale,
+        curve(color.b() * exposure_bias) * white_scale
+    )
+}
+
+// Kim et al. 2002 cubic spline fit of the Planckian locus, valid from 1667K to 25000K.
+pub fn kelvin_to_xy(kelvin: f64) -> (f64, f64) {
+    let t: f64 = kelvin.clamp(1667.0, 25000.0);
+    let t2: f64 = t * t;
+    let t3: f64 = t2 * t;
+
+    let x: f64 = if t <= 4000.0 {
+        -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910
+    } else {
+        -3.0258469e9 / t3 + 2.1070379e6 / t2 + 0.2226347e3 / t + 0.240390
+    };
+
+    let x2: f64 = x * x;
+    let x3: f64 = x2 * x;
+    let y: f64 = if t <= 2222.0 {
+        -1.1063814 * x3 - 1.34811020 * x2 + 2.18555832 * x - 0.20219683
+    } else if t <= 4000.0 {
+        -0.9549476 * x3 - 1.37418593 * x2 + 2.09137015 * x - 0.16748867
+    } else {
+        3.0817580 * x3 - 5.87338670 * x2 + 3.75112997 * x - 0.37001483
+    };
+
+    (x, y)
+}
+
+pub fn xy_to_xyz(x: f64, y: f64) -> Vec3 {
+    Vec3::new(x / y, 1.0, (1.0 - x - y) / y)
+}
+
+pub fn srgb_to_xyz_matrix() -> Mat3 {
+    Mat3::new([
+        [0.4124564, 0.3575761, 0.1804375],
+        [0.2126729, 0.7151522, 0.0721750],
+        [0.0193339, 0.1191920, 0.9503041]
+    ])
+}
+
+pub fn bradford_matrix() -> Mat3 {
+    Mat3::new([
+        [ 0.8951,  0.2664, -0.1614],
+        [-0.7502,  1.7135,  0.0367],
+        [ 0.0389, -0.0685,  1.0296]
+    ])
+}
+
+// Linear sRGB to linear sRGB chromatic adaptation from white `src` to white `dst`, both XYZ.
+pub fn chromatic_adaptation(src: &Vec3, dst: &Vec3) -> Mat3 {
+    let bradford: Mat3 = bradford_matrix();
+    let src_lms: Vec3 = bradford * *src;
+    let dst_lms: Vec3 = bradford * *dst;
+    let scale: Mat3 = Mat3::diagonal(&Vec3::new(
+        dst_lms.x() / src_lms.x(),
+        dst_lms.y() / src_lms.y(),
+        dst_lms.z() / src_lms.z()
+    ));
+    let to_xyz: Mat3 = srgb_to_xyz_matrix();
+    to_xyz.inverse() * bradford.inverse() * scale * bradford * to_xyz
+}
+
+pub fn white_balance_matrix(kelvin: f64, reference_kelvin: f64) -> Mat3 {
+    let src: (f64, f64) = kelvin_to_xy(kelvin);
+    let dst: (f64, f64) = kelvin_to_xy(reference_kelvin);
+    chromatic_adaptation(&xy_to_xyz(src.0, src.1), &xy_to_xyz(dst.0, dst.1))
+}
@@ -180,8 +180,8 @@ impl ops::MulAssign<Vec3> for Vec3 {
     fn mul_assign(&mut self, rhs: Vec3)  {
         *self = Vec3::new( 
             self.x() * rhs.x(),
-            self.y() * rhs.z(),
-            self.z() * rhs.x(),
+            self.y() * rhs.y(),
+            self.z() * rhs.z(),
         );
     }
 }
@@ -339,6 +339,18 @@ impl Color {
         self.z()
     }
 
+    pub fn is_black(self: &Color) -> bool {
+        self.r() <= 0.0 && self.g() <= 0.0 && self.b() <= 0.0
+    }
+
+    pub fn average(self: &Color) -> f64 {
+        (self.r() + self.g() + self.b()) / 3.0
+    }
+
+    pub fn luminance(self: &Color) -> f64 {
+        0.2126 * self.r() + 0.7152 * self.g() + 0.0722 * self.b()
+    }
+
     pub fn to_srgb(self: &Color) -> Color {
         Color::new(
             linear_to_srgb_f64(self.r()),
@@ -7,12 +7,20 @@ use crate::hittable::*;
 use crate::hittable_list::HittableList;
 use crate::quad::*;
 use crate::material::*;
+use crate::mtl::MaterialLibrary;
 use crate::sphere::*;
-use crate::texture::CheckerTexture;
-use crate::texture::ImageTexture;
+use crate::color_space::ColorSpace;
+use crate::procedural::*;
+use crate::projection::{Projection, ProjectedTexture};
+use crate::texture_graph::TextureGraph;
+use crate::texture::{CheckerTexture, solid_color, solid_value};
+use crate::texture::{ImageTexture, FilterMode, MipFilter, WrapMode, UvTransform};
 use crate::texture::Texture;
 use crate::vec3::*;
 use crate::bvh::*;
+use crate::light::*;
+use crate::spectrum::Dispersion;
+use crate::thin_film::ThinFilm;
 
 pub fn quads() -> HittableList {
     let mut l_world: HittableList = HittableList{..HittableList::default()};
@@ -34,16 +42,619 @@ pub fn quads() -> HittableList {
     world
 }
 
+pub fn lights() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    let checker: Arc<dyn Texture + Sync> = Arc::new(CheckerTexture::new(Color::new(0.2, 0.2, 0.2), Color::new(0.8, 0.8, 0.8), 20.0));
+    let ground: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&checker));
+    let white: Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let red: Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let metal: Arc<dyn Material + Sync> = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.1));
+
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-10.0, 0.0, 10.0), Vec3::new(20.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -20.0), &ground)));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-10.0, 0.0, -4.0), Vec3::new(20.0, 0.0, 0.0), Vec3::new(0.0, 8.0, 0.0), &white)));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-2.5, 1.0, 0.0), 1.0, &white)));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new( 0.0, 1.0, 0.0), 1.0, &red)));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new( 2.5, 1.0, 0.0), 1.0, &metal)));
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+
+    let mut key: PointLight = PointLight::new(Point3::new(-4.0, 5.0, 4.0), Color::new(40.0, 36.0, 30.0));
+    key.range = 20.0;
+    world.add_light(Arc::new(key));
+    world.add_light(Arc::new(SpotLight::new(Point3::new(3.0, 6.0, 3.0), Point3::new(2.5, 0.0, 0.0), Color::new(30.0, 40.0, 60.0), 12.0, 20.0)));
+    world.add_light(Arc::new(DirectionalLight::new(Vec3::new(1.0, -1.0, -0.5), Color::new(0.3, 0.3, 0.35))));
+    world
+}
+
+pub fn emitters() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    let white: Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let bezel: Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::new(0.05, 0.05, 0.05)));
+
+    // A TV screen showing the earth texture, unfiltered like a low resolution display.
+    let mut earth_image: ImageTexture = ImageTexture::new(&PathBuf::from("input/earthmap.tga"));
+    earth_image.filter = FilterMode::Nearest;
+    let earth_texture: Arc<dyn Texture + Sync> = Arc::new(earth_image);
+    let screen: Arc<dyn Material + Sync> = Arc::new(Emiter::new_texture(&earth_texture, 4.0));
+
+    // A lamp panel hanging in the room, lighting both the floor and the ceiling through a soft cone.
+    let mut panel_emiter: Emiter = Emiter::new(Color::new(1.0, 0.85, 0.6));
+    panel_emiter.intensity = 8.0;
+    panel_emiter.two_sided = true;
+    panel_emiter.set_cone(30.0, 60.0);
+    let panel: Arc<dyn Material + Sync> = Arc::new(panel_emiter);
+
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-10.0, 0.0, 10.0), Vec3::new(20.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -20.0), &white)));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-10.0, 6.0, -10.0), Vec3::new(20.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 20.0), &white)));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-10.0, 0.0, -4.0), Vec3::new(20.0, 0.0, 0.0), Vec3::new(0.0, 6.0, 0.0), &white)));
+
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-3.2, 0.9, -3.99), Vec3::new(6.4, 0.0, 0.0), Vec3::new(0.0, 3.7, 0.0), &bezel)));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-3.0, 1.0, -3.98), Vec3::new(6.0, 0.0, 0.0), Vec3::new(0.0, 3.5, 0.0), &screen)));
+
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-1.0, 4.0, 2.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0), &panel)));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(0.0, 1.0, 2.5), 1.0, &white)));
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world
+}
+
+// Spheres inside a uniform white environment.  A material that conserves energy and never absorbs
+// should vanish against the background, any darkening shows energy lost by the model.
+pub fn white_furnace() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    let white_diffuse: Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::white()));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-6.0, 1.2, 0.0), 1.0, &white_diffuse)));
+
+    // Perfect reflectors with increasing roughness, the top row is isotropic and the bottom anisotropic.
+    for i in 0..5 {
+        let roughness: f64 = i as f64 * 0.25;
+        let x: f64 = -3.0 + i as f64 * 2.4;
+        let isotropic: Arc<dyn Material + Sync> = Arc::new(Conductor::new_reflectance(Color::white(), roughness, 0.0));
+        let anisotropic: Arc<dyn Material + Sync> = Arc::new(Conductor::new_reflectance(Color::white(), roughness, 0.8));
+        l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(x, 1.2, 0.0), 1.0, &isotropic)));
+        l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(x, -1.2, 0.0), 1.0, &anisotropic)));
+    }
+
+    let presets: [ConductorPreset; 4] = [ConductorPreset::Gold, ConductorPreset::Copper, ConductorPreset::Aluminium, ConductorPreset::Silver];
+    for (i, preset) in presets.iter().enumerate() {
+        let mat: Arc<dyn Material + Sync> = Arc::new(Conductor::new_preset(*preset, 0.3, 0.0));
+        l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-3.6 + i as f64 * 2.4, -3.6, 0.0), 1.0, &mat)));
+    }
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world
+}
+
+pub fn rough_dielectrics() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    let checker: Arc<dyn Texture + Sync> = Arc::new(CheckerTexture::new(Color::new(0.1, 0.1, 0.1), Color::new(0.9, 0.9, 0.9), 16.0));
+    let backdrop: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&checker));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-8.0, 0.0, 8.0), Vec3::new(16.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -16.0), &backdrop)));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-8.0, 0.0, -3.0), Vec3::new(16.0, 0.0, 0.0), Vec3::new(0.0, 8.0, 0.0), &backdrop)));
+
+    // Smooth glass for reference, then each preset with increasing roughness.
+    let smooth: Arc<dyn Material + Sync> = Arc::new(Dielectric::new_preset(DielectricPreset::Glass));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-5.0, 1.0, 0.0), 0.9, &smooth)));
+
+    let presets: [DielectricPreset; 6] = [
+        DielectricPreset::Ice,
+        DielectricPreset::Water,
+        DielectricPreset::Acrylic,
+        DielectricPreset::Glass,
+        DielectricPreset::Sapphire,
+        DielectricPreset::Diamond
+    ];
+    for (i, preset) in presets.iter().enumerate() {
+        let roughness: f64 = 0.05 + i as f64 * 0.1;
+        let mat: Arc<dyn Material + Sync> = Arc::new(RoughDielectric::new_preset(*preset, roughness));
+        l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-3.0 + i as f64 * 1.9, 1.0, 0.0), 0.9, &mat)));
+    }
+
+    // Tinted glass blocks of increasing thickness, all sharing the same transmission colour.
+    let mut green_glass: Dielectric = Dielectric::new_preset(DielectricPreset::Glass);
+    green_glass.set_transmission_color(Color::new(0.4, 0.8, 0.5), 0.5);
+    let green_glass: Arc<dyn Material + Sync> = Arc::new(green_glass);
+    let mut amber_frost: RoughDielectric = RoughDielectric::new_preset(DielectricPreset::Acrylic, 0.2);
+    amber_frost.set_transmission_color(Color::new(0.9, 0.6, 0.2), 0.5);
+    let amber_frost: Arc<dyn Material + Sync> = Arc::new(amber_frost);
+    for i in 0..4 {
+        let thickness: f64 = 0.2 + i as f64 * 0.4;
+        let x: f64 = -4.0 + i as f64 * 1.2;
+        l_world.add_obj(Arc::new(make_box(&Point3::new(x, 0.0, 3.0), &Point3::new(x + 0.8, 1.2, 3.0 + thickness), &green_glass)));
+        l_world.add_obj(Arc::new(make_box(&Point3::new(x + 5.0, 0.0, 3.0), &Point3::new(x + 5.8, 1.2, 3.0 + thickness), &amber_frost)));
+    }
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world.add_light(Arc::new(DirectionalLight::new(Vec3::new(-0.3, -1.0, -0.6), Color::new(2.0, 2.0, 2.0))));
+    world
+}
+
+// Dispersive glass in front of a striped light wall, the fringes only show with --spectral.
+pub fn dispersion() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    let floor_checker: Arc<dyn Texture + Sync> = Arc::new(CheckerTexture::new(Color::new(0.05, 0.05, 0.05), Color::new(0.5, 0.5, 0.5), 2.0));
+    let floor: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&floor_checker));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-10.0, 0.0, 10.0), Vec3::new(20.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -20.0), &floor)));
+
+    // High contrast stripes make the colour fringes easy to see through the glass.
+    let stripes: Arc<dyn Texture + Sync> = Arc::new(CheckerTexture::new(Color::black(), Color::white(), 3.0));
+    let wall: Arc<dyn Material + Sync> = Arc::new(Emiter::new_texture(&stripes, 2.0));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-10.0, 0.0, -4.0), Vec3::new(20.0, 0.0, 0.0), Vec3::new(0.0, 10.0, 0.0), &wall)));
+
+    let materials: [Dielectric; 4] = [
+        Dielectric::new_dispersive(1.49, Dispersion::Cauchy { a: 1.4767, b: 0.0046 }),
+        Dielectric::new_dispersive(1.517, Dispersion::bk7()),
+        Dielectric::new_dispersive(1.805, Dispersion::dense_flint()),
+        Dielectric::new_dispersive(2.417, Dispersion::diamond())
+    ];
+    for (i, dielectric) in materials.iter().enumerate() {
+        let mat: Arc<dyn Material + Sync> = Arc::new(dielectric.clone());
+        l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-3.3 + i as f64 * 2.2, 1.0, 0.0), 1.0, &mat)));
+    }
+
+    // A thick dense flint slab turned towards the camera.
+    let slab_glass: Arc<dyn Material + Sync> = Arc::new(Dielectric::new_dispersive(1.805, Dispersion::dense_flint()));
+    let slab: Arc<dyn Hittable + Sync> = Arc::new(make_box(&Point3::new(-1.5, 0.0, -0.4), &Point3::new(1.5, 0.8, 0.4), &slab_glass));
+    let slab: Arc<dyn Hittable + Sync> = Arc::new(RotateY::new(slab, 35.0));
+    l_world.add_obj(Arc::new(Translate::new(slab, &Vec3::new(0.0, 0.0, 2.5))));
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world
+}
+
+pub fn principled() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    let checker: Arc<dyn Texture + Sync> = Arc::new(CheckerTexture::new(Color::new(0.2, 0.2, 0.2), Color::new(0.6, 0.6, 0.6), 2.0));
+    let floor: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&checker));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-12.0, 0.0, 12.0), Vec3::new(24.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -24.0), &floor)));
+
+    // Back row is a plastic and front row a gold metal, both with increasing roughness.
+    for i in 0..5 {
+        let roughness: f64 = i as f64 * 0.25;
+        let x: f64 = -4.8 + i as f64 * 2.4;
+
+        let mut plastic: Principled = Principled::new(Color::new(0.8, 0.1, 0.1));
+        plastic.roughness = solid_value(roughness);
+        let plastic: Arc<dyn Material + Sync> = Arc::new(plastic);
+        l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(x, 1.0, -2.5), 1.0, &plastic)));
+
+        let mut gold: Principled = Principled::new(Color::new(1.0, 0.78, 0.34));
+        gold.metallic = solid_value(1.0);
+        gold.roughness = solid_value(roughness);
+        let gold: Arc<dyn Material + Sync> = Arc::new(gold);
+        l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(x, 1.0, 0.0), 1.0, &gold)));
+    }
+
+    // Sheen, clearcoat, transmission and emission read from a material library, and a textured roughness.
+    // Missing libraries and materials render magenta like missing images.
+    let library: MaterialLibrary = MaterialLibrary::load(&PathBuf::from("input/principled.mtl")).unwrap_or_else(|e: std::io::Error| -> MaterialLibrary {
+        eprintln!("Failed to load material library: {}", e);
+        MaterialLibrary::default()
+    });
+    let material = |name: &str| -> Arc<dyn Material + Sync> {
+        library.get(name).unwrap_or_else(|e: std::io::Error| -> Arc<dyn Material + Sync> {
+            eprintln!("{}", e);
+            Arc::new(Lambertian::new(Color::new(1.0, 0.0, 1.0)))
+        })
+    };
+
+    let roughness_checker: Arc<dyn Texture + Sync> = Arc::new(CheckerTexture::new(Color::new(0.05, 0.05, 0.05), Color::new(0.7, 0.7, 0.7), 8.0));
+    let mut patchy: Principled = Principled::new(Color::new(0.9, 0.9, 0.9));
+    patchy.metallic = solid_value(1.0);
+    patchy.roughness = roughness_checker;
+
+    let specials: [Arc<dyn Material + Sync>; 5] = [material("velvet"), material("car_paint"), material("glass"), material("lamp"), Arc::new(patchy)];
+    for (i, mat) in specials.iter().enumerate() {
+        l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-4.8 + i as f64 * 2.4, 1.0, 2.5), 1.0, mat)));
+    }
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world.add_light(Arc::new(DirectionalLight::new(Vec3::new(-0.4, -1.0, -0.5), Color::new(2.5, 2.5, 2.5))));
+    world
+}
+
+pub fn layered() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    let checker: Arc<dyn Texture + Sync> = Arc::new(CheckerTexture::new(Color::new(0.2, 0.2, 0.2), Color::new(0.6, 0.6, 0.6), 2.0));
+    let floor: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&checker));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-12.0, 0.0, 12.0), Vec3::new(24.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -24.0), &floor)));
+
+    // Rust patches on steel, following the continents of the earth texture.
+    let earth_texture: Arc<dyn Texture + Sync> = Arc::new(ImageTexture::new(&PathBuf::from("input/earthmap.tga")));
+    let steel: Arc<dyn Material + Sync> = Arc::new(Conductor::new_preset(ConductorPreset::Aluminium, 0.25, 0.0));
+    let rust: Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::new(0.45, 0.18, 0.06)));
+    let rusty: Arc<dyn Material + Sync> = Arc::new(MixMaterial::new_texture(&steel, &rust, &earth_texture));
+
+    // Varnished wood, lacquered copper and a varnished globe.
+    let wood: Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.08)));
+    let varnished_wood: Arc<dyn Material + Sync> = Arc::new(Coated::new(&wood, 1.5));
+    let copper: Arc<dyn Material + Sync> = Arc::new(Conductor::new_preset(ConductorPreset::Copper, 0.4, 0.0));
+    let lacquered_copper: Arc<dyn Material + Sync> = Arc::new(Coated::new(&copper, 1.5));
+    let globe: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&earth_texture));
+    let varnished_globe: Arc<dyn Material + Sync> = Arc::new(Coated::new(&globe, 1.5));
+
+    // Half mirror, half diffuse.
+    let mirror: Arc<dyn Material + Sync> = Arc::new(Metal::new(Color::new(0.9, 0.9, 0.9), 0.0));
+    let green: Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::new(0.2, 0.6, 0.2)));
+    let half_mirror: Arc<dyn Material + Sync> = Arc::new(MixMaterial::new(&green, &mirror, 0.5));
+
+    let materials: [Arc<dyn Material + Sync>; 5] = [rusty, varnished_wood, lacquered_copper, varnished_globe, half_mirror];
+    for (i, mat) in materials.iter().enumerate() {
+        l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-4.8 + i as f64 * 2.4, 1.0, 0.0), 1.0, mat)));
+    }
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world.add_light(Arc::new(DirectionalLight::new(Vec3::new(-0.4, -1.0, -0.5), Color::new(2.5, 2.5, 2.5))));
+    world
+}
+
+pub fn normal_maps() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    let checker: Arc<dyn Texture + Sync> = Arc::new(CheckerTexture::new(Color::new(0.2, 0.2, 0.2), Color::new(0.6, 0.6, 0.6), 2.0));
+    let floor: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&checker));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-12.0, 0.0, 12.0), Vec3::new(24.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -24.0), &floor)));
+
+    // The earth bump mapped by its own brightness, bicubic keeps the slopes smooth.
+    let mut earth_image: ImageTexture = ImageTexture::new(&PathBuf::from("input/earthmap.tga"));
+    earth_image.filter = FilterMode::Bicubic;
+    let earth_texture: Arc<dyn Texture + Sync> = Arc::new(earth_image);
+    let globe: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&earth_texture));
+    let bumpy_globe: Arc<dyn Material + Sync> = Arc::new(BumpMapped::new(&globe, &earth_texture, 0.02));
+
+    // Alternating tilted normals make faceted bands across the uvs.
+    let facets: Arc<dyn Texture + Sync> = Arc::new(CheckerTexture::new(Color::new(0.8, 0.5, 0.9), Color::new(0.2, 0.5, 0.9), 24.0));
+    let plastic: Arc<dyn Material + Sync> = Arc::new(Principled::new(Color::new(0.7, 0.1, 0.1)));
+    let faceted: Arc<dyn Material + Sync> = Arc::new(NormalMapped::new(&plastic, &facets, 1.0));
+
+    // Brushed metal, the anisotropy follows dpdu around the sphere.
+    let brushed: Arc<dyn Material + Sync> = Arc::new(Conductor::new_preset(ConductorPreset::Gold, 0.4, 0.9));
+
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-3.6, 1.0, 0.0), 1.0, &bumpy_globe)));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-1.2, 1.0, 0.0), 1.0, &faceted)));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(1.2, 1.0, 0.0), 1.0, &brushed)));
+
+    // A rotated box, the normal map follows the transformed tangents.
+    let faceted_box: Arc<dyn Hittable + Sync> = Arc::new(make_box(&Point3::new(-0.8, 0.0, -0.8), &Point3::new(0.8, 1.6, 0.8), &faceted));
+    let faceted_box: Arc<dyn Hittable + Sync> = Arc::new(RotateY::new(faceted_box, 30.0));
+    l_world.add_obj(Arc::new(Translate::new(faceted_box, &Vec3::new(3.6, 0.001, 0.0))));
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world.add_light(Arc::new(DirectionalLight::new(Vec3::new(-0.4, -1.0, -0.5), Color::new(2.5, 2.5, 2.5))));
+    world
+}
+
+pub fn cutouts() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    let checker: Arc<dyn Texture + Sync> = Arc::new(CheckerTexture::new(Color::new(0.2, 0.2, 0.2), Color::new(0.6, 0.6, 0.6), 2.0));
+    let floor: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&checker));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-12.0, 0.0, 12.0), Vec3::new(24.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -24.0), &floor)));
+
+    // A chain link fence with crisp edges, in front of a ball.
+    let fence_texture: Arc<dyn Texture + Sync> = Arc::new(ImageTexture::new(&PathBuf::from("input/fence.tga")));
+    let wire: Arc<dyn Material + Sync> = Arc::new(Conductor::new_preset(ConductorPreset::Aluminium, 0.4, 0.0));
+    let fence: Arc<dyn Material + Sync> = Arc::new(AlphaMasked::new(&wire, &fence_texture, AlphaMode::Threshold(0.5)));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-5.0, 0.0, 1.5), Vec3::new(4.0, 0.0, 0.0), Vec3::new(0.0, 2.5, 0.0), &fence)));
+    let red: Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::new(0.7, 0.1, 0.1)));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-3.0, 1.0, -0.5), 1.0, &red)));
+
+    // Leaves with soft edges, the far side of each quad is lit through the holes.
+    let mut leaf_image: ImageTexture = ImageTexture::new(&PathBuf::from("input/leaf.tga"));
+    // A decal, filtering must not blend in the opposite edge.
+    leaf_image.wrap = WrapMode::Clamp;
+    let leaf_texture: Arc<dyn Texture + Sync> = Arc::new(leaf_image);
+    let leaf_albedo: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&leaf_texture));
+    let leaf: Arc<dyn Material + Sync> = Arc::new(AlphaMasked::new(&leaf_albedo, &leaf_texture, AlphaMode::Stochastic));
+    for i in 0..5 {
+        let angle: f64 = i as f64 * 35.0 - 70.0;
+        let blade: Arc<dyn Hittable + Sync> = Arc::new(Quad::new(Point3::new(-0.6, 0.0, 0.0), Vec3::new(1.2, 0.0, 0.0), Vec3::new(0.0, 2.2, 0.4), &leaf));
+        let blade: Arc<dyn Hittable + Sync> = Arc::new(RotateY::new(blade, angle));
+        l_world.add_obj(Arc::new(Translate::new(blade, &Vec3::new(0.8 + i as f64 * 0.5, 0.01, 0.5 - (i % 2) as f64 * 0.8))));
+    }
+
+    // A cage, the back of the sphere shows through the front.
+    let cage: Arc<dyn Material + Sync> = Arc::new(AlphaMasked::new(&red, &fence_texture, AlphaMode::Threshold(0.5)));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(4.5, 1.2, 0.0), 1.2, &cage)));
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world.add_light(Arc::new(DirectionalLight::new(Vec3::new(0.3, -1.0, -0.6), Color::new(2.5, 2.5, 2.5))));
+    world
+}
+
+pub fn subsurface() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    let checker: Arc<dyn Texture + Sync> = Arc::new(CheckerTexture::new(Color::new(0.2, 0.2, 0.2), Color::new(0.6, 0.6, 0.6), 2.0));
+    let floor: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&checker));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-12.0, 0.0, 12.0), Vec3::new(24.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -24.0), &floor)));
+
+    // Plaster for reference, then wax, skin, marble and milk.
+    let plaster: Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::new(0.8, 0.75, 0.6)));
+    let wax: Arc<dyn Material + Sync> = Arc::new(Subsurface::new(Color::new(0.8, 0.75, 0.6), Color::new(0.5, 0.4, 0.25), 0.0));
+    let skin: Arc<dyn Material + Sync> = Arc::new(Subsurface::new(Color::new(0.85, 0.55, 0.45), Color::new(0.6, 0.25, 0.15), 0.0));
+    let marble_base: Arc<dyn Material + Sync> = Arc::new(Subsurface::new(Color::new(0.85, 0.85, 0.82), Color::new(0.3, 0.3, 0.3), -0.3));
+    let marble: Arc<dyn Material + Sync> = Arc::new(Coated::new(&marble_base, 1.5));
+    let milk_base: Arc<dyn Material + Sync> = Arc::new(Subsurface::new(Color::new(0.95, 0.95, 0.9), Color::new(0.15, 0.18, 0.25), 0.8));
+    let milk: Arc<dyn Material + Sync> = Arc::new(Coated::new(&milk_base, 1.35));
+
+    let materials: [&Arc<dyn Material + Sync>; 5] = [&plaster, &wax, &skin, &marble, &milk];
+    for (i, mat) in materials.iter().enumerate() {
+        let x: f64 = (i as f64 - 2.0) * 2.4;
+        l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(x, 1.0, 0.0), 1.0, mat)));
+    }
+
+    // A thin wax slab in front of a light, bleeding through.
+    let slab: Arc<dyn Hittable + Sync> = Arc::new(make_box(&Point3::new(-1.5, 0.0, -3.0), &Point3::new(1.5, 2.5, -2.8), &wax));
+    l_world.add_obj(slab);
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world.add_light(Arc::new(PointLight::new(Point3::new(0.0, 1.2, -4.5), Color::new(8.0, 6.0, 4.0))));
+    world.add_light(Arc::new(DirectionalLight::new(Vec3::new(-0.4, -1.0, -0.5), Color::new(2.0, 2.0, 2.0))));
+    world
+}
+
+pub fn thin_films() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    let checker: Arc<dyn Texture + Sync> = Arc::new(CheckerTexture::new(Color::new(0.2, 0.2, 0.2), Color::new(0.6, 0.6, 0.6), 2.0));
+    let floor: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&checker));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-12.0, 0.0, 12.0), Vec3::new(24.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -24.0), &floor)));
+
+    // A soap bubble, air on both sides of a swirling water film.  The map is film thickness, not colour.
+    let mut swirl_image: ImageTexture = ImageTexture::new_in(&PathBuf::from("input/earthmap.tga"), Some(ColorSpace::Rec709));
+    swirl_image.wrap = WrapMode::Mirror;
+    swirl_image.transform = UvTransform::new(2.0, 2.0, 0.25, 0.0, 30.0);
+    let swirl: Arc<dyn Texture + Sync> = Arc::new(swirl_image);
+    let mut soap: Dielectric = Dielectric::new(1.0);
+    soap.thin_film = Some(ThinFilm::new_texture(1200.0, &swirl, 1.33));
+    let bubble: Arc<dyn Material + Sync> = Arc::new(soap);
+
+    // Glass with a quarter wave magnesium fluoride anti-reflective coating.
+    let mut lens_glass: Dielectric = Dielectric::new_preset(DielectricPreset::Glass);
+    lens_glass.thin_film = Some(ThinFilm::new(110.0, 1.38));
+    let lens: Arc<dyn Material + Sync> = Arc::new(lens_glass);
+
+    // Anodised titanium and oxidised gold.
+    let mut anodised_metal: Conductor = Conductor::new(Color::new(2.74, 2.54, 2.27), Color::new(3.81, 3.43, 3.04), 0.1, 0.0);
+    anodised_metal.thin_film = Some(ThinFilm::new(120.0, 2.4));
+    let anodised: Arc<dyn Material + Sync> = Arc::new(anodised_metal);
+    let mut tarnished_metal: Conductor = Conductor::new_preset(ConductorPreset::Gold, 0.35, 0.0);
+    tarnished_metal.thin_film = Some(ThinFilm::new(400.0, 1.5));
+    let tarnished: Arc<dyn Material + Sync> = Arc::new(tarnished_metal);
+
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-3.6, 1.4, 0.0), 1.0, &bubble)));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-1.2, 1.0, 0.0), 1.0, &lens)));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(1.2, 1.0, 0.0), 1.0, &anodised)));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(3.6, 1.0, 0.0), 1.0, &tarnished)));
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world.add_light(Arc::new(DirectionalLight::new(Vec3::new(-0.4, -1.0, -0.5), Color::new(2.5, 2.5, 2.5))));
+    world
+}
+
+pub fn texture_filtering() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    // Three long strips of tiles receding to the horizon, unfiltered, trilinear and EWA from left to right.
+    let filters: [MipFilter; 3] = [MipFilter::None, MipFilter::Trilinear, MipFilter::Ewa];
+    for (i, filter) in filters.iter().enumerate() {
+        let mut tiles: ImageTexture = ImageTexture::new(&PathBuf::from("input/tiles.tga"));
+        tiles.transform = UvTransform::tile(4.0, 100.0);
+        tiles.mip_filter = *filter;
+        let tiles: Arc<dyn Texture + Sync> = Arc::new(tiles);
+        let floor: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&tiles));
+        let x: f64 = -6.0 + 4.0 * i as f64;
+        l_world.add_obj(Arc::new(Quad::new(Point3::new(x, 0.0, 10.0), Vec3::new(4.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -100.0), &floor)));
+    }
+
+    // Mirror and glass balls, their reflections and refractions of the floor are filtered too.
+    let chrome: Arc<dyn Material + Sync> = Arc::new(Conductor::new_preset(ConductorPreset::Silver, 0.0, 0.0));
+    let glass: Arc<dyn Material + Sync> = Arc::new(Dielectric::new_preset(DielectricPreset::Glass));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-1.2, 1.0, 2.0), 1.0, &chrome)));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(1.2, 1.0, 2.0), 1.0, &glass)));
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world.add_light(Arc::new(DirectionalLight::new(Vec3::new(-0.4, -1.0, -0.5), Color::new(2.5, 2.5, 2.5))));
+    world
+}
+
+pub fn procedural_textures() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    // A brick wall in world space, so the courses line up with the floor, and a floor darkening away from
+    // the spheres.
+    let bricks: Arc<dyn Texture + Sync> = Arc::new(BrickTexture::new(TextureSpace::World, 0.5, 0.25, Color::new(0.55, 0.2, 0.12), Color::new(0.7, 0.68, 0.62)));
+    let wall: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&bricks));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-12.0, 0.0, -3.0), Vec3::new(24.0, 0.0, 0.0), Vec3::new(0.0, 8.0, 0.0), &wall)));
+    let fade: Arc<dyn Texture + Sync> = Arc::new(GradientTexture::new(TextureSpace::World, GradientKind::Radial, Point3::new(0.0, 0.0, 0.0), Point3::new(10.0, 0.0, 0.0), Color::new(0.6, 0.6, 0.6), Color::new(0.15, 0.15, 0.15)));
+    let floor: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&fade));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-12.0, 0.0, 12.0), Vec3::new(24.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -15.0), &floor)));
+
+    // Wood rings around each sphere's own axis.
+    let wood: Arc<dyn Texture + Sync> = Arc::new(WoodTexture::new(TextureSpace::Object, Color::new(0.75, 0.52, 0.3), Color::new(0.35, 0.18, 0.08), 6.0));
+
+    // Randomly coloured cells outlined by a ramp over the distance to their edges.
+    let cells: Arc<dyn Texture + Sync> = Arc::new(VoronoiTexture::new(TextureSpace::Object, 4.0, VoronoiOutput::Cell));
+    let edges: Arc<dyn Texture + Sync> = Arc::new(VoronoiTexture::new(TextureSpace::Object, 4.0, VoronoiOutput::Edge));
+    let outline: Arc<dyn Texture + Sync> = Arc::new(ColorRamp::new(&edges, &[(0.0, Color::white()), (0.1, Color::black())]));
+    let outlined_cells: Arc<dyn Texture + Sync> = Arc::new(MixTexture::new(&cells, &solid_color(Color::new(0.05, 0.05, 0.05)), &outline));
+
+    // Marble from fBm through a colour ramp with a thin dark vein.
+    let noise: Arc<dyn Texture + Sync> = Arc::new(NoiseTexture::new(TextureSpace::Object, 2.5, 6));
+    let marble: Arc<dyn Texture + Sync> = Arc::new(ColorRamp::new(&noise, &[
+        (0.0, Color::new(0.9, 0.9, 0.88)),
+        (0.45, Color::new(0.8, 0.8, 0.78)),
+        (0.5, Color::new(0.2, 0.22, 0.25)),
+        (0.55, Color::new(0.8, 0.8, 0.78)),
+        (1.0, Color::new(0.95, 0.95, 0.93))
+    ]));
+
+    // A bottom to top gradient scaled by noise.
+    let gradient: Arc<dyn Texture + Sync> = Arc::new(GradientTexture::new(TextureSpace::Object, GradientKind::Linear, Point3::new(0.0, -0.8, 0.0), Point3::new(0.0, 0.8, 0.0), Color::new(0.1, 0.2, 0.8), Color::new(0.95, 0.5, 0.1)));
+    let clouds: Arc<dyn Texture + Sync> = Arc::new(NoiseTexture::new(TextureSpace::Object, 4.0, 4));
+    let cloudy_gradient: Arc<dyn Texture + Sync> = Arc::new(ScaleTexture::new(&gradient, &clouds));
+
+    let textures: [&Arc<dyn Texture + Sync>; 4] = [&wood, &outlined_cells, &marble, &cloudy_gradient];
+    for (i, texture) in textures.iter().enumerate() {
+        let mut principled: Principled = Principled::new_texture(texture);
+        principled.roughness = solid_value(0.4);
+        let mat: Arc<dyn Material + Sync> = Arc::new(principled);
+        l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-3.0 + 2.0 * i as f64, 0.8, 0.0), 0.8, &mat)));
+    }
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world.add_light(Arc::new(DirectionalLight::new(Vec3::new(-0.4, -1.0, -0.7), Color::new(2.5, 2.5, 2.5))));
+    world
+}
+
+pub fn texture_graphs() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    // Every texture comes from a graph file, missing graphs and nodes render magenta like missing images.
+    let graph: TextureGraph = TextureGraph::load(&PathBuf::from("input/materials.graph")).unwrap_or_else(|e: std::io::Error| -> TextureGraph {
+        eprintln!("Failed to load texture graph: {}", e);
+        TextureGraph::default()
+    });
+    let node = |name: &str| -> Arc<dyn Texture + Sync> {
+        graph.get(name).unwrap_or_else(|e: std::io::Error| -> Arc<dyn Texture + Sync> {
+            eprintln!("{}", e);
+            solid_color(Color::new(1.0, 0.0, 1.0))
+        })
+    };
+
+    let floor: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&node("floor")));
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-12.0, 0.0, 12.0), Vec3::new(24.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -24.0), &floor)));
+
+    let mut marble: Principled = Principled::new_texture(&node("marble"));
+    marble.roughness = node("marble_roughness");
+    let marble: Arc<dyn Material + Sync> = Arc::new(marble);
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-2.2, 1.0, 0.0), 1.0, &marble)));
+
+    let mut rust: Principled = Principled::new_texture(&node("rust_color"));
+    rust.metallic = node("rust_metallic");
+    rust.roughness = node("rust_roughness");
+    let rust: Arc<dyn Material + Sync> = Arc::new(rust);
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(0.0, 1.0, 0.0), 1.0, &rust)));
+
+    let mut earth: Principled = Principled::new_texture(&node("night_earth"));
+    earth.emission = Some(node("night_earth"));
+    earth.emission_strength = 0.5;
+    let earth: Arc<dyn Material + Sync> = Arc::new(earth);
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(2.2, 1.0, 0.0), 1.0, &earth)));
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world.add_light(Arc::new(DirectionalLight::new(Vec3::new(-0.4, -1.0, -0.7), Color::new(2.0, 2.0, 2.0))));
+    world
+}
+
+pub fn projection_mapping() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    let earth: Arc<dyn Texture + Sync> = Arc::new(ImageTexture::new(&PathBuf::from("input/earthmap.tga")));
+    let tiles: Arc<dyn Texture + Sync> = Arc::new(ImageTexture::new(&PathBuf::from("input/tiles.tga")));
+    let projected = |texture: &Arc<dyn Texture + Sync>, projection: Projection, space: TextureSpace, scale: f64| -> Arc<dyn Material + Sync> {
+        let mut projected: ProjectedTexture = ProjectedTexture::new(texture, projection, space);
+        projected.scale = scale;
+        let projected: Arc<dyn Texture + Sync> = Arc::new(projected);
+        Arc::new(Lambertian::new_texture(&projected))
+    };
+
+    // Tiles laid over the floor in world space, running on regardless of the quad's own uvs.
+    let floor: Arc<dyn Material + Sync> = projected(&tiles, Projection::Planar, TextureSpace::World, 2.0);
+    l_world.add_obj(Arc::new(Quad::new(Point3::new(-12.0, 0.0, 12.0), Vec3::new(24.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -24.0), &floor)));
+
+    // The globe wrapped onto a cube, and around a sphere as a cylinder.
+    let globe_cube: Arc<dyn Material + Sync> = projected(&earth, Projection::Spherical, TextureSpace::Object, 1.0);
+    let cube: Arc<dyn Hittable + Sync> = Arc::new(make_box(&Point3::new(-0.75, -0.75, -0.75), &Point3::new(0.75, 0.75, 0.75), &globe_cube));
+    let cube: Arc<dyn Hittable + Sync> = Arc::new(RotateY::new(cube, 30.0));
+    l_world.add_obj(Arc::new(Translate::new(cube, &Vec3::new(-3.3, 0.75, 0.0))));
+
+    let mut globe_cylinder: ProjectedTexture = ProjectedTexture::new(&earth, Projection::Cylindrical, TextureSpace::Object);
+    globe_cylinder.origin = Point3::new(0.0, -1.0, 0.0);
+    globe_cylinder.scale = 2.0;
+    let globe_cylinder: Arc<dyn Texture + Sync> = Arc::new(globe_cylinder);
+    let globe_cylinder: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&globe_cylinder));
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(1.1, 1.0, 0.0), 1.0, &globe_cylinder)));
+
+    // Tiles from three sides, blended over a sphere and with hard seams on a cube.
+    let triplanar: Arc<dyn Material + Sync> = projected(&tiles, Projection::Box { sharpness: 4.0 }, TextureSpace::Object, 0.5);
+    l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-1.1, 1.0, 0.0), 1.0, &triplanar)));
+
+    let box_tiles: Arc<dyn Material + Sync> = projected(&tiles, Projection::Box { sharpness: f64::INFINITY }, TextureSpace::Object, 0.5);
+    let cube: Arc<dyn Hittable + Sync> = Arc::new(make_box(&Point3::new(-0.75, -0.75, -0.75), &Point3::new(0.75, 0.75, 0.75), &box_tiles));
+    let cube: Arc<dyn Hittable + Sync> = Arc::new(RotateY::new(cube, -25.0));
+    l_world.add_obj(Arc::new(Translate::new(cube, &Vec3::new(3.3, 0.75, 0.0))));
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world.add_light(Arc::new(DirectionalLight::new(Vec3::new(-0.4, -1.0, -0.7), Color::new(2.0, 2.0, 2.0))));
+    world
+}
+
+pub fn planar_shapes() -> HittableList {
+    let mut l_world: HittableList = HittableList{..HittableList::default()};
+
+    // An infinite floor out to the horizon, the tiles repeating every two units.
+    let tiles: Arc<dyn Texture + Sync> = Arc::new(ImageTexture::new(&PathBuf::from("input/tiles.tga")));
+    let floor: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&tiles));
+    l_world.add_obj(Arc::new(Plane::new(Point3::zero(), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -2.0), &floor)));
+
+    let checker: Arc<dyn Texture + Sync> = Arc::new(CheckerTexture::new(Color::new(0.8, 0.75, 0.65), Color::new(0.15, 0.25, 0.45), 8.0));
+    let checker: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&checker));
+    l_world.add_obj(Arc::new(Triangle::new(Point3::new(-4.3, 0.2, 0.0), Vec3::new(1.4, 0.0, 0.0), Vec3::new(0.7, 1.6, 0.0), &checker)));
+
+    // Polar texture coordinates turn the checker into a dartboard.
+    l_world.add_obj(Arc::new(Disk::new(Point3::new(-1.2, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0), 0.8, &checker)));
+
+    let earth: Arc<dyn Texture + Sync> = Arc::new(ImageTexture::new(&PathBuf::from("input/earthmap.tga")));
+    let earth: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&earth));
+    l_world.add_obj(Arc::new(Ellipse::new(Point3::new(1.2, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.6, 0.0), &earth)));
+
+    let gold: Arc<dyn Material + Sync> = Arc::new(Metal::new(Color::new(0.9, 0.65, 0.3), 0.2));
+    l_world.add_obj(Arc::new(Annulus::new(Point3::new(3.6, 1.0, 0.0), Vec3::new(-0.3, 0.0, 1.0), 0.4, 0.8, &checker)));
+    l_world.add_obj(Arc::new(Disk::new(Point3::new(3.6, 0.01, 1.2), Vec3::new(0.0, 1.0, 0.0), 0.6, &gold)));
+
+    let mut world: HittableList = HittableList{..HittableList::default()};
+    world.add_obj(Arc::new(BVHNode::new_list(&l_world)));
+    world.add_light(Arc::new(DirectionalLight::new(Vec3::new(-0.4, -1.0, -0.7), Color::new(2.0, 2.0, 2.0))));
+    world
+}
+
 pub fn cornell_box() -> HittableList {
     let mut l_world: HittableList = HittableList{..HittableList::default()};
 
     let red  : Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::new(0.65, 0.05,0.05)));
     let white: Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::new(0.73, 0.73,0.73)));
     let green: Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::new(0.12, 0.45,0.15)));
-    let light: Arc<dyn Material + Sync> = Arc::new(Emiter{ emission: Color::new(15.0, 15.0, 15.0) });
+    let light: Arc<dyn Material + Sync> = Arc::new(Emiter::new(Color::new(15.0, 15.0, 15.0)));
 
-    let checker: Arc<dyn Texture + Sync> = Arc::new(CheckerTexture::new(Color::new(0.2, 0.2, 0.2), Color::new(0.8, 0.8, 0.8), 10.0));
-    let bottom: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&checker));
+    // Square tiles repeated across the floor quad.
+    let mut tiles: ImageTexture = ImageTexture::new(&PathBuf::from("input/tiles.tga"));
+    tiles.transform = UvTransform::tile(6.0, 6.0);
+    let tiles: Arc<dyn Texture + Sync> = Arc::new(tiles);
+    let bottom: Arc<dyn Material + Sync> = Arc::new(Lambertian::new_texture(&tiles));
 
     l_world.add_obj(Arc::new(Quad::new(
         Point3::new(555.0, 0.0, 0.0),
@@ -117,7 +728,7 @@ pub fn random_world() -> HittableList {
     l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(0.0, -1000.0, -1.0), 1000.0, &ground_mat)));
 
     let left_mat: Arc<dyn Material + Sync> = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    let center_mat: Arc<dyn Material + Sync> = Arc::new(Dielectric{ior:1.5});
+    let center_mat: Arc<dyn Material + Sync> = Arc::new(Dielectric::new_preset(DielectricPreset::Glass));
     let right_mat: Arc<dyn Material + Sync> = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
 
     l_world.add_obj(Arc::new(Sphere::new_static(Point3::new(-4.0, 1.0, 0.0), 1.0, &left_mat)));
@@ -161,7 +772,7 @@ pub fn random_world() -> HittableList {
                 }
                 else {
                     // Glass
-                    let mat: Arc<dyn Material + Sync> = Arc::new(Dielectric{ior:1.5});
+                    let mat: Arc<dyn Material + Sync> = Arc::new(Dielectric::new_preset(DielectricPreset::Glass));
                     l_world.add_obj(Arc::new(Sphere::new_static(center, 0.2, &mat)));
                 }
             }